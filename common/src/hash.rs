@@ -81,10 +81,19 @@ impl Transaction {
 }
 
 impl Agenda {
+    /// Calculates the hash of the agenda of the given transactions.
+    ///
+    /// The transactions are aggregated in the order of their hashes, not in the given order,
+    /// so that the same set of transactions always results in the same agenda hash.
     pub fn calculate_hash(height: BlockHeight, transactions: &[Transaction]) -> Hash256 {
+        let mut transaction_hashes = transactions
+            .iter()
+            .map(|tx| tx.to_hash256())
+            .collect::<Vec<_>>();
+        transaction_hashes.sort();
         let mut hash = Hash256::hash(format!("{}", height));
-        for tx in transactions {
-            hash = hash.aggregate(&tx.to_hash256());
+        for tx_hash in transaction_hashes {
+            hash = hash.aggregate(&tx_hash);
         }
         hash
     }
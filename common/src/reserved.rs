@@ -18,6 +18,45 @@ pub struct ReservedState {
 }
 
 impl ReservedState {
+    /// Checks the structure that the verifiers take for granted:
+    /// there is a member, the public keys and the names of the members are unique,
+    /// and the consensus leader order is a non-empty list of distinct members.
+    pub fn check_sanity(&self) -> Result<(), String> {
+        if self.members.is_empty() {
+            return Err("there is no member".to_owned());
+        }
+        let mut public_keys = std::collections::BTreeSet::new();
+        let mut names = std::collections::BTreeSet::new();
+        for member in &self.members {
+            if !public_keys.insert(&member.public_key) {
+                return Err(format!(
+                    "duplicate member public key: {}",
+                    member.public_key
+                ));
+            }
+            if !names.insert(&member.name) {
+                return Err(format!("duplicate member name: {}", member.name));
+            }
+        }
+        if self.consensus_leader_order.is_empty() {
+            return Err("the consensus leader order is empty".to_owned());
+        }
+        let mut leaders = std::collections::BTreeSet::new();
+        for leader in &self.consensus_leader_order {
+            if *leader >= self.members.len() {
+                return Err(format!(
+                    "the consensus leader order refers to member {}, but there are only {}",
+                    leader,
+                    self.members.len()
+                ));
+            }
+            if !leaders.insert(leader) {
+                return Err(format!("duplicate consensus leader: {}", leader));
+            }
+        }
+        Ok(())
+    }
+
     pub fn create_validator_set(&self) -> Result<Vec<(PublicKey, VotingPower)>, String> {
         let mut validator_set = Vec::new();
         for leader in &self.consensus_leader_order {
@@ -31,11 +70,73 @@ impl ReservedState {
         Ok(validator_set)
     }
 
-    pub fn apply_delegate(&mut self, _tx: &TxDelegate) -> Result<Self, String> {
-        unimplemented!()
+    /// Makes the delegator delegate its consensus voting power (and the governance voting
+    /// power if `governance` is set) to the delegatee, and returns the new state.
+    ///
+    /// Delegations can't be chained, so neither of them may have delegated, and nobody may
+    /// have delegated to the delegator. The proof of the transaction is not verified here.
+    /// On an error, the state is left unchanged.
+    pub fn apply_delegate(&mut self, tx: &TxDelegate) -> Result<Self, String> {
+        if tx.delegator == tx.delegatee {
+            return Err(format!("{} can't delegate to itself", tx.delegator));
+        }
+        let delegatee = self.find_member(&tx.delegatee)?;
+        if has_delegated(delegatee) {
+            return Err(format!(
+                "{} can't be a delegatee: it has delegated to another member",
+                tx.delegatee
+            ));
+        }
+        if has_delegated(self.find_member(&tx.delegator)?) {
+            return Err(format!("{} has already delegated", tx.delegator));
+        }
+        if self.members.iter().any(|member| {
+            member.consensus_delegations.as_ref() == Some(&tx.delegator)
+                || member.governance_delegations.as_ref() == Some(&tx.delegator)
+        }) {
+            return Err(format!(
+                "{} can't delegate: another member has delegated to it",
+                tx.delegator
+            ));
+        }
+        let delegator = self
+            .members
+            .iter_mut()
+            .find(|member| member.public_key == tx.delegator)
+            .expect("checked above");
+        delegator.consensus_delegations = Some(tx.delegatee.clone());
+        if tx.governance {
+            delegator.governance_delegations = Some(tx.delegatee.clone());
+        }
+        Ok(self.clone())
+    }
+
+    /// Revokes all the delegations of the delegator, and returns the new state.
+    ///
+    /// The proof of the transaction is not verified here.
+    /// On an error, the state is left unchanged.
+    pub fn apply_undelegate(&mut self, tx: &TxUndelegate) -> Result<Self, String> {
+        if !has_delegated(self.find_member(&tx.delegator)?) {
+            return Err(format!("{} has no delegation to undo", tx.delegator));
+        }
+        let delegator = self
+            .members
+            .iter_mut()
+            .find(|member| member.public_key == tx.delegator)
+            .expect("checked above");
+        delegator.consensus_delegations = None;
+        delegator.governance_delegations = None;
+        Ok(self.clone())
     }
 
-    pub fn apply_undelegate(&mut self, _tx: &TxUndelegate) -> Result<Self, String> {
-        unimplemented!()
+    fn find_member(&self, public_key: &PublicKey) -> Result<&Member, String> {
+        self.members
+            .iter()
+            .find(|member| member.public_key == *public_key)
+            .ok_or_else(|| format!("{} is not a member", public_key))
     }
 }
+
+fn has_delegated(member: &Member) -> bool {
+    member.consensus_delegations.is_some() || member.governance_delegations.is_some()
+}
@@ -68,7 +68,13 @@ pub fn verify_finalization_proof(
         signature
             .verify(header)
             .map_err(|e| Error::CryptoError("invalid finalization proof".to_string(), e))?;
-        voted_validators.insert(signature.signer());
+        // Each validator must sign at most once, not to be counted repeatedly.
+        if !voted_validators.insert(signature.signer()) {
+            return Err(Error::InvalidProof(format!(
+                "invalid finalization proof - duplicate signer: {}",
+                signature.signer()
+            )));
+        }
     }
     let voted_voting_power: VotingPower = header
         .validator_set
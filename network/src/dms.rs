@@ -506,6 +506,9 @@ mod tests {
                 members: keys.iter().map(|(x, _)| x).cloned().collect(),
                 public_key: keys[i + 1].0.clone(),
                 private_key: keys[i + 1].1.clone(),
+                max_branches_per_peer: 16,
+                fetch_interval_ms: 10_000,
+                fetch_jitter_ms: 2_000,
             });
         }
         (
@@ -515,6 +518,9 @@ mod tests {
                 members: keys.iter().map(|(x, _)| x).cloned().collect(),
                 public_key: keys[0].0.clone(),
                 private_key: keys[0].1.clone(),
+                max_branches_per_peer: 16,
+                fetch_interval_ms: 10_000,
+                fetch_jitter_ms: 2_000,
             },
             configs,
         )
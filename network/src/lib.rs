@@ -38,6 +38,13 @@ pub struct NetworkConfig {
     pub public_key: PublicKey,
     /// The private key of this node.
     pub private_key: PrivateKey,
+    /// The maximum number of branches that will be imported from a single peer in a fetch.
+    pub max_branches_per_peer: usize,
+    /// The base interval between the periodic fetches from the peers, in milliseconds.
+    pub fetch_interval_ms: u64,
+    /// The maximum random delay added to each fetch interval, in milliseconds,
+    /// so that the nodes don't fetch in lockstep.
+    pub fetch_jitter_ms: u64,
 }
 
 /// The currently known peers that are for other modules,
@@ -48,6 +55,12 @@ pub struct SharedKnownPeers {
 }
 
 impl SharedKnownPeers {
+    pub fn new(peers: Vec<Peer>) -> Self {
+        Self {
+            lock: Arc::new(RwLock::new(peers)),
+        }
+    }
+
     pub async fn read(&self) -> Vec<Peer> {
         self.lock.read().await.clone()
     }
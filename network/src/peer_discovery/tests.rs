@@ -119,6 +119,9 @@ impl TestNet {
                 .collect(),
             public_key: dummy_pubkey,
             private_key: dummy_privkey,
+            max_branches_per_peer: 16,
+            fetch_interval_ms: 10_000,
+            fetch_jitter_ms: 2_000,
         };
         Self {
             keystore,
@@ -53,8 +53,9 @@ impl<N: GossipNetwork, S: Storage, R: RawRepository> SimperbyApi for Node<N, S,
         let repo =
             DistributedRepository::new(R::open(&self.config.repository_directory).await?).await?;
         let valid_agendas = repo.get_agendas().await?;
-        let agenda_hash = if let Some(x) = valid_agendas.iter().find(|(x, _)| *x == agenda_commit) {
-            x.1
+        let agenda_hash = if let Some(x) = valid_agendas.iter().find(|x| x.commit == agenda_commit)
+        {
+            x.hash
         } else {
             return Err(anyhow!(
                 "the given commit hash {} is not one of the valid agendas",
@@ -0,0 +1,146 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures::future::join_all;
+use simperby_common::reserved::ReservedState;
+use simperby_common::*;
+use simperby_repository::format::to_semantic_commit;
+use simperby_repository::raw::{reserved_state, RawRepository, RawRepositoryImpl};
+use simperby_repository::{DistributedRepository, FINALIZED_BRANCH_NAME};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const BRANCHES: [usize; 2] = [16, 128];
+const CONCURRENT_CALLS: usize = 4;
+
+fn generate_reserved_state(keys: &[(PublicKey, PrivateKey)]) -> ReservedState {
+    let header = BlockHeader {
+        author: keys[0].0.clone(),
+        prev_block_finalization_proof: vec![],
+        previous_hash: Hash256::zero(),
+        height: 0,
+        timestamp: 0,
+        commit_merkle_root: BlockHeader::calculate_commit_merkle_root(&[]),
+        repository_merkle_root: Hash256::zero(),
+        validator_set: keys
+            .iter()
+            .map(|(public_key, _)| (public_key.clone(), 1))
+            .collect(),
+        version: "0.0.0".to_owned(),
+    };
+    ReservedState {
+        genesis_info: GenesisInfo {
+            genesis_proof: keys
+                .iter()
+                .map(|(_, private_key)| TypedSignature::sign(&header, private_key).unwrap())
+                .collect(),
+            header,
+            chain_name: "bench".to_owned(),
+        },
+        members: keys
+            .iter()
+            .enumerate()
+            .map(|(i, (public_key, _))| Member {
+                public_key: public_key.clone(),
+                name: format!("member{}", i),
+                governance_voting_power: 1,
+                consensus_voting_power: 1,
+                governance_delegations: None,
+                consensus_delegations: None,
+            })
+            .collect(),
+        consensus_leader_order: (0..keys.len()).collect(),
+        version: "0.0.0".to_owned(),
+    }
+}
+
+/// Creates a repository on the genesis block with an agenda branch for each of `branches`.
+async fn setup(path: &str, branches: usize) -> DistributedRepository<RawRepositoryImpl> {
+    let keys: Vec<_> = (0..4).map(|i| generate_keypair([i])).collect();
+    let reserved_state = generate_reserved_state(&keys);
+    let genesis_header = reserved_state.genesis_info.header.clone();
+    let mut raw = RawRepositoryImpl::init(path, "initial", &FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    let initial_commit = raw.get_head().await.unwrap();
+    let files = reserved_state::to_files(&reserved_state)
+        .into_iter()
+        .map(|(path, content)| (path, content.into_bytes()))
+        .collect();
+    let reserved_state_commit = raw
+        .create_commit_with_parents("reserved state".to_owned(), files, vec![initial_commit])
+        .await
+        .unwrap();
+    let semantic_commit =
+        to_semantic_commit(&Commit::Block(genesis_header.clone()), &genesis_header);
+    let genesis_commit = raw
+        .create_commit_with_parents(
+            format!("{}\n\n{}", semantic_commit.title, semantic_commit.body),
+            vec![],
+            vec![reserved_state_commit],
+        )
+        .await
+        .unwrap();
+    raw.move_branch(
+        FINALIZED_BRANCH_NAME.into(),
+        genesis_commit,
+        true,
+        "genesis".to_owned(),
+    )
+    .await
+    .unwrap();
+
+    for i in 0..branches {
+        let agenda = Commit::Agenda(Agenda {
+            author: keys[i % keys.len()].0.clone(),
+            timestamp: i as Timestamp,
+            hash: Agenda::calculate_hash(0, &[]),
+        });
+        let semantic_commit = to_semantic_commit(&agenda, &genesis_header);
+        let agenda_commit = raw
+            .create_commit_with_parents(
+                format!("{}\n\n{}", semantic_commit.title, semantic_commit.body),
+                vec![],
+                vec![genesis_commit],
+            )
+            .await
+            .unwrap();
+        raw.create_branch(format!("a-{}", i), agenda_commit)
+            .await
+            .unwrap();
+    }
+    DistributedRepository::new(raw).await.unwrap()
+}
+
+/// Measures `get_agendas()` alone, and several of them at once as the concurrent
+/// readers of a shared repository (e.g., the server and the CLI) would call it.
+fn get_agendas(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("get_agendas");
+    group.sample_size(10);
+    for branches in BRANCHES {
+        let td = TempDir::new().unwrap();
+        let drepo = Arc::new(runtime.block_on(setup(td.path().to_str().unwrap(), branches)));
+        assert_eq!(
+            runtime.block_on(drepo.get_agendas()).unwrap().len(),
+            branches
+        );
+        group.bench_with_input(BenchmarkId::new("single", branches), &drepo, |b, drepo| {
+            b.iter(|| runtime.block_on(drepo.get_agendas()).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("concurrent", branches),
+            &drepo,
+            |b, drepo| {
+                b.iter(|| {
+                    runtime.block_on(join_all((0..CONCURRENT_CALLS).map(|_| {
+                        let drepo = Arc::clone(drepo);
+                        runtime.spawn(async move { drepo.get_agendas().await.unwrap() })
+                    })))
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, get_agendas);
+criterion_main!(benches);
@@ -1,10 +1,61 @@
 use crate::raw::SemanticCommit;
+use serde::de::DeserializeOwned;
 use simperby_common::*;
 
+/// Creates the title of a non-transaction commit, which is `<type>: <height>/<hash>`.
+fn create_title(commit_type: &str, height: BlockHeight, hash: Hash256) -> String {
+    format!("{}: {}/{}", commit_type, height, hash)
+}
+
+/// Parses the body of a non-transaction commit and checks that it matches the hash in the title.
+fn parse_body<T: DeserializeOwned + ToHash256>(title: &str, body: &str) -> Result<T, String> {
+    let (_, rest) = title
+        .split_once(": ")
+        .ok_or_else(|| format!("invalid title: {}", title))?;
+    let (height, hash) = rest
+        .split_once('/')
+        .ok_or_else(|| format!("invalid title: {}", title))?;
+    height
+        .parse::<BlockHeight>()
+        .map_err(|_| format!("invalid height in title: {}", title))?;
+    let value: T = serde_json::from_str(body).map_err(|e| format!("invalid body: {}", e))?;
+    if value.to_hash256().to_string() != hash {
+        return Err(format!(
+            "hash mismatch: title has {}, body has {}",
+            hash,
+            value.to_hash256()
+        ));
+    }
+    Ok(value)
+}
+
+fn get_extra_agenda_transaction_type(transaction: &ExtraAgendaTransaction) -> &'static str {
+    match transaction {
+        ExtraAgendaTransaction::Delegate(_) => "tx-delegate",
+        ExtraAgendaTransaction::Undelegate(_) => "tx-undelegate",
+        ExtraAgendaTransaction::Report(_) => "tx-report",
+    }
+}
+
 pub fn to_semantic_commit(commit: &Commit, last_header: &BlockHeader) -> SemanticCommit {
+    let height = last_header.height + 1;
     match commit {
+        Commit::Block(header) => {
+            let title = create_title("block", header.height, header.to_hash256());
+            let body = serde_json::to_string(header).unwrap();
+            SemanticCommit {
+                title,
+                body,
+                diff: Diff::None,
+            }
+        }
+        Commit::Transaction(transaction) => SemanticCommit {
+            title: transaction.head.clone(),
+            body: serde_json::to_string(transaction).unwrap(),
+            diff: transaction.diff.clone(),
+        },
         Commit::Agenda(agenda) => {
-            let title = format!("agenda: {}/{}", last_header.height + 1, agenda.to_hash256());
+            let title = create_title("agenda", height, agenda.to_hash256());
             let body = serde_json::to_string(agenda).unwrap();
             SemanticCommit {
                 title,
@@ -12,13 +63,69 @@ pub fn to_semantic_commit(commit: &Commit, last_header: &BlockHeader) -> Semanti
                 diff: Diff::None,
             }
         }
-        _ => todo!(),
+        Commit::AgendaProof(agenda_proof) => {
+            let title = create_title("agenda-proof", height, agenda_proof.to_hash256());
+            let body = serde_json::to_string(agenda_proof).unwrap();
+            SemanticCommit {
+                title,
+                body,
+                diff: Diff::None,
+            }
+        }
+        Commit::ExtraAgendaTransaction(transaction) => {
+            let title = create_title(
+                get_extra_agenda_transaction_type(transaction),
+                height,
+                transaction.to_hash256(),
+            );
+            let body = serde_json::to_string(transaction).unwrap();
+            SemanticCommit {
+                title,
+                body,
+                diff: Diff::None,
+            }
+        }
+        Commit::ChatLog(chat_log) => {
+            let title = create_title("chat", height, chat_log.to_hash256());
+            let body = serde_json::to_string(chat_log).unwrap();
+            SemanticCommit {
+                title,
+                body,
+                diff: Diff::None,
+            }
+        }
     }
 }
 
-pub fn from_semantic_commit(
-    _semantic_commit: SemanticCommit,
-    _last_header: &BlockHeader,
-) -> Result<Commit, String> {
-    todo!()
+/// Converts a semantic commit into a commit.
+///
+/// Note that it only checks the format, not the validity of the commit.
+pub fn from_semantic_commit(semantic_commit: SemanticCommit) -> Result<Commit, String> {
+    let SemanticCommit { title, body, .. } = semantic_commit;
+    let commit_type = title.split_once(": ").map(|(commit_type, _)| commit_type);
+    match commit_type {
+        Some("block") => parse_body(&title, &body).map(Commit::Block),
+        Some("agenda") => parse_body(&title, &body).map(Commit::Agenda),
+        Some("agenda-proof") => parse_body(&title, &body).map(Commit::AgendaProof),
+        Some(commit_type @ ("tx-delegate" | "tx-undelegate" | "tx-report")) => {
+            let transaction: ExtraAgendaTransaction = parse_body(&title, &body)?;
+            if get_extra_agenda_transaction_type(&transaction) != commit_type {
+                return Err(format!("type mismatch: title has {}", commit_type));
+            }
+            Ok(Commit::ExtraAgendaTransaction(transaction))
+        }
+        Some("chat") => parse_body(&title, &body).map(Commit::ChatLog),
+        // A transaction is the only commit whose title does not start with its type.
+        _ => {
+            let transaction: Transaction =
+                serde_json::from_str(&body).map_err(|e| format!("invalid body: {}", e))?;
+            if transaction.head != title {
+                return Err(format!(
+                    "title mismatch: commit has {}, transaction has {}",
+                    title, transaction.head
+                ));
+            }
+            Ok(Commit::Transaction(transaction))
+        }
+    }
 }
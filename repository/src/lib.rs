@@ -1,8 +1,11 @@
 pub mod format;
 pub mod raw;
+#[cfg(test)]
+mod tests;
 
 use anyhow::anyhow;
 use format::*;
+use fs2::FileExt;
 use futures::prelude::*;
 use raw::RawRepository;
 use serde::{Deserialize, Serialize};
@@ -10,35 +13,412 @@ use simperby_common::reserved::ReservedState;
 use simperby_common::verify::CommitSequenceVerifier;
 use simperby_common::*;
 use simperby_network::{NetworkConfig, Peer, SharedKnownPeers};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
+use std::num::NonZeroU64;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
 
 pub type Branch = String;
 pub type Tag = String;
 
 pub const FINALIZED_BRANCH_NAME: &str = "finalized";
 pub const WORK_BRANCH_NAME: &str = "work";
+pub const FP_BRANCH_NAME: &str = "fp";
+/// The prefix of the `vote-<number>` tags, which must be on agenda commits.
+pub const VOTE_TAG_PREFIX: &str = "vote-";
+/// The prefix of the `veto-<number>` tags, which must be on agenda or block commits.
+///
+/// On an agenda commit, the number is the index of the vetoing member in the reserved state.
+pub const VETO_TAG_PREFIX: &str = "veto-";
+/// The prefix of the `block-<height>` tags, which are put on the finalized block commits.
+pub const BLOCK_TAG_PREFIX: &str = "block-";
+/// The prefix of the `fp-<height>` tags, which some clients use instead of the `fp` branch.
+///
+/// Such a tag is annotated and on a block commit, with the finalization proof as its message.
+pub const FP_TAG_PREFIX: &str = "fp-";
+
+/// The key of the repository service port in `Peer::ports`.
+pub const REPOSITORY_PORT_KEY: &str = "repository";
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize, Deserialize, Hash)]
 pub struct CommitHash {
     pub hash: [u8; 20],
 }
 
+/// The number of hex digits of a commit hash when displayed, as Git abbreviates.
+const SHORT_HASH_LENGTH: usize = 7;
+
+/// Displays the abbreviated hash, or the full hash in the alternate form (`{:#}`).
 impl fmt::Display for CommitHash {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "?")
+        let hash = self
+            .hash
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        if f.alternate() {
+            write!(f, "{}", hash)
+        } else {
+            write!(f, "{}", &hash[..SHORT_HASH_LENGTH])
+        }
     }
 }
 
 pub type Error = anyhow::Error;
 
+/// The name of the lock file in the `.git` directory, which a `DistributedRepository` locks.
+///
+/// It is not in the working tree so that `checkout_clean()` doesn't remove it.
+/// The file itself stays after unlocking; only the OS lock on it matters.
+pub const LOCK_FILE_NAME: &str = "simperby.lock";
+
+/// An OS advisory lock on the lock file of a repository, held until dropped.
+///
+/// The OS releases it when the process dies, so a crashed process doesn't leave
+/// the repository locked.
+#[derive(Debug)]
+struct RepositoryLock {
+    _file: std::fs::File,
+}
+
+impl RepositoryLock {
+    fn acquire(git_directory: &str) -> Result<Self, Error> {
+        let path = Path::new(git_directory).join(LOCK_FILE_NAME);
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| anyhow!("failed to open {}: {}", path.display(), e))?;
+        file.try_lock_exclusive()
+            .map_err(|e| anyhow!("failed to lock the repository at {}: {}", git_directory, e))?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// The default number of parsed commits kept in memory by `get_commit()`.
+pub const DEFAULT_COMMIT_CACHE_CAPACITY: usize = 1024;
+
+/// The default number of blocks that `sync()` verifies before checkpointing `finalized`.
+pub const DEFAULT_SYNC_CHECKPOINT_INTERVAL: BlockHeight = 100;
+
+/// The number of fetches of `serve()` between its garbage collections.
+pub const SERVE_GC_PERIOD: u64 = 100;
+
+/// The number of events that a lagging subscriber of `subscribe()` can miss
+/// before it receives `RecvError::Lagged`.
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// A cache of parsed commits that evicts the least recently used one when full.
+///
+/// Commits never change once created, so an entry never gets stale.
+#[derive(Debug)]
+struct CommitCache {
+    capacity: usize,
+    /// Incremented on every access to track the recency.
+    clock: u64,
+    entries: HashMap<CommitHash, (u64, Commit)>,
+    /// From the last access time to the commit.
+    recency: BTreeMap<u64, CommitHash>,
+}
+
+impl CommitCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            clock: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, commit_hash: &CommitHash) -> Option<Commit> {
+        self.clock += 1;
+        let (last_access, commit) = self.entries.get_mut(commit_hash)?;
+        self.recency.remove(last_access);
+        self.recency.insert(self.clock, *commit_hash);
+        *last_access = self.clock;
+        Some(commit.clone())
+    }
+
+    fn insert(&mut self, commit_hash: CommitHash, commit: Commit) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.clock += 1;
+        if let Some((last_access, _)) = self.entries.remove(&commit_hash) {
+            self.recency.remove(&last_access);
+        }
+        while self.entries.len() >= self.capacity {
+            self.evict_oldest();
+        }
+        self.entries.insert(commit_hash, (self.clock, commit));
+        self.recency.insert(self.clock, commit_hash);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > self.capacity {
+            self.evict_oldest();
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        let (last_access, oldest) = self
+            .recency
+            .iter()
+            .next()
+            .map(|(last_access, oldest)| (*last_access, *oldest))
+            .expect("recency must have the same entries");
+        self.recency.remove(&last_access);
+        self.entries.remove(&oldest);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
 /// The local Simperby blockchain data repository.
 ///
-/// It automatically locks the repository once created.
+/// It automatically locks the repository once created, and unlocks it when dropped.
+/// The OS releases the lock if the process crashes, so a lock file left behind
+/// doesn't block the next one.
+///
+/// Every method that modifies the repository takes `&mut self`, so within a process
+/// a multi-step operation such as `finalize()` never interleaves with another one such as `fetch()`.
+/// Across processes, the lock on `LOCK_FILE_NAME` serializes them instead, since only one
+/// `DistributedRepository` can hold it at a time. Together they make each operation exclusive
+/// without locking the raw repository across the steps.
 ///
 /// - It **verifies** all the incoming changes and applies them to the local repository
 /// only if they are valid.
 pub struct DistributedRepository<T> {
     raw: T,
+    /// The reserved state of the `finalized` branch, with the commit that it was read from.
+    reserved_state_cache: Mutex<Option<(CommitHash, ReservedState)>>,
+    /// The header of the last finalized block, with the commit of `finalized`.
+    last_header_cache: Mutex<Option<(CommitHash, BlockHeader)>>,
+    /// The commits that have been verified on top of the `finalized` branch,
+    /// with the commit of `finalized` that they were verified from.
+    verified_commits_cache: Mutex<Option<(CommitHash, BTreeSet<CommitHash>)>>,
+    /// The commits parsed by `get_commit()`.
+    commit_cache: Mutex<CommitCache>,
+    /// The number of blocks that `sync()` verifies before checkpointing `finalized`.
+    sync_checkpoint_interval: BlockHeight,
+    /// The depth of the shallow fetches by `fetch()`; `None` to fetch the whole history.
+    fetch_depth: Option<usize>,
+    /// Called with the header of every block that has been successfully finalized.
+    on_finalize: Option<FinalizeCallback>,
+    events: tokio::sync::broadcast::Sender<RepositoryEvent>,
+    _lock: RepositoryLock,
+}
+
+type FinalizeCallback = Box<dyn Fn(&BlockHeader) + Send + Sync>;
+
+/// The phase of the `work` branch, determined by its last commit after the last finalized block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// There is no commit after the last finalized block.
+    Block,
+    Transaction,
+    Agenda,
+    AgendaProof,
+    ExtraAgendaTransaction,
+}
+
+impl Phase {
+    fn from_last_commit(commit: Option<&Commit>) -> Self {
+        match commit {
+            None | Some(Commit::Block(_)) => Phase::Block,
+            Some(Commit::Transaction(_)) => Phase::Transaction,
+            Some(Commit::Agenda(_)) => Phase::Agenda,
+            Some(Commit::AgendaProof(_)) => Phase::AgendaProof,
+            Some(Commit::ExtraAgendaTransaction(_) | Commit::ChatLog(_)) => {
+                Phase::ExtraAgendaTransaction
+            }
+        }
+    }
+}
+
+/// The `work` branch is not in the phase that the operation requires.
+///
+/// It can be retrieved from the returned error with `downcast_ref()`.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("branch work is in the {actual:?} phase, but the {expected:?} phase is expected")]
+pub struct PhaseError {
+    pub expected: Phase,
+    pub actual: Phase,
+}
+
+/// The reserved state of a commit is malformed (e.g., it has no member).
+///
+/// It can be retrieved from the returned error with `downcast_ref()`.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid reserved state at commit {commit_hash}: {reason}")]
+pub struct InvalidReservedStateError {
+    pub commit_hash: CommitHash,
+    pub reason: String,
+}
+
+/// The summary of a `fetch()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchOutcome {
+    /// The new height of `finalized`, if it has advanced.
+    pub finalized_height: Option<BlockHeight>,
+    /// The newly imported `a-#` and `b-#` branches.
+    pub new_branches: Vec<Branch>,
+    /// `(remote_name, error)` of the branches that failed to be imported or finalized.
+    pub errors: Vec<(String, String)>,
+}
+
+/// The result of `verify_branch()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchVerification {
+    /// Why the branch is invalid, or `None` if all of its commits are valid.
+    pub error: Option<String>,
+    /// The phase after the last valid commit.
+    pub phase: Phase,
+    /// The first valid block after `finalized`, which can be finalized with a proof.
+    pub finalizable_block: Option<(CommitHash, BlockHeader)>,
+}
+
+impl BranchVerification {
+    pub fn is_valid(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// The new tip of `finalized`, returned by `finalize()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finalized {
+    pub height: BlockHeight,
+    /// The hash of the block header.
+    pub block_hash: Hash256,
+    pub commit_hash: CommitHash,
+}
+
+/// An agenda returned by `get_agendas()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgendaInfo {
+    pub commit: CommitHash,
+    /// The hash of the agenda itself (i.e., what the governance signs), not `Agenda::hash`.
+    pub hash: Hash256,
+    pub author: PublicKey,
+    pub timestamp: Timestamp,
+    /// The height of the block that the agenda is for, i.e., the one after the last finalized.
+    pub height: BlockHeight,
+}
+
+/// An event of `subscribe()`, sent after the change has been fully applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepositoryEvent {
+    /// `create_agenda()` has created an agenda commit on `work`.
+    AgendaCreated {
+        commit_hash: CommitHash,
+        agenda_hash: Hash256,
+    },
+    /// `create_block()` has created a block commit on `work`.
+    BlockCreated {
+        commit_hash: CommitHash,
+        header: BlockHeader,
+    },
+    /// `finalized` has advanced, by `finalize()`, `sync()` (including its checkpoints)
+    /// or `fetch()`.
+    Finalized(Finalized),
+    /// `rewind_finalized()` has moved `finalized` back to an earlier block.
+    Rewound(Finalized),
+    /// `fetch()` has found a new fork branch, as `check_health()` reports.
+    ForkDetected { branch: Branch, height: BlockHeight },
+    /// `fetch()` has received a new `vote-#` or `veto-#` tag.
+    VoteReceived { tag: Tag, commit_hash: CommitHash },
+}
+
+/// The result of `check_health()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepositoryHealth {
+    /// Whether `check()` has passed.
+    pub valid: bool,
+    /// The `b-#` branches that diverge from `finalized`, with the heights of their blocks.
+    pub forks: Vec<(Branch, BlockHeight)>,
+    /// The reserved branches that contain a merge commit, with the nearest one of each.
+    pub merges: Vec<(Branch, CommitHash)>,
+}
+
+/// Schedules the periodic fetches of `serve()`.
+///
+/// Each wait lasts `fetch_interval_ms` plus a random jitter of up to `fetch_jitter_ms`,
+/// so that the nodes don't fetch from a well-connected peer in lockstep.
+/// A wait ends early if the timer is woken up, for an event-driven sync.
+#[derive(Debug, Clone)]
+pub struct FetchTimer {
+    interval: Duration,
+    jitter: Duration,
+    wakeup: Arc<Notify>,
+}
+
+impl FetchTimer {
+    pub fn new(network_config: &NetworkConfig) -> Self {
+        Self {
+            interval: Duration::from_millis(network_config.fetch_interval_ms),
+            jitter: Duration::from_millis(network_config.fetch_jitter_ms),
+            wakeup: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Returns the delay until the next fetch, which is in `[interval, interval + jitter]`.
+    pub fn next_delay(&self) -> Duration {
+        let jitter_ms = rand::Rng::gen_range(&mut rand::thread_rng(), 0..=self.jitter.as_millis());
+        self.interval + Duration::from_millis(jitter_ms as u64)
+    }
+
+    /// Waits for the next fetch, or until `wake_up()` is called.
+    ///
+    /// A wakeup that comes while nobody is waiting ends the next wait immediately.
+    pub async fn wait(&self) {
+        tokio::select! {
+            _ = tokio::time::sleep(self.next_delay()) => {}
+            _ = self.wakeup.notified() => {}
+        }
+    }
+
+    /// Makes the current (or the next) `wait()` return immediately.
+    pub fn wake_up(&self) {
+        self.wakeup.notify_one();
+    }
+}
+
+/// Returns the name of the remote that is used to fetch from the given peer.
+///
+/// It contains the whole public key of the peer, so the same peer always maps to the same remote
+/// and two distinct peers never collide.
+fn get_remote_name(peer: &Peer) -> String {
+    let public_key = peer
+        .public_key
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("peer-{}", public_key)
+}
+
+/// The maximum number of characters of a commit title or body quoted in an error message.
+const COMMIT_SNIPPET_LENGTH: usize = 100;
+
+/// Truncates the given text to `COMMIT_SNIPPET_LENGTH` characters for an error message.
+fn snippet(text: &str) -> String {
+    if text.chars().count() <= COMMIT_SNIPPET_LENGTH {
+        text.to_owned()
+    } else {
+        format!(
+            "{}...",
+            text.chars().take(COMMIT_SNIPPET_LENGTH).collect::<String>()
+        )
+    }
 }
 
 fn get_timestamp() -> Timestamp {
@@ -47,9 +427,96 @@ fn get_timestamp() -> Timestamp {
     since_the_epoch.as_millis() as Timestamp
 }
 
+impl DistributedRepository<raw::RawRepositoryImpl> {
+    /// Opens the repository in the given directory.
+    pub async fn open(directory: &str) -> Result<Self, Error> {
+        Self::new(raw::RawRepositoryImpl::open(directory).await?).await
+    }
+
+    /// Initializes a repository in the given directory,
+    /// with an initial commit on the `finalized` branch.
+    pub async fn init(directory: &str) -> Result<Self, Error> {
+        let raw = raw::RawRepositoryImpl::init(directory, "initial", &FINALIZED_BRANCH_NAME.into())
+            .await?;
+        Self::new(raw).await
+    }
+}
+
 impl<T: RawRepository> DistributedRepository<T> {
+    /// Fails if the repository is already locked by another one.
     pub async fn new(raw: T) -> Result<Self, Error> {
-        Ok(Self { raw })
+        let lock = RepositoryLock::acquire(&raw.get_git_directory().await?)?;
+        Ok(Self {
+            raw,
+            reserved_state_cache: Mutex::new(None),
+            last_header_cache: Mutex::new(None),
+            verified_commits_cache: Mutex::new(None),
+            commit_cache: Mutex::new(CommitCache::new(DEFAULT_COMMIT_CACHE_CAPACITY)),
+            sync_checkpoint_interval: DEFAULT_SYNC_CHECKPOINT_INTERVAL,
+            fetch_depth: None,
+            on_finalize: None,
+            events: tokio::sync::broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            _lock: lock,
+        })
+    }
+
+    /// Sets the callback that is called with the block header
+    /// whenever `finalize()` succeeds or `sync()` checkpoints `finalized`
+    /// (i.e., both `finalized` and `fp` are updated).
+    ///
+    /// It replaces the previous one, if any.
+    pub fn set_on_finalize(&mut self, on_finalize: impl Fn(&BlockHeader) + Send + Sync + 'static) {
+        self.on_finalize = Some(Box::new(on_finalize));
+    }
+
+    /// Subscribes to the changes made by this repository, including the ones from `fetch()`.
+    ///
+    /// The events sent before the subscription are not received.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<RepositoryEvent> {
+        self.events.subscribe()
+    }
+
+    /// Sends the event to the subscribers, if any.
+    fn emit(&self, event: RepositoryEvent) {
+        // It fails only if there is no subscriber.
+        let _ = self.events.send(event);
+    }
+
+    /// Calls the `on_finalize` callback and emits `Finalized` for the new tip of `finalized`,
+    /// once both `finalized` and `fp` are updated.
+    fn notify_finalized(&self, commit_hash: &CommitHash, block_header: &BlockHeader) -> Finalized {
+        if let Some(on_finalize) = &self.on_finalize {
+            on_finalize(block_header);
+        }
+        let finalized = Finalized {
+            height: block_header.height,
+            block_hash: block_header.to_hash256(),
+            commit_hash: *commit_hash,
+        };
+        self.emit(RepositoryEvent::Finalized(finalized.clone()));
+        finalized
+    }
+
+    /// Sets the maximum number of parsed commits kept in memory by `get_commit()`.
+    ///
+    /// Zero disables the cache.
+    pub fn set_commit_cache_capacity(&mut self, capacity: usize) {
+        self.commit_cache.lock().unwrap().set_capacity(capacity);
+    }
+
+    /// Sets the number of blocks that `sync()` verifies before checkpointing `finalized`.
+    pub fn set_sync_checkpoint_interval(&mut self, interval: NonZeroU64) {
+        self.sync_checkpoint_interval = interval.get();
+    }
+
+    /// Makes `fetch()` download only the last `depth` commits of each branch,
+    /// so that a peer can't make us download an arbitrarily long history.
+    ///
+    /// The branches that don't reach `finalized` within the depth are rejected,
+    /// and their remote tracking branches are deleted so that no walk runs into the cut.
+    /// `None` (the default) fetches the whole history.
+    pub fn set_fetch_depth(&mut self, depth: Option<usize>) {
+        self.fetch_depth = depth;
     }
 
     /// Initializes the genesis repository from the genesis working tree.
@@ -57,27 +524,591 @@ impl<T: RawRepository> DistributedRepository<T> {
         unimplemented!()
     }
     /// Returns the block header from the `finalized` branch.
+    ///
+    /// It is cached until the `finalized` branch moves.
     pub async fn get_last_finalized_block_header(&self) -> Result<BlockHeader, Error> {
         let commit_hash = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
-        let semantic_commit = self.raw.read_semantic_commit(commit_hash).await?;
-        let block_header: BlockHeader = serde_json::from_str(&semantic_commit.body)?;
-        Ok(block_header)
+        if let Some((cached_commit_hash, block_header)) = &*self.last_header_cache.lock().unwrap() {
+            if *cached_commit_hash == commit_hash {
+                return Ok(block_header.clone());
+            }
+        }
+        if let Commit::Block(block_header) = self.get_commit(&commit_hash).await? {
+            *self.last_header_cache.lock().unwrap() = Some((commit_hash, block_header.clone()));
+            Ok(block_header)
+        } else {
+            Err(anyhow!(
+                "branch {} does not point to a block commit",
+                FINALIZED_BRANCH_NAME
+            ))
+        }
+    }
+
+    /// Reads the given commit and parses it.
+    ///
+    /// The recently read ones are cached, up to the capacity set by `set_commit_cache_capacity()`.
+    pub async fn get_commit(&self, commit_hash: &CommitHash) -> Result<Commit, Error> {
+        if let Some(commit) = self.commit_cache.lock().unwrap().get(commit_hash) {
+            return Ok(commit);
+        }
+        let semantic_commit = self.raw.read_semantic_commit(*commit_hash).await?;
+        let title = snippet(&semantic_commit.title);
+        let body = snippet(&semantic_commit.body);
+        let commit = from_semantic_commit(semantic_commit).map_err(|e| {
+            anyhow!(
+                "failed to parse commit {} (title: {:?}, body: {:?}): {}",
+                commit_hash,
+                title,
+                body,
+                e
+            )
+        })?;
+        self.commit_cache
+            .lock()
+            .unwrap()
+            .insert(*commit_hash, commit.clone());
+        Ok(commit)
     }
 
     /// Returns the reserved state from the `finalized` branch.
+    ///
+    /// It is cached until the `finalized` branch moves.
+    /// A malformed one fails with an `InvalidReservedStateError`.
     pub async fn get_reserved_state(&self) -> Result<ReservedState, Error> {
-        self.raw.read_reserved_state().await.map_err(|e| anyhow!(e))
+        let commit_hash = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        if let Some((cached_commit_hash, reserved_state)) =
+            &*self.reserved_state_cache.lock().unwrap()
+        {
+            if *cached_commit_hash == commit_hash {
+                return Ok(reserved_state.clone());
+            }
+        }
+        let reserved_state = self.get_reserved_state_at(&commit_hash).await?;
+        *self.reserved_state_cache.lock().unwrap() = Some((commit_hash, reserved_state.clone()));
+        Ok(reserved_state)
+    }
+
+    /// Reads the reserved state at the given commit, which may be an old one.
+    ///
+    /// A malformed one fails with an `InvalidReservedStateError`.
+    pub async fn get_reserved_state_at(
+        &self,
+        commit_hash: &CommitHash,
+    ) -> Result<ReservedState, Error> {
+        let reserved_state = self.raw.read_reserved_state_at_commit(*commit_hash).await?;
+        reserved_state
+            .check_sanity()
+            .map_err(|reason| InvalidReservedStateError {
+                commit_hash: *commit_hash,
+                reason,
+            })?;
+        Ok(reserved_state)
+    }
+
+    /// Drops the cached data, so that it will be read from the repository again.
+    pub fn invalidate_cache(&self) {
+        *self.reserved_state_cache.lock().unwrap() = None;
+        *self.last_header_cache.lock().unwrap() = None;
+        *self.verified_commits_cache.lock().unwrap() = None;
+        self.commit_cache.lock().unwrap().clear();
+    }
+
+    /// Verifies that each finalized block above `from_height` carries a valid finalization proof
+    /// of the previous block.
+    ///
+    /// It reports the height of the first block whose proof is broken,
+    /// or that doesn't directly follow the previous block.
+    pub async fn verify_finalization_chain(&self, from_height: BlockHeight) -> Result<(), Error> {
+        let last_header = self.get_last_finalized_block_header().await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        // Walks back from `finalized` to the block at `from_height`, from the newest one.
+        let mut headers = vec![last_header];
+        for commit_hash in self.raw.list_ancestors(last_header_commit, None).await? {
+            let height = headers.last().expect("not empty").height;
+            if height <= from_height {
+                break;
+            }
+            match self
+                .get_commit(&commit_hash)
+                .await
+                .map_err(|e| anyhow!("broken finalization chain below height {}: {}", height, e))?
+            {
+                Commit::Block(block_header) => headers.push(block_header),
+                _ => continue,
+            }
+        }
+        let lowest_height = headers.last().expect("not empty").height;
+        if lowest_height > from_height {
+            return Err(anyhow!(
+                "broken finalization chain at height {}: no previous block",
+                lowest_height
+            ));
+        }
+        for pair in headers.windows(2).rev() {
+            let (header, prev_header) = (&pair[0], &pair[1]);
+            if header.height != prev_header.height + 1 {
+                return Err(anyhow!(
+                    "broken finalization chain at height {}: the previous block is at height {}",
+                    header.height,
+                    prev_header.height
+                ));
+            }
+            verify::verify_finalization_proof(prev_header, &header.prev_block_finalization_proof)
+                .map_err(|e| {
+                anyhow!(
+                    "broken finalization proof at height {}: {}",
+                    header.height,
+                    e
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns the finalization proof of the finalized block at the given height.
+    ///
+    /// The proof of the last finalized block is the one in the `fp` branch (or the genesis proof),
+    /// and that of an earlier block is the one embedded in the next block.
+    pub async fn get_proof_at_height(
+        &self,
+        height: BlockHeight,
+    ) -> Result<FinalizationProof, Error> {
+        let last_header = self.get_last_finalized_block_header().await?;
+        if height > last_header.height {
+            return Err(anyhow!(
+                "block {} is not finalized; the last finalized height is {}",
+                height,
+                last_header.height
+            ));
+        }
+        if height == last_header.height {
+            return self.get_last_finalization_proof().await;
+        }
+        if height + 1 == last_header.height {
+            return Ok(last_header.prev_block_finalization_proof);
+        }
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        for commit_hash in self.raw.list_ancestors(last_header_commit, None).await? {
+            // The other commits are skipped, even if they are malformed,
+            // since only the block at the next height matters.
+            if let Ok(Commit::Block(block_header)) = self.get_commit(&commit_hash).await {
+                if block_header.height == height + 1 {
+                    return Ok(block_header.prev_block_finalization_proof);
+                }
+                if block_header.height <= height {
+                    break;
+                }
+            }
+        }
+        Err(anyhow!(
+            "block {} is not found in {}",
+            height + 1,
+            FINALIZED_BRANCH_NAME
+        ))
     }
 
     /// Fetches new commits from the network.
     /// It **verifies** all the incoming changes and applies them to the local repository
     /// only if they are valid.
+    ///
+    /// At most `network_config.max_branches_per_peer` new branches are imported from each peer;
+    /// the rest are skipped.
+    /// With `set_fetch_depth()`, the branches whose history doesn't reach ours within the depth
+    /// are rejected.
+    /// If a peer has the finalization proof of a newer block, `finalized` advances to it,
+    /// unless the next block embeds a proof of our `finalized` block other than the local one.
+    ///
+    /// It is idempotent, so it can be safely re-run (e.g., after a crash);
+    /// the existing remotes are reused and the already imported commits are skipped.
+    ///
+    /// The new votes and forks are sent to the subscribers of `subscribe()`.
     pub async fn fetch(
         &mut self,
-        _network_config: &NetworkConfig,
-        _known_peers: &[Peer],
+        network_config: &NetworkConfig,
+        known_peers: &[Peer],
+    ) -> Result<FetchOutcome, Error> {
+        self.add_remotes(known_peers).await?;
+        let old_forks = self.find_forks().await?;
+        let old_votes = self.list_vote_tags().await?;
+        match self.fetch_depth {
+            Some(depth) => self.raw.fetch_all_with_depth(depth).await?,
+            None => self.raw.fetch_all().await?,
+        }
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+
+        let last_height = self.get_last_finalized_block_header().await?.height;
+        let mut outcome = FetchOutcome::default();
+        let mut remote_branches: BTreeMap<String, Vec<(Branch, CommitHash)>> = BTreeMap::new();
+        for (remote_name, branch, commit_hash) in
+            self.raw.list_remote_tracking_branches(None).await?
+        {
+            remote_branches
+                .entry(remote_name)
+                .or_default()
+                .push((branch, commit_hash));
+        }
+        let mut fp_commits = Vec::new();
+        for (remote_name, branches) in remote_branches {
+            let mut imported = 0;
+            for (branch, commit_hash) in branches {
+                if branch == FP_BRANCH_NAME {
+                    fp_commits.push((remote_name.clone(), commit_hash));
+                    continue;
+                }
+                if self.is_branch_tip(&commit_hash).await? {
+                    continue;
+                }
+                if imported >= network_config.max_branches_per_peer {
+                    log::warn!(
+                        "skipping the rest of the branches of {}: exceeded the limit of {}",
+                        remote_name,
+                        network_config.max_branches_per_peer
+                    );
+                    break;
+                }
+                // Invalid branches count toward the limit too.
+                imported += 1;
+                let error = match self
+                    .raw
+                    .try_merge_base(last_header_commit, commit_hash)
+                    .await
+                {
+                    Ok(Some(_)) => None,
+                    Ok(None) => Some(format!(
+                        "branch {} is unrelated to {}",
+                        branch, FINALIZED_BRANCH_NAME
+                    )),
+                    // After a shallow fetch, the history may stop before reaching ours.
+                    Err(e) => Some(format!(
+                        "branch {} has no common history with {} within the fetch depth: {}",
+                        branch, FINALIZED_BRANCH_NAME, e
+                    )),
+                };
+                if let Some(error) = error {
+                    outcome.errors.push((remote_name.clone(), error));
+                    // A branch cut by a shallow fetch would break the walks over the history,
+                    // so it is dropped until a later fetch brings it back.
+                    if self.fetch_depth.is_some() {
+                        self.raw
+                            .delete_remote_tracking_branch(remote_name.clone(), branch)
+                            .await?;
+                    }
+                    continue;
+                }
+                match self.import_branch(&commit_hash).await {
+                    Ok(Some(new_branch)) => outcome.new_branches.push(new_branch),
+                    Ok(None) => (),
+                    Err(e) => outcome.errors.push((
+                        remote_name.clone(),
+                        format!("failed to import branch {}: {}", branch, e),
+                    )),
+                }
+            }
+        }
+        for (remote_name, fp_commit) in fp_commits {
+            if let Err(e) = self.finalize_from_fp_commit(&fp_commit).await {
+                outcome.errors.push((
+                    remote_name,
+                    format!("failed to finalize with {}: {}", FP_BRANCH_NAME, e),
+                ));
+            }
+        }
+        // Tags are fetched from all the remotes into the same namespace,
+        // so the failures can't be attributed to a remote.
+        for tag in self.list_fp_tags().await? {
+            if let Err(e) = self.finalize_from_fp_tag(&tag).await {
+                log::warn!("failed to finalize with tag {}: {}", tag, e);
+            }
+        }
+        let height = self.get_last_finalized_block_header().await?.height;
+        if height > last_height {
+            outcome.finalized_height = Some(height);
+        }
+        for (tag, commit_hash) in self.list_vote_tags().await? {
+            if !old_votes.contains(&(tag.clone(), commit_hash)) {
+                self.emit(RepositoryEvent::VoteReceived { tag, commit_hash });
+            }
+        }
+        for (branch, height) in self.find_forks().await? {
+            if !old_forks.contains(&(branch.clone(), height)) {
+                self.emit(RepositoryEvent::ForkDetected { branch, height });
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Verifies the commits from `finalized` to the given tip, without changing the repository.
+    ///
+    /// An invalid branch, including one that diverges from `finalized`, is reported in
+    /// the returned `BranchVerification`; an `Err` means that the verification itself failed.
+    pub async fn verify_branch(&self, tip: &CommitHash) -> Result<BranchVerification, Error> {
+        let last_header = self.get_last_finalized_block_header().await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let mut verification = BranchVerification {
+            error: None,
+            phase: Phase::Block,
+            finalizable_block: None,
+        };
+        match self.raw.try_merge_base(last_header_commit, *tip).await? {
+            Some(merge_base) if merge_base == last_header_commit => (),
+            Some(merge_base) => {
+                verification.error = Some(format!(
+                    "{} diverges from {} at {}",
+                    tip, FINALIZED_BRANCH_NAME, merge_base
+                ));
+                return Ok(verification);
+            }
+            None => {
+                verification.error =
+                    Some(format!("{} is unrelated to {}", tip, FINALIZED_BRANCH_NAME));
+                return Ok(verification);
+            }
+        }
+        let commits = match self.raw.descendants_until(last_header_commit, *tip).await {
+            Ok(commits) => commits,
+            Err(e) => {
+                verification.error = Some(e.to_string());
+                return Ok(verification);
+            }
+        };
+
+        let reserved_state = self.get_reserved_state().await?;
+        let mut verifier = CommitSequenceVerifier::new(last_header, reserved_state)
+            .map_err(|e| anyhow!("verification error on commit {}: {}", last_header_commit, e))?;
+        for commit_hash in &commits {
+            let commit = match self.get_commit(commit_hash).await {
+                Ok(commit) => commit,
+                Err(e) => {
+                    verification.error = Some(e.to_string());
+                    return Ok(verification);
+                }
+            };
+            if let Err(e) = self
+                .apply_commit_to_verifier(&mut verifier, commit_hash, &commit)
+                .await
+            {
+                verification.error = Some(e.to_string());
+                return Ok(verification);
+            }
+            verification.phase = Phase::from_last_commit(Some(&commit));
+            if let Commit::Block(block_header) = commit {
+                if verification.finalizable_block.is_none() {
+                    verification.finalizable_block = Some((*commit_hash, block_header));
+                }
+            }
+        }
+        self.cache_verified_commits(last_header_commit, commits);
+        Ok(verification)
+    }
+
+    /// Syncs and finalizes the block of the given `fp` commit, if it is newer than `finalized`.
+    async fn finalize_from_fp_commit(&mut self, fp_commit: &CommitHash) -> Result<(), Error> {
+        let block_commit = *self
+            .raw
+            .list_ancestors(*fp_commit, Some(1))
+            .await?
+            .first()
+            .ok_or_else(|| anyhow!("commit {} has no parent", fp_commit))?;
+        let block_header = match self.get_commit(&block_commit).await? {
+            Commit::Block(block_header) => block_header,
+            _ => return Err(anyhow!("commit {} is not a block commit", block_commit)),
+        };
+        if block_header.height <= self.get_last_finalized_block_header().await?.height {
+            return Ok(());
+        }
+        let semantic_commit = self.raw.read_semantic_commit(*fp_commit).await?;
+        let proof: FinalizationProof = serde_json::from_str(&semantic_commit.body)
+            .map_err(|e| anyhow!("failed to parse commit {}: {}", fp_commit, e))?;
+        self.check_embedded_finalization_proof(&block_commit)
+            .await?;
+        self.sync(&block_commit).await?;
+        self.finalize(&block_commit, &proof).await?;
+        Ok(())
+    }
+
+    /// Checks that the first block on the way to the given block embeds the same finalization
+    /// proof of the `finalized` block as the local one, so that a peer can't claim a different
+    /// proof for our finalized tip.
+    ///
+    /// The signatures may come in any order. Nothing is checked if there is no local proof.
+    async fn check_embedded_finalization_proof(
+        &self,
+        block_commit: &CommitHash,
     ) -> Result<(), Error> {
-        unimplemented!()
+        let local_proof = match self.get_last_finalization_proof().await {
+            Ok(proof) => proof,
+            Err(_) => return Ok(()),
+        };
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        for commit_hash in self
+            .raw
+            .descendants_until(last_header_commit, *block_commit)
+            .await?
+        {
+            if let Commit::Block(block_header) = self.get_commit(&commit_hash).await? {
+                let proof = &block_header.prev_block_finalization_proof;
+                if proof.len() != local_proof.len()
+                    || proof
+                        .iter()
+                        .any(|signature| !local_proof.contains(signature))
+                {
+                    return Err(anyhow!(
+                        "block {} embeds a finalization proof of the finalized block {} \
+                        that differs from the local one",
+                        commit_hash,
+                        last_header_commit
+                    ));
+                }
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the `fp-<height>` tags in the order of the heights.
+    async fn list_fp_tags(&self) -> Result<Vec<Tag>, Error> {
+        let mut tags = self
+            .raw
+            .list_tags()
+            .await?
+            .into_iter()
+            .filter_map(|tag| {
+                let height = tag
+                    .strip_prefix(FP_TAG_PREFIX)?
+                    .parse::<BlockHeight>()
+                    .ok()?;
+                Some((height, tag))
+            })
+            .collect::<Vec<_>>();
+        tags.sort();
+        Ok(tags.into_iter().map(|(_, tag)| tag).collect())
+    }
+
+    /// Syncs and finalizes the block of the given `fp-<height>` tag, if it is newer than `finalized`.
+    ///
+    /// The proof is verified by `finalize()` as if it came from an `fp` commit.
+    async fn finalize_from_fp_tag(&mut self, tag: &Tag) -> Result<(), Error> {
+        let block_commit = self.raw.locate_tag(tag.clone()).await?;
+        let block_header = match self.get_commit(&block_commit).await? {
+            Commit::Block(block_header) => block_header,
+            _ => return Err(anyhow!("commit {} is not a block commit", block_commit)),
+        };
+        if tag.strip_prefix(FP_TAG_PREFIX) != Some(&block_header.height.to_string()) {
+            return Err(anyhow!(
+                "tag {} is on the block of height {}",
+                tag,
+                block_header.height
+            ));
+        }
+        if block_header.height <= self.get_last_finalized_block_header().await?.height {
+            return Ok(());
+        }
+        let message = self
+            .raw
+            .read_tag_message(tag.clone())
+            .await?
+            .ok_or_else(|| anyhow!("tag {} has no message", tag))?;
+        let proof: FinalizationProof = serde_json::from_str(&message)
+            .map_err(|e| anyhow!("failed to parse tag {}: {}", tag, e))?;
+        self.check_embedded_finalization_proof(&block_commit)
+            .await?;
+        self.sync(&block_commit).await?;
+        self.finalize(&block_commit, &proof).await?;
+        Ok(())
+    }
+
+    /// Lists the `vote-#` and `veto-#` tags with their commits.
+    async fn list_vote_tags(&self) -> Result<Vec<(Tag, CommitHash)>, Error> {
+        Ok(self
+            .raw
+            .list_tags_with_targets()
+            .await?
+            .into_iter()
+            .filter(|(tag, _)| tag.starts_with(VOTE_TAG_PREFIX) || tag.starts_with(VETO_TAG_PREFIX))
+            .collect())
+    }
+
+    /// Checks whether the given commit is the tip of any local branch.
+    async fn is_branch_tip(&self, commit_hash: &CommitHash) -> Result<bool, Error> {
+        Ok(self
+            .raw
+            .list_branches_with_tips()
+            .await?
+            .iter()
+            .any(|(_, tip)| tip == commit_hash))
+    }
+
+    /// Verifies the given commit with `verify_branch()`, and only then creates an `a-#` or `b-#`
+    /// branch for it,
+    /// depending on whether it is an agenda (or agenda proof) or a block.
+    ///
+    /// Returns the name of the new branch; commits of the other types, and stale commits
+    /// at or below the last finalized block, are ignored.
+    async fn import_branch(&mut self, commit_hash: &CommitHash) -> Result<Option<Branch>, Error> {
+        let last_header = self.get_last_finalized_block_header().await?;
+        let prefix = match self.get_commit(commit_hash).await? {
+            Commit::Agenda(_) | Commit::AgendaProof(_) => "a",
+            // Stale blocks, such as the ones from lagging peers, are skipped without verification.
+            Commit::Block(block_header) if block_header.height <= last_header.height => {
+                return Ok(None)
+            }
+            Commit::Block(_) => "b",
+            _ => return Ok(None),
+        };
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        if self
+            .raw
+            .is_ancestor(*commit_hash, last_header_commit)
+            .await?
+        {
+            return Ok(None);
+        }
+        if let Some(error) = self.verify_branch(commit_hash).await?.error {
+            return Err(anyhow!(error));
+        }
+        let branches = self.raw.list_branches().await?;
+        let branch = (0..)
+            .map(|i| format!("{}-{}", prefix, i))
+            .find(|branch| !branches.contains(branch))
+            .expect("there must be an unused branch name");
+        let branch_commit = self.raw.create_branch(branch.clone(), *commit_hash).await?;
+        if branch_commit != *commit_hash {
+            return Err(anyhow!(
+                "branch {} was created on {} instead of {}",
+                branch,
+                branch_commit,
+                commit_hash
+            ));
+        }
+        Ok(Some(branch))
+    }
+
+    /// Adds a remote for each of the given peers, unless it is already added.
+    async fn add_remotes(&mut self, known_peers: &[Peer]) -> Result<(), Error> {
+        let mut remotes = self
+            .raw
+            .list_remotes()
+            .await?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<BTreeSet<_>>();
+        for peer in known_peers {
+            let remote_name = get_remote_name(peer);
+            // The same peer may be given more than once.
+            if !remotes.insert(remote_name.clone()) {
+                continue;
+            }
+            let port = peer.ports.get(REPOSITORY_PORT_KEY).ok_or_else(|| {
+                anyhow!(
+                    "can't find port key {} of peer {}",
+                    REPOSITORY_PORT_KEY,
+                    remote_name
+                )
+            })?;
+            let remote_url = format!("git://{}:{}/", peer.address.ip(), port);
+            self.raw.add_remote(remote_name, remote_url).await?;
+        }
+        Ok(())
     }
 
     /// Notifies there was a push for the given repository.
@@ -92,22 +1123,340 @@ impl<T: RawRepository> DistributedRepository<T> {
     /// Serves the distributed repository protocol indefinitely.
     /// It **verifies** all the incoming changes and applies them to the local repository
     /// only if they are valid.
+    ///
+    /// It fetches from the currently known peers periodically, waiting on the given timer
+    /// between the fetches. A failed fetch is logged and retried at the next one.
+    /// The caller may keep a clone of the timer to wake it up for an early fetch
+    /// (e.g., when a peer announces new commits).
+    ///
+    /// Every `SERVE_GC_PERIOD` fetches, it runs garbage collection right after the fetch.
+    /// The task owns the repository, so no other operation can be in progress at that point,
+    /// and everything that the fetch has written is referenced by then.
     pub async fn serve(
-        self,
-        _network_config: &NetworkConfig,
-        _peers: SharedKnownPeers,
+        mut self,
+        network_config: &NetworkConfig,
+        peers: SharedKnownPeers,
+        timer: FetchTimer,
     ) -> Result<tokio::task::JoinHandle<Result<(), Error>>, Error> {
-        unimplemented!()
+        let network_config = network_config.clone();
+        Ok(tokio::spawn(async move {
+            for fetch_count in 1.. {
+                timer.wait().await;
+                let known_peers = peers.read().await;
+                if let Err(e) = self.fetch(&network_config, &known_peers).await {
+                    log::warn!("failed to fetch from the peers: {}", e);
+                }
+                if fetch_count % SERVE_GC_PERIOD == 0 {
+                    if let Err(e) = self.raw.run_garbage_collection().await {
+                        log::warn!("failed to collect garbage: {}", e);
+                    }
+                }
+            }
+            Ok(())
+        }))
+    }
+
+    /// Checks the validity of the repository, starting from the given height.
+    ///
+    /// It checks
+    /// 1. all the reserved branches and tags
+    /// 2. the existence of merge commits on any of the reserved branches
+    /// 3. the canonical history of the `finalized` branch.
+    ///
+    /// The history below `starting_height` is trusted and not verified again.
+    /// It fails if `starting_height` is above the last finalized height.
+    pub async fn check(&self, starting_height: BlockHeight) -> Result<bool, Error> {
+        let last_header = self.get_last_finalized_block_header().await?;
+        if starting_height > last_header.height {
+            return Err(anyhow!(
+                "starting height {} is above the last finalized height {}",
+                starting_height,
+                last_header.height
+            ));
+        }
+        if !self.check_reserved_branches().await? || !self.check_reserved_tags().await? {
+            return Ok(false);
+        }
+        let merges = self.find_merge_commits().await?;
+        for (branch, commit_hash) in &merges {
+            log::warn!("branch {} contains a merge commit {}", branch, commit_hash);
+        }
+        if !merges.is_empty() {
+            return Ok(false);
+        }
+
+        // Walks back from `finalized` to the block at the starting height, without verification.
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let ancestors = match self.raw.list_ancestors(last_header_commit, None).await {
+            Ok(ancestors) => ancestors,
+            // There is a merge commit.
+            Err(_) => return Ok(false),
+        };
+        let mut commits = Vec::new();
+        let mut starting_block = None;
+        for commit_hash in [vec![last_header_commit], ancestors].concat() {
+            let commit = match self.get_commit(&commit_hash).await {
+                Ok(commit) => commit,
+                Err(_) => return Ok(false),
+            };
+            if let Commit::Block(block_header) = &commit {
+                if block_header.height == starting_height {
+                    starting_block = Some((commit_hash, block_header.clone()));
+                    break;
+                }
+            }
+            commits.push((commit_hash, commit));
+        }
+        let (starting_block_commit, starting_header) = match starting_block {
+            Some(starting_block) => starting_block,
+            None => return Ok(false),
+        };
+
+        let reserved_state = self
+            .raw
+            .read_reserved_state_at_commit(starting_block_commit)
+            .await?;
+        let mut verifier = match CommitSequenceVerifier::new(starting_header, reserved_state) {
+            Ok(verifier) => verifier,
+            Err(_) => return Ok(false),
+        };
+        for (commit_hash, commit) in commits.iter().rev() {
+            if self
+                .apply_commit_to_verifier(&mut verifier, commit_hash, commit)
+                .await
+                .is_err()
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Runs `check()` and also reports the fork branches and the branches with merge commits.
+    ///
+    /// A `b-#` branch is a fork if its block is neither on the history of `finalized`
+    /// nor on top of it. Even if the history is canonical, a fork is a sign of danger.
+    pub async fn check_health(
+        &self,
+        starting_height: BlockHeight,
+    ) -> Result<RepositoryHealth, Error> {
+        let valid = self.check(starting_height).await?;
+        let forks = self.find_forks().await?;
+        let merges = self.find_merge_commits().await?;
+        Ok(RepositoryHealth {
+            valid,
+            forks,
+            merges,
+        })
+    }
+
+    /// Finds the `b-#` branches that diverge from `finalized`, with the heights of their blocks.
+    async fn find_forks(&self) -> Result<Vec<(Branch, BlockHeight)>, Error> {
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let mut forks = Vec::new();
+        for branch in self.raw.list_branches().await? {
+            if !branch.starts_with("b-") {
+                continue;
+            }
+            let commit_hash = self.raw.locate_branch(branch.clone()).await?;
+            let block_header = match self.get_commit(&commit_hash).await {
+                Ok(Commit::Block(block_header)) => block_header,
+                _ => continue,
+            };
+            if !self
+                .raw
+                .is_ancestor(commit_hash, last_header_commit)
+                .await?
+                && !self
+                    .raw
+                    .is_ancestor(last_header_commit, commit_hash)
+                    .await?
+            {
+                forks.push((branch, block_header.height));
+            }
+        }
+        Ok(forks)
+    }
+
+    /// Finds the nearest merge commit of each reserved branch
+    /// (`finalized`, `work`, `fp`, `a-#` and `b-#`), if any.
+    async fn find_merge_commits(&self) -> Result<Vec<(Branch, CommitHash)>, Error> {
+        let mut merges = Vec::new();
+        for branch in self.raw.list_branches().await? {
+            if ![FINALIZED_BRANCH_NAME, WORK_BRANCH_NAME, FP_BRANCH_NAME].contains(&branch.as_str())
+                && !branch.starts_with("a-")
+                && !branch.starts_with("b-")
+            {
+                continue;
+            }
+            let commit_hash = self.raw.locate_branch(branch.clone()).await?;
+            if let Some(merge_commit) = self.raw.find_merge_commit(commit_hash).await? {
+                merges.push((branch, merge_commit));
+            }
+        }
+        Ok(merges)
+    }
+
+    /// Checks that each reserved tag has a valid name and is on a commit of the expected kind.
+    ///
+    /// Every invalid tag is reported as a warning.
+    async fn check_reserved_branches(&self) -> Result<bool, Error> {
+        let branches = self.raw.list_branches().await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let mut valid = true;
+        if branches.contains(&FP_BRANCH_NAME.to_owned()) {
+            let fp_commit = self.raw.locate_branch(FP_BRANCH_NAME.into()).await?;
+            if let Err(e) = self.check_fp_commit(&fp_commit, &last_header_commit).await {
+                log::warn!("invalid branch {}: {}", FP_BRANCH_NAME, e);
+                valid = false;
+            }
+        }
+        // `work` may not have been rebased on `finalized` yet, but must share the history.
+        if branches.contains(&WORK_BRANCH_NAME.to_owned()) {
+            let work_commit = self.raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
+            if self
+                .raw
+                .try_merge_base(last_header_commit, work_commit)
+                .await?
+                .is_none()
+            {
+                log::warn!(
+                    "branch {} is unrelated to {}",
+                    WORK_BRANCH_NAME,
+                    FINALIZED_BRANCH_NAME
+                );
+                valid = false;
+            }
+        }
+        Ok(valid)
+    }
+
+    /// Checks that the given `fp` commit is on a block in the history of `finalized`
+    /// and carries a valid finalization proof of it.
+    ///
+    /// The block may be below `finalized`, since `sync()` can leave `fp` behind.
+    async fn check_fp_commit(
+        &self,
+        fp_commit: &CommitHash,
+        last_header_commit: &CommitHash,
+    ) -> Result<(), Error> {
+        let block_commit = *self
+            .raw
+            .list_ancestors(*fp_commit, Some(1))
+            .await?
+            .first()
+            .ok_or_else(|| anyhow!("commit {} has no parent", fp_commit))?;
+        if !self
+            .raw
+            .is_ancestor(block_commit, *last_header_commit)
+            .await?
+        {
+            return Err(anyhow!(
+                "commit {} is not in the history of {}",
+                block_commit,
+                FINALIZED_BRANCH_NAME
+            ));
+        }
+        let block_header = match self.get_commit(&block_commit).await? {
+            Commit::Block(block_header) => block_header,
+            _ => return Err(anyhow!("commit {} is not a block commit", block_commit)),
+        };
+        let semantic_commit = self.raw.read_semantic_commit(*fp_commit).await?;
+        let proof: FinalizationProof = serde_json::from_str(&semantic_commit.body)
+            .map_err(|e| anyhow!("failed to parse commit {}: {}", fp_commit, e))?;
+        verify::verify_finalization_proof(&block_header, &proof)
+            .map_err(|e| anyhow!("verification error on commit {}: {}", fp_commit, e))?;
+        Ok(())
+    }
+
+    async fn check_reserved_tags(&self) -> Result<bool, Error> {
+        let mut valid = true;
+        for tag in self.raw.list_tags().await? {
+            let (prefix, number) = match [
+                VOTE_TAG_PREFIX,
+                VETO_TAG_PREFIX,
+                BLOCK_TAG_PREFIX,
+                FP_TAG_PREFIX,
+            ]
+            .into_iter()
+            .find_map(|prefix| tag.strip_prefix(prefix).map(|number| (prefix, number)))
+            {
+                Some(x) => x,
+                None => continue,
+            };
+            let number = match number.parse::<u64>() {
+                Ok(number) => number,
+                Err(_) => {
+                    log::warn!("invalid reserved tag name: {}", tag);
+                    valid = false;
+                    continue;
+                }
+            };
+            let commit_hash = match self.raw.locate_tag(tag.clone()).await {
+                Ok(commit_hash) => commit_hash,
+                Err(e) => {
+                    log::warn!("dangling reserved tag {}: {}", tag, e);
+                    valid = false;
+                    continue;
+                }
+            };
+            let is_expected = match self.get_commit(&commit_hash).await {
+                Ok(Commit::Agenda(_)) => prefix == VOTE_TAG_PREFIX || prefix == VETO_TAG_PREFIX,
+                Ok(Commit::Block(block_header)) => {
+                    prefix == VETO_TAG_PREFIX
+                        || ((prefix == BLOCK_TAG_PREFIX || prefix == FP_TAG_PREFIX)
+                            && block_header.height == number)
+                }
+                _ => false,
+            };
+            if !is_expected {
+                log::warn!(
+                    "reserved tag {} is on an unexpected commit {}",
+                    tag,
+                    commit_hash
+                );
+                valid = false;
+            }
+        }
+        Ok(valid)
     }
 
-    /// Checks the validity of the repository, starting from the given height.
+    /// Removes the outdated `a-#` and `b-#` branches, which are not on top of `finalized`,
+    /// and the `vote-#` and `veto-#` tags on the commits that are reachable from
+    /// neither `finalized` nor one of the remaining branches.
     ///
-    /// It checks
-    /// 1. all the reserved branches and tags
-    /// 2. the existence of merge commits
-    /// 3. the canonical history of the `finalized` branch.
-    pub async fn check(&self, _starting_height: BlockHeight) -> Result<bool, Error> {
-        unimplemented!()
+    /// The votes and vetoes on the pending agendas and blocks are kept.
+    pub async fn clean(&mut self) -> Result<(), Error> {
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let mut live_commits = vec![last_header_commit];
+        for (branch, commit_hash) in self.raw.list_branches_with_tips().await? {
+            if !branch.starts_with("a-") && !branch.starts_with("b-") {
+                continue;
+            }
+            if commit_hash != last_header_commit
+                && self
+                    .raw
+                    .is_ancestor(last_header_commit, commit_hash)
+                    .await?
+            {
+                live_commits.push(commit_hash);
+            } else {
+                self.raw.delete_branch(branch).await?;
+            }
+        }
+        for (tag, commit_hash) in self.list_vote_tags().await? {
+            let mut is_live = false;
+            for live_commit in &live_commits {
+                if self.raw.is_ancestor(commit_hash, *live_commit).await? {
+                    is_live = true;
+                    break;
+                }
+            }
+            if !is_live {
+                self.raw.remove_tag(tag).await?;
+            }
+        }
+        Ok(())
     }
 
     /// Synchronizes the `finalized` branch to the given commit.
@@ -119,51 +1468,689 @@ impl<T: RawRepository> DistributedRepository<T> {
     /// Note that if you sync to a block `H`, then the `finalized` branch will move to `H-1`.
     /// To sync the last block `H`, you have to run `finalize()`.
     /// (This is because the finalization proof for a block appears in the next block.)
-    pub async fn sync(&mut self, _block_commit: &CommitHash) -> Result<(), Error> {
-        unimplemented!()
+    ///
+    /// On a long sync, `finalized` (with `fp`) advances to a verified block every
+    /// `set_sync_checkpoint_interval()` blocks, so that a failed or interrupted sync resumes
+    /// from there.
+    pub async fn sync(&mut self, block_commit: &CommitHash) -> Result<(), Error> {
+        self.sync_with_progress(block_commit, false, |_, _| {})
+            .await
+    }
+
+    /// Same as `sync()`, but calls `progress` with `(height, target height)`
+    /// whenever a block is verified.
+    ///
+    /// If `repair_fp` is set and the `fp` branch is not on top of the new `finalized`,
+    /// it rewrites `fp` with the finalization proof in the given block.
+    /// Otherwise `fp` is only written at the checkpoints.
+    pub async fn sync_with_progress(
+        &mut self,
+        block_commit: &CommitHash,
+        repair_fp: bool,
+        mut progress: impl FnMut(BlockHeight, BlockHeight) + Send,
+    ) -> Result<(), Error> {
+        let last_header = self.get_last_finalized_block_header().await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        if *block_commit == last_header_commit {
+            return Ok(());
+        }
+        let target_header = match self.get_commit(block_commit).await? {
+            Commit::Block(block_header) => block_header,
+            _ => return Err(anyhow!("commit {} is not a block commit", block_commit)),
+        };
+        let target_height = target_header.height;
+
+        // This fails unless the given commit is linearly descended from the `finalized` branch.
+        let commits = self
+            .raw
+            .descendants_until(last_header_commit, *block_commit)
+            .await?;
+
+        let mut checkpoint_height = last_header.height;
+        let reserved_state = self.get_reserved_state().await?;
+        let mut verifier = CommitSequenceVerifier::new(last_header.clone(), reserved_state)
+            .map_err(|e| anyhow!("verification error on commit {}: {}", last_header_commit, e))?;
+        let mut last_verified_block_commit = last_header_commit;
+        let mut last_verified_block_header = last_header;
+        // The last block that `finalized` has been reported to have moved to.
+        let mut reported_block_commit = last_header_commit;
+        for commit_hash in commits {
+            let commit = self.get_commit(&commit_hash).await?;
+            self.apply_commit_to_verifier(&mut verifier, &commit_hash, &commit)
+                .await?;
+            if let Commit::Block(block_header) = commit {
+                progress(block_header.height, target_height);
+                // This block carries the (now verified) proof of the previous one.
+                let previous_height = block_header.height - 1;
+                if previous_height >= checkpoint_height + self.sync_checkpoint_interval {
+                    self.raw
+                        .move_branch(
+                            FINALIZED_BRANCH_NAME.into(),
+                            last_verified_block_commit,
+                            true,
+                            format!("sync: checkpoint at block {}", previous_height),
+                        )
+                        .await?;
+                    self.write_finalization_proof(
+                        &last_verified_block_commit,
+                        &block_header.prev_block_finalization_proof,
+                    )
+                    .await?;
+                    self.notify_finalized(&last_verified_block_commit, &last_verified_block_header);
+                    reported_block_commit = last_verified_block_commit;
+                    checkpoint_height = previous_height;
+                }
+                if commit_hash != *block_commit {
+                    last_verified_block_commit = commit_hash;
+                    last_verified_block_header = block_header;
+                }
+            }
+        }
+
+        // The given block itself can't be finalized here since its finalization proof is unknown.
+        self.raw
+            .move_branch(
+                FINALIZED_BRANCH_NAME.into(),
+                last_verified_block_commit,
+                true,
+                format!("sync: advance to {:#}", last_verified_block_commit),
+            )
+            .await?;
+
+        if repair_fp {
+            let is_fp_fresh = if self
+                .raw
+                .list_branches()
+                .await?
+                .contains(&FP_BRANCH_NAME.to_owned())
+            {
+                let fp_commit = self.raw.locate_branch(FP_BRANCH_NAME.into()).await?;
+                self.raw.list_ancestors(fp_commit, Some(1)).await?
+                    == vec![last_verified_block_commit]
+            } else {
+                false
+            };
+            // The given block carries the (already verified) proof of the new `finalized`.
+            if !is_fp_fresh {
+                self.write_finalization_proof(
+                    &last_verified_block_commit,
+                    &target_header.prev_block_finalization_proof,
+                )
+                .await?;
+            }
+        }
+        // Unlike at the checkpoints, `fp` may be left behind, so `on_finalize` is not called.
+        if last_verified_block_commit != reported_block_commit {
+            self.emit(RepositoryEvent::Finalized(Finalized {
+                height: last_verified_block_header.height,
+                block_hash: last_verified_block_header.to_hash256(),
+                commit_hash: last_verified_block_commit,
+            }));
+        }
+        Ok(())
     }
     /// Returns the currently valid and height-acceptable agendas in the repository.
-    pub async fn get_agendas(&self) -> Result<Vec<(CommitHash, Hash256)>, Error> {
-        unimplemented!()
+    pub async fn get_agendas(&self) -> Result<Vec<AgendaInfo>, Error> {
+        let height = self.get_last_finalized_block_header().await?.height + 1;
+        let mut agendas: Vec<AgendaInfo> = Vec::new();
+        for branch in self.raw.list_branches().await? {
+            let commit_hash = self.raw.locate_branch(branch).await?;
+            if agendas.iter().any(|agenda| agenda.commit == commit_hash) {
+                continue;
+            }
+            // Branches that don't point to an agenda or that are invalid are just ignored.
+            if let Ok(Commit::Agenda(agenda)) = self.get_commit(&commit_hash).await {
+                if self
+                    .verify_commits_from_finalized(&commit_hash)
+                    .await
+                    .is_ok()
+                {
+                    agendas.push(AgendaInfo {
+                        commit: commit_hash,
+                        hash: agenda.to_hash256(),
+                        author: agenda.author,
+                        timestamp: agenda.timestamp,
+                        height,
+                    });
+                }
+            }
+        }
+        Ok(agendas)
+    }
+
+    /// Returns the agendas of `get_agendas()` that are still actionable,
+    /// i.e., those that have not been approved by a valid agenda-proof commit on any branch,
+    /// nor vetoed by members with at least 1/3 of the governance voting power.
+    ///
+    /// With that many vetoes, the agenda can't get the more than 2/3 that its approval requires.
+    /// The vetoes are the `veto-#` tags on the agenda commit, counted by the members that they
+    /// refer to in the reserved state of `finalized`; those that refer to no member are ignored.
+    pub async fn get_actionable_agendas(&self) -> Result<Vec<AgendaInfo>, Error> {
+        let mut agendas = self.get_agendas().await?;
+        let reserved_state = self.get_reserved_state().await?;
+        let total_voting_power: VotingPower = reserved_state
+            .members
+            .iter()
+            .map(|member| member.governance_voting_power)
+            .sum();
+        let mut vetoers: HashMap<CommitHash, BTreeSet<usize>> = HashMap::new();
+        for (tag, commit_hash) in self.list_vote_tags().await? {
+            if let Some(Ok(index)) = tag.strip_prefix(VETO_TAG_PREFIX).map(str::parse::<usize>) {
+                if index < reserved_state.members.len() {
+                    vetoers.entry(commit_hash).or_default().insert(index);
+                }
+            }
+        }
+        agendas.retain(|agenda| {
+            let vetoed_voting_power: VotingPower = vetoers
+                .get(&agenda.commit)
+                .into_iter()
+                .flatten()
+                .map(|&index| reserved_state.members[index].governance_voting_power)
+                .sum();
+            vetoed_voting_power * 3 < total_voting_power
+        });
+        for branch in self.raw.list_branches().await? {
+            let commit_hash = self.raw.locate_branch(branch).await?;
+            if !matches!(
+                self.get_commit(&commit_hash).await,
+                Ok(Commit::AgendaProof(_))
+            ) {
+                continue;
+            }
+            let agenda_commit = match self.raw.list_ancestors(commit_hash, Some(1)).await?.first() {
+                Some(agenda_commit) => *agenda_commit,
+                None => continue,
+            };
+            if agendas.iter().any(|agenda| agenda.commit == agenda_commit)
+                && self.verify_agenda_proof(&commit_hash).await.is_ok()
+                && self
+                    .verify_commits_from_finalized(&commit_hash)
+                    .await
+                    .is_ok()
+            {
+                agendas.retain(|agenda| agenda.commit != agenda_commit);
+            }
+        }
+        Ok(agendas)
+    }
+
+    /// Verifies the commits from the `finalized` branch (exclusive) to the given one (inclusive).
+    ///
+    /// Commits that have already been verified since `finalized` last moved are not verified again.
+    async fn verify_commits_from_finalized(&self, commit_hash: &CommitHash) -> Result<(), Error> {
+        let last_header = self.get_last_finalized_block_header().await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        if *commit_hash == last_header_commit {
+            return Ok(());
+        }
+        // The cache is valid only while `finalized` stays on the commit it was built from.
+        if let Some((cached_commit_hash, verified_commits)) =
+            &*self.verified_commits_cache.lock().unwrap()
+        {
+            if *cached_commit_hash == last_header_commit && verified_commits.contains(commit_hash) {
+                return Ok(());
+            }
+        }
+        // This fails unless the given commit is linearly descended from the `finalized` branch.
+        let commits = self
+            .raw
+            .descendants_until(last_header_commit, *commit_hash)
+            .await?;
+
+        let reserved_state = self.get_reserved_state().await?;
+        let mut verifier = CommitSequenceVerifier::new(last_header, reserved_state)
+            .map_err(|e| anyhow!("verification error on commit {}: {}", last_header_commit, e))?;
+        for commit_hash in &commits {
+            let commit = self.get_commit(commit_hash).await?;
+            self.apply_commit_to_verifier(&mut verifier, commit_hash, &commit)
+                .await?;
+        }
+        // Every commit in the sequence is valid, not only the last one.
+        self.cache_verified_commits(last_header_commit, commits);
+        Ok(())
+    }
+
+    /// Applies the given commit to the verifier, which restarts from the commit if it is a block.
+    ///
+    /// Once the verifier has passed through a block, it expects the commit merkle root of the
+    /// next block to include the block itself, unlike when it starts from the block.
+    /// `create_block()` builds a block in the latter way, so every block is verified from
+    /// its previous block.
+    async fn apply_commit_to_verifier(
+        &self,
+        verifier: &mut CommitSequenceVerifier,
+        commit_hash: &CommitHash,
+        commit: &Commit,
+    ) -> Result<(), Error> {
+        verifier
+            .apply_commit(commit)
+            .map_err(|e| anyhow!("verification error on commit {}: {}", commit_hash, e))?;
+        if let Commit::Block(block_header) = commit {
+            let reserved_state = self.raw.read_reserved_state_at_commit(*commit_hash).await?;
+            *verifier = CommitSequenceVerifier::new(block_header.clone(), reserved_state)
+                .map_err(|e| anyhow!("verification error on commit {}: {}", commit_hash, e))?;
+        }
+        Ok(())
+    }
+
+    /// Records the commits verified on top of the given `finalized` commit.
+    fn cache_verified_commits(&self, last_header_commit: CommitHash, commits: Vec<CommitHash>) {
+        let mut cache = self.verified_commits_cache.lock().unwrap();
+        match &mut *cache {
+            Some((cached_commit_hash, verified_commits))
+                if *cached_commit_hash == last_header_commit =>
+            {
+                verified_commits.extend(commits)
+            }
+            _ => *cache = Some((last_header_commit, commits.into_iter().collect())),
+        }
+    }
+
+    /// Verifies the given agenda-proof commit against the members
+    /// as of the last block before the agenda, not the current ones.
+    pub async fn verify_agenda_proof(&self, agenda_proof_commit: &CommitHash) -> Result<(), Error> {
+        let agenda_proof = match self.get_commit(agenda_proof_commit).await? {
+            Commit::AgendaProof(agenda_proof) => agenda_proof,
+            _ => {
+                return Err(anyhow!(
+                    "commit {} is not an agenda-proof commit",
+                    agenda_proof_commit
+                ))
+            }
+        };
+        let ancestors = self.raw.list_ancestors(*agenda_proof_commit, None).await?;
+        let agenda = match ancestors.first() {
+            Some(agenda_commit) => match self.get_commit(agenda_commit).await? {
+                Commit::Agenda(agenda) => agenda,
+                _ => return Err(anyhow!("commit {} is not an agenda commit", agenda_commit)),
+            },
+            None => return Err(anyhow!("commit {} has no parent", agenda_proof_commit)),
+        };
+        if agenda_proof.agenda_hash != agenda.hash {
+            return Err(anyhow!(
+                "invalid agenda hash: expected {}, got {}",
+                agenda.hash,
+                agenda_proof.agenda_hash
+            ));
+        }
+        let mut block_commit = None;
+        for commit_hash in &ancestors[1..] {
+            if let Commit::Block(_) = self.get_commit(commit_hash).await? {
+                block_commit = Some(*commit_hash);
+                break;
+            }
+        }
+        let block_commit =
+            block_commit.ok_or_else(|| anyhow!("no block before {}", agenda_proof_commit))?;
+        let reserved_state = self.get_reserved_state_at(&block_commit).await?;
+
+        let total_voting_power: VotingPower = reserved_state
+            .members
+            .iter()
+            .map(|member| member.governance_voting_power)
+            .sum();
+        let mut voted_voting_power = 0;
+        let mut signers = BTreeSet::new();
+        for signature in &agenda_proof.proof {
+            signature
+                .verify(&agenda)
+                .map_err(|e| anyhow!("invalid agenda proof: {}", e))?;
+            if !signers.insert(signature.signer()) {
+                return Err(anyhow!(
+                    "invalid agenda proof: duplicate signer {}",
+                    signature.signer()
+                ));
+            }
+            let member = reserved_state
+                .members
+                .iter()
+                .find(|member| member.public_key == *signature.signer())
+                .ok_or_else(|| anyhow!("{} is not a member", signature.signer()))?;
+            voted_voting_power += member.governance_voting_power;
+        }
+        if voted_voting_power * 3 <= total_voting_power * 2 {
+            return Err(anyhow!(
+                "voted voting power is too low: {} / {}",
+                voted_voting_power,
+                total_voting_power
+            ));
+        }
+        Ok(())
     }
 
-    /// Returns the currently valid and height-acceptable blocks in the repository.
+    /// Returns the currently valid and height-acceptable blocks in the repository,
+    /// with the hashes of their headers.
     pub async fn get_blocks(&self) -> Result<Vec<(CommitHash, Hash256)>, Error> {
-        unimplemented!()
+        let height = self.get_last_finalized_block_header().await?.height + 1;
+        let mut blocks: Vec<(CommitHash, Hash256)> = Vec::new();
+        for branch in self.raw.list_branches().await? {
+            let commit_hash = self.raw.locate_branch(branch).await?;
+            if blocks.iter().any(|(commit, _)| *commit == commit_hash) {
+                continue;
+            }
+            // Branches that don't point to a block of the next height or that are invalid
+            // are just ignored.
+            if let Ok(Commit::Block(block_header)) = self.get_commit(&commit_hash).await {
+                if block_header.height == height
+                    && self
+                        .verify_commits_from_finalized(&commit_hash)
+                        .await
+                        .is_ok()
+                {
+                    blocks.push((commit_hash, block_header.to_hash256()));
+                }
+            }
+        }
+        Ok(blocks)
     }
 
     /// Finalizes a single block and moves the `finalized` branch to it.
     ///
     /// It will verify the finalization proof and the commits.
+    /// The proof is recorded in an empty commit on top of the block, pointed by the `fp` branch.
+    /// If recording the proof fails, the `finalized` branch is moved back.
+    ///
+    /// Returns the new tip of `finalized`.
     pub async fn finalize(
         &mut self,
-        _block_commit_hash: &CommitHash,
-        _proof: &FinalizationProof,
+        block_commit_hash: &CommitHash,
+        proof: &FinalizationProof,
+    ) -> Result<Finalized, Error> {
+        let last_header = self.get_last_finalized_block_header().await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let block_header = match self.get_commit(block_commit_hash).await? {
+            Commit::Block(block_header) => block_header,
+            _ => {
+                return Err(anyhow!(
+                    "commit {} is not a block commit",
+                    block_commit_hash
+                ))
+            }
+        };
+        // Heights must be contiguous; checked before any branch moves.
+        if block_header.height != last_header.height + 1 {
+            return Err(anyhow!(
+                "block {} has height {}, but the last finalized height is {}",
+                block_commit_hash,
+                block_header.height,
+                last_header.height
+            ));
+        }
+        // Verifies the commits; `finalized` doesn't move since the block is the next one.
+        self.sync(block_commit_hash).await?;
+        verify::verify_finalization_proof(&block_header, proof)
+            .map_err(|e| anyhow!("verification error on commit {}: {}", block_commit_hash, e))?;
+
+        let last_fp_commit = if self
+            .raw
+            .list_branches()
+            .await?
+            .contains(&FP_BRANCH_NAME.to_owned())
+        {
+            Some(self.raw.locate_branch(FP_BRANCH_NAME.into()).await?)
+        } else {
+            None
+        };
+        self.raw
+            .move_branch(
+                FINALIZED_BRANCH_NAME.into(),
+                *block_commit_hash,
+                true,
+                format!("finalize: advance to block {}", block_header.height),
+            )
+            .await?;
+        if let Err(e) = self
+            .write_finalization_proof(block_commit_hash, proof)
+            .await
+        {
+            // Roll back so that `finalized` never goes ahead of its proof.
+            self.raw
+                .move_branch(
+                    FINALIZED_BRANCH_NAME.into(),
+                    last_header_commit,
+                    false,
+                    format!("finalize: roll back from block {}", block_header.height),
+                )
+                .await?;
+            if let Some(last_fp_commit) = last_fp_commit {
+                self.raw
+                    .move_branch(
+                        FP_BRANCH_NAME.into(),
+                        last_fp_commit,
+                        false,
+                        format!("finalize: roll back from block {}", block_header.height),
+                    )
+                    .await?;
+            }
+            return Err(e);
+        }
+        // The tag is only for the convenience of explorers, so failing to create it is not fatal.
+        // An existing tag from an earlier attempt is overwritten.
+        let block_tag = format!("{}{}", BLOCK_TAG_PREFIX, block_header.height);
+        if let Err(e) = self
+            .raw
+            .create_tag(block_tag, *block_commit_hash, Some(raw::ObjectKind::Commit))
+            .await
+        {
+            log::warn!(
+                "failed to create the block tag on {}: {}",
+                block_commit_hash,
+                e
+            );
+        }
+        Ok(self.notify_finalized(block_commit_hash, &block_header))
+    }
+
+    /// Finalizes a single block like `finalize`, taking the proof as JSON.
+    ///
+    /// This is the same encoding as in `fp` commits and finalization tags.
+    pub async fn finalize_from_json(
+        &mut self,
+        block_commit_hash: &CommitHash,
+        proof_json: &str,
+    ) -> Result<Finalized, Error> {
+        let proof: FinalizationProof = serde_json::from_str(proof_json).map_err(|e| {
+            anyhow!(
+                "malformed finalization proof for commit {}: {}",
+                block_commit_hash,
+                e
+            )
+        })?;
+        self.finalize(block_commit_hash, &proof).await
+    }
+
+    /// Creates the `fp` commit for the given block and points the `fp` branch to it.
+    async fn write_finalization_proof(
+        &mut self,
+        block_commit_hash: &CommitHash,
+        proof: &FinalizationProof,
     ) -> Result<(), Error> {
-        unimplemented!()
+        if self
+            .raw
+            .list_branches()
+            .await?
+            .contains(&FP_BRANCH_NAME.to_owned())
+        {
+            self.raw
+                .move_branch(
+                    FP_BRANCH_NAME.into(),
+                    *block_commit_hash,
+                    false,
+                    format!("finalize: move onto {:#} for its proof", block_commit_hash),
+                )
+                .await?;
+        } else {
+            self.raw
+                .create_branch(FP_BRANCH_NAME.into(), *block_commit_hash)
+                .await?;
+        }
+        self.raw.checkout(FP_BRANCH_NAME.into()).await?;
+        self.raw
+            .create_commit(
+                format!("fp\n\n{}", serde_json::to_string(proof).unwrap()),
+                None,
+            )
+            .await?;
+        self.raw.checkout(FINALIZED_BRANCH_NAME.into()).await?;
+        Ok(())
+    }
+
+    /// Moves `finalized` back to the given earlier block, for testing and disaster recovery.
+    ///
+    /// The `fp` branch is restored with the proof of that block, which is taken from the header
+    /// of the next block. The `block-#` and `fp-#` tags above it are removed.
+    /// It does nothing unless `confirm` is set, so that it never happens by accident.
+    ///
+    /// The subscribers receive `RepositoryEvent::Rewound`, and `on_finalize` is not called.
+    pub async fn rewind_finalized(&mut self, to: &CommitHash, confirm: bool) -> Result<(), Error> {
+        if !confirm {
+            return Err(anyhow!("rewinding `finalized` requires a confirmation"));
+        }
+        let last_header = self.get_last_finalized_block_header().await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        let block_header = match self.get_commit(to).await? {
+            Commit::Block(block_header) => block_header,
+            _ => return Err(anyhow!("commit {} is not a block commit", to)),
+        };
+        if *to == last_header_commit || !self.raw.is_ancestor(*to, last_header_commit).await? {
+            return Err(anyhow!(
+                "commit {} is not an ancestor of the finalized commit {}",
+                to,
+                last_header_commit
+            ));
+        }
+        let mut proof = None;
+        for commit_hash in self.raw.descendants_until(*to, last_header_commit).await? {
+            if let Commit::Block(next_header) = self.get_commit(&commit_hash).await? {
+                proof = Some(next_header.prev_block_finalization_proof);
+                break;
+            }
+        }
+        // `last_header_commit` is a block descended from `to`, so there is always the next block.
+        let proof = proof.expect("there must be a block after the rewound one");
+
+        self.raw
+            .move_branch(
+                FINALIZED_BRANCH_NAME.into(),
+                *to,
+                false,
+                format!(
+                    "rewind: back from block {} to block {}",
+                    last_header.height, block_header.height
+                ),
+            )
+            .await?;
+        self.write_finalization_proof(to, &proof).await?;
+        let stale_tags = ((block_header.height + 1)..=last_header.height)
+            .flat_map(|height| {
+                [
+                    format!("{}{}", BLOCK_TAG_PREFIX, height),
+                    format!("{}{}", FP_TAG_PREFIX, height),
+                ]
+            })
+            .collect::<BTreeSet<_>>();
+        for tag in self.raw.list_tags().await? {
+            if stale_tags.contains(&tag) {
+                self.raw.remove_tag(tag).await?;
+            }
+        }
+        self.invalidate_cache();
+        self.emit(RepositoryEvent::Rewound(Finalized {
+            height: block_header.height,
+            block_hash: block_header.to_hash256(),
+            commit_hash: *to,
+        }));
+        Ok(())
     }
 
     /// Informs that the given agenda has been approved.
+    ///
+    /// It creates an agenda-proof commit on top of the agenda, with the signatures in its body
+    /// so that peers can verify it by themselves. The branches on the agenda (`work` or `a-#`)
+    /// are moved to the new commit; if there is none, a new `a-#` branch is created.
     pub async fn approve(
         &mut self,
-        _agenda_commit_hash: &CommitHash,
-        _proof: Vec<(PublicKey, TypedSignature<Agenda>)>,
+        agenda_commit_hash: &CommitHash,
+        proof: Vec<(PublicKey, TypedSignature<Agenda>)>,
     ) -> Result<CommitHash, Error> {
-        unimplemented!()
+        let agenda = match self.get_commit(agenda_commit_hash).await? {
+            Commit::Agenda(agenda) => agenda,
+            _ => {
+                return Err(anyhow!(
+                    "commit {} is not an agenda commit",
+                    agenda_commit_hash
+                ))
+            }
+        };
+        for (public_key, signature) in &proof {
+            if signature.signer() != public_key {
+                return Err(anyhow!(
+                    "the signature of {} is signed by {}",
+                    public_key,
+                    signature.signer()
+                ));
+            }
+        }
+        let last_header = self.get_last_finalized_block_header().await?;
+        let agenda_proof = AgendaProof {
+            agenda_hash: agenda.hash,
+            proof: proof.into_iter().map(|(_, signature)| signature).collect(),
+        };
+        let semantic_commit = to_semantic_commit(&Commit::AgendaProof(agenda_proof), &last_header);
+        let agenda_proof_commit = self
+            .raw
+            .create_commit_with_parents(
+                format!("{}\n\n{}", semantic_commit.title, semantic_commit.body),
+                vec![],
+                vec![*agenda_commit_hash],
+            )
+            .await?;
+        // No branch points to the commit until it is verified.
+        self.verify_agenda_proof(&agenda_proof_commit).await?;
+
+        let mut branches = Vec::new();
+        for branch in self.raw.list_branches().await? {
+            if (branch == WORK_BRANCH_NAME || branch.starts_with("a-"))
+                && self.raw.locate_branch(branch.clone()).await? == *agenda_commit_hash
+            {
+                branches.push(branch);
+            }
+        }
+        if branches.is_empty() {
+            let existing_branches = self.raw.list_branches().await?;
+            let branch = (0..)
+                .map(|i| format!("a-{}", i))
+                .find(|branch| !existing_branches.contains(branch))
+                .expect("there must be an unused branch name");
+            self.raw.create_branch(branch, agenda_proof_commit).await?;
+        }
+        for branch in branches {
+            self.raw
+                .move_branch(
+                    branch,
+                    agenda_proof_commit,
+                    true,
+                    format!("approve: {:#}", agenda_commit_hash),
+                )
+                .await?;
+        }
+        Ok(agenda_proof_commit)
     }
 
-    /// Creates an agenda commit on top of the `work` branch.
-    pub async fn create_agenda(&mut self, author: PublicKey) -> Result<CommitHash, Error> {
+    /// Reads the commits of the `work` branch after the last finalized block and verifies them.
+    ///
+    /// Returns the last finalized block header and the commits in order.
+    async fn read_work_commits(&self) -> Result<(BlockHeader, Vec<(Commit, CommitHash)>), Error> {
         let last_header = self.get_last_finalized_block_header().await?;
         let work_commit = self.raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
         let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
 
         // Check if the `work` branch is rebased on top of the `finalized` branch.
-        if self
+        if !self
             .raw
-            .find_merge_base(last_header_commit, work_commit)
+            .is_ancestor(last_header_commit, work_commit)
             .await?
-            != last_header_commit
         {
             return Err(anyhow!(
                 "branch {} should be rebased on {}",
@@ -173,78 +2160,338 @@ impl<T: RawRepository> DistributedRepository<T> {
         }
 
         // Fetch and convert commits
-        let commits = self.raw.list_ancestors(work_commit, Some(256)).await?;
+        // The ancestors don't include the `work` commit itself.
+        let commits = [
+            vec![work_commit],
+            self.raw.list_ancestors(work_commit, Some(256)).await?,
+        ]
+        .concat();
         let position = commits
             .iter()
             .position(|c| *c == last_header_commit)
             .expect("TODO: handle the case where it exceeds the limit.");
 
         // commits starting from the very next one to the last finalized block.
-        let commits = stream::iter(commits.iter().take(position).rev().cloned().map(|c| {
-            let raw = &self.raw;
-            async move { raw.read_semantic_commit(c).await.map(|x| (x, c)) }
-        }))
+        let commits = stream::iter(
+            commits
+                .iter()
+                .take(position)
+                .rev()
+                .cloned()
+                .map(|c| async move { self.get_commit(&c).await.map(|x| (x, c)) }),
+        )
         .buffered(256)
         .collect::<Vec<_>>()
         .await;
         let commits = commits.into_iter().collect::<Result<Vec<_>, _>>()?;
-        let commits = commits
-            .into_iter()
-            .map(|(commit, hash)| {
-                from_semantic_commit(commit, &last_header)
-                    .map_err(|e| (e, hash))
-                    .map(|x| (x, hash))
-            })
-            .collect::<Result<Vec<_>, _>>()
-            .map_err(|(error, hash)| anyhow!("failed to convert the commit {}: {}", hash, error))?;
 
         // Check the validity of the commit sequence
         let reserved_state = self.get_reserved_state().await?;
         let mut verifier = CommitSequenceVerifier::new(last_header.clone(), reserved_state)
             .map_err(|e| anyhow!("verification error on commit {}: {}", last_header_commit, e))?;
         for (commit, hash) in commits.iter() {
-            verifier
-                .apply_commit(commit)
-                .map_err(|e| anyhow!("verification error on commit {}: {}", hash, e))?;
+            self.apply_commit_to_verifier(&mut verifier, hash, commit)
+                .await?;
         }
 
-        // Check whether the commit sequence is in the transaction phase.
-        let mut transactions = Vec::new();
+        Ok((last_header, commits))
+    }
 
-        for (commit, _) in commits {
-            if let Commit::Transaction(t) = commit {
-                transactions.push(t.clone());
-            } else {
-                return Err(anyhow!(
-                    "branch {} is not in the transaction phase",
-                    WORK_BRANCH_NAME
-                ));
+    /// Returns the finalization proof of the last finalized block,
+    /// which is either in the `fp` branch or the genesis proof.
+    async fn get_last_finalization_proof(&self) -> Result<FinalizationProof, Error> {
+        let last_header = self.get_last_finalized_block_header().await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        if self
+            .raw
+            .list_branches()
+            .await?
+            .contains(&FP_BRANCH_NAME.to_owned())
+        {
+            let fp_commit = self.raw.locate_branch(FP_BRANCH_NAME.into()).await?;
+            if self.raw.list_ancestors(fp_commit, Some(1)).await? == vec![last_header_commit] {
+                let semantic_commit = self.raw.read_semantic_commit(fp_commit).await?;
+                return serde_json::from_str(&semantic_commit.body)
+                    .map_err(|e| anyhow!("failed to parse commit {}: {}", fp_commit, e));
+            }
+        }
+        let reserved_state = self.get_reserved_state().await?;
+        if reserved_state.genesis_info.header == last_header {
+            return Ok(reserved_state.genesis_info.genesis_proof);
+        }
+        Err(anyhow!(
+            "no finalization proof for the block {}",
+            last_header_commit
+        ))
+    }
+
+    /// Counts the transaction commits on the `work` branch after the last finalized block.
+    ///
+    /// It fails if `work` is not rebased on `finalized`.
+    pub async fn pending_transaction_count(&self) -> Result<usize, Error> {
+        let work_commit = self.raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
+        let last_header_commit = self.raw.locate_branch(FINALIZED_BRANCH_NAME.into()).await?;
+        if !self
+            .raw
+            .is_ancestor(last_header_commit, work_commit)
+            .await?
+        {
+            return Err(anyhow!(
+                "branch {} should be rebased on {}",
+                WORK_BRANCH_NAME,
+                FINALIZED_BRANCH_NAME
+            ));
+        }
+        if work_commit == last_header_commit {
+            return Ok(0);
+        }
+        let mut count = 0;
+        for commit_hash in self
+            .raw
+            .descendants_until(last_header_commit, work_commit)
+            .await?
+        {
+            if let Commit::Transaction(_) = self.get_commit(&commit_hash).await? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Creates an agenda commit on top of the `work` branch.
+    ///
+    /// Returns the commit hash and the hash of the agenda, which are the ones `get_agendas()` returns.
+    ///
+    /// Like the other methods creating a commit on `work`, it takes `&mut self`, so concurrent
+    /// callers must share the repository behind a lock and are thus mutually exclusive;
+    /// the later one sees the agenda of the earlier one and fails with a `PhaseError`.
+    pub async fn create_agenda(
+        &mut self,
+        author: PublicKey,
+    ) -> Result<(CommitHash, Hash256), Error> {
+        let (last_header, commits) = self.read_work_commits().await?;
+
+        // Check whether the commit sequence is in the transaction phase.
+        let phase = Phase::from_last_commit(commits.last().map(|(commit, _)| commit));
+        if !matches!(phase, Phase::Block | Phase::Transaction) {
+            return Err(PhaseError {
+                expected: Phase::Transaction,
+                actual: phase,
             }
+            .into());
         }
+        let transactions = commits
+            .into_iter()
+            .filter_map(|(commit, _)| match commit {
+                Commit::Transaction(transaction) => Some(transaction),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
 
-        let agenda_commit = Commit::Agenda(Agenda {
+        // The agenda hash is calculated with the height of the last finalized block,
+        // as the verifier does.
+        let agenda = Agenda {
             author,
             timestamp: get_timestamp(),
-            hash: Agenda::calculate_hash(last_header.height + 1, &transactions),
-        });
-        let semantic_commit = to_semantic_commit(&agenda_commit, &last_header);
+            hash: Agenda::calculate_hash(last_header.height, &transactions),
+        };
+        let agenda_hash = agenda.to_hash256();
+        let semantic_commit = to_semantic_commit(&Commit::Agenda(agenda), &last_header);
 
-        self.raw.checkout_clean().await?;
-        self.raw.checkout(WORK_BRANCH_NAME.into()).await?;
-        let result = self.raw.create_semantic_commit(semantic_commit).await?;
-        Ok(result)
+        // An agenda commit is empty, so it doesn't need the working tree.
+        let result = self
+            .raw
+            .create_commit_on_branch(
+                WORK_BRANCH_NAME.into(),
+                format!("{}\n\n{}", semantic_commit.title, semantic_commit.body),
+                None,
+            )
+            .await?;
+        self.emit(RepositoryEvent::AgendaCreated {
+            commit_hash: result,
+            agenda_hash,
+        });
+        Ok((result, agenda_hash))
     }
 
     /// Creates a block commit on top of the `work` branch.
-    pub async fn create_block(&mut self, _author: PublicKey) -> Result<CommitHash, Error> {
-        unimplemented!()
+    ///
+    /// The author must be the proposer of the next height, which is the leader of the first
+    /// consensus round (the first in `consensus_leader_order` of the reserved state).
+    /// A block of a later round is rejected, since the repository doesn't know the round.
+    /// The agenda proof on `work` must have enough voting power.
+    ///
+    /// Returns the new commit with its header, which is what the validators sign to finalize it;
+    /// its hash (`to_hash256()`) is the one that `get_blocks()` reports.
+    pub async fn create_block(
+        &mut self,
+        author: PublicKey,
+    ) -> Result<(CommitHash, BlockHeader), Error> {
+        let (last_header, commits) = self.read_work_commits().await?;
+        let phase = Phase::from_last_commit(commits.last().map(|(commit, _)| commit));
+        if !matches!(phase, Phase::AgendaProof | Phase::ExtraAgendaTransaction) {
+            return Err(PhaseError {
+                expected: Phase::AgendaProof,
+                actual: phase,
+            }
+            .into());
+        }
+        let reserved_state = self.get_reserved_state().await?;
+        let proposer = reserved_state
+            .consensus_leader_order
+            .first()
+            .and_then(|leader| reserved_state.members.get(*leader))
+            .map(|member| &member.public_key)
+            .ok_or_else(|| anyhow!("invalid reserved state: there is no consensus leader"))?;
+        if author != *proposer {
+            return Err(anyhow!(
+                "{} is not the proposer of height {}; {} is",
+                author,
+                last_header.height + 1,
+                proposer
+            ));
+        }
+        // The verifier checks the signatures of the agenda proof, but not their voting power.
+        let agenda_proof_commit = commits
+            .iter()
+            .rev()
+            .find(|(commit, _)| matches!(commit, Commit::AgendaProof(_)))
+            .map(|(_, commit_hash)| *commit_hash)
+            .expect("the phase must be after an agenda proof");
+        self.verify_agenda_proof(&agenda_proof_commit)
+            .await
+            .map_err(|e| anyhow!("the agenda is not approved: {}", e))?;
+
+        let commits = commits
+            .into_iter()
+            .map(|(commit, _)| commit)
+            .collect::<Vec<_>>();
+        let block_header = BlockHeader {
+            author,
+            prev_block_finalization_proof: self.get_last_finalization_proof().await?,
+            previous_hash: last_header.to_hash256(),
+            height: last_header.height + 1,
+            timestamp: get_timestamp(),
+            commit_merkle_root: BlockHeader::calculate_commit_merkle_root(&commits),
+            // TODO: calculate the repository merkle root.
+            repository_merkle_root: Hash256::zero(),
+            validator_set: reserved_state
+                .create_validator_set()
+                .map_err(|e| anyhow!("invalid reserved state: {}", e))?,
+            version: reserved_state.version,
+        };
+        let semantic_commit =
+            to_semantic_commit(&Commit::Block(block_header.clone()), &last_header);
+
+        let work_commit = self.raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
+        let result = self
+            .raw
+            .create_commit_on_branch(
+                WORK_BRANCH_NAME.into(),
+                format!("{}\n\n{}", semantic_commit.title, semantic_commit.body),
+                None,
+            )
+            .await?;
+        self.check_block_read_back(&result, &block_header, &work_commit)
+            .await?;
+        self.emit(RepositoryEvent::BlockCreated {
+            commit_hash: result,
+            header: block_header.clone(),
+        });
+        Ok((result, block_header))
     }
 
-    /// Creates an agenda commit on top of the `work` branch.
+    /// Checks that the block commit reads back as the header that it was created from,
+    /// which is what the validators sign and what `get_blocks()` reports.
+    ///
+    /// Otherwise the format has drifted, so it moves `work` back to `prev_work_commit` and fails.
+    async fn check_block_read_back(
+        &mut self,
+        block_commit: &CommitHash,
+        block_header: &BlockHeader,
+        prev_work_commit: &CommitHash,
+    ) -> Result<(), Error> {
+        let block_hash = block_header.to_hash256();
+        let read_hash = match self.get_commit(block_commit).await {
+            Ok(Commit::Block(header)) => Some(header.to_hash256()),
+            _ => None,
+        };
+        if read_hash == Some(block_hash) {
+            return Ok(());
+        }
+        self.raw
+            .move_branch(
+                WORK_BRANCH_NAME.into(),
+                *prev_work_commit,
+                false,
+                format!("create_block: revert {:#}", block_commit),
+            )
+            .await?;
+        Err(anyhow!(
+            "block commit {} doesn't read back as the created header {}",
+            block_commit,
+            block_hash
+        ))
+    }
+
+    /// Creates an extra-agenda transaction commit on top of the `work` branch.
+    ///
+    /// Extra-agenda transactions go between the agenda proof and the block,
+    /// so it fails with a `PhaseError` unless `work` is in the `AgendaProof` or
+    /// `ExtraAgendaTransaction` phase (e.g., while the agenda is not approved yet).
+    /// It also fails if the reserved state on `work` can't apply the transaction.
+    ///
+    /// The commit carries the reserved state with the transaction applied.
     pub async fn create_extra_agenda_transaction(
         &mut self,
-        _transaction: &ExtraAgendaTransaction,
+        transaction: &ExtraAgendaTransaction,
     ) -> Result<CommitHash, Error> {
-        unimplemented!()
+        let (last_header, commits) = self.read_work_commits().await?;
+        let phase = Phase::from_last_commit(commits.last().map(|(commit, _)| commit));
+        if !matches!(phase, Phase::AgendaProof | Phase::ExtraAgendaTransaction) {
+            return Err(PhaseError {
+                expected: Phase::AgendaProof,
+                actual: phase,
+            }
+            .into());
+        }
+
+        // The earlier transactions on `work` have already changed the reserved state.
+        let work_commit = self.raw.locate_branch(WORK_BRANCH_NAME.into()).await?;
+        let mut reserved_state = self.get_reserved_state_at(&work_commit).await?;
+        let reserved_state = match transaction {
+            ExtraAgendaTransaction::Delegate(tx) => reserved_state.apply_delegate(tx),
+            ExtraAgendaTransaction::Undelegate(tx) => reserved_state.apply_undelegate(tx),
+            ExtraAgendaTransaction::Report(_) => {
+                return Err(anyhow!("report transactions are not supported yet"))
+            }
+        }
+        .map_err(|e| anyhow!("invalid extra-agenda transaction: {}", e))?;
+
+        let semantic_commit = to_semantic_commit(
+            &Commit::ExtraAgendaTransaction(transaction.clone()),
+            &last_header,
+        );
+        let files = raw::reserved_state::to_files(&reserved_state)
+            .into_iter()
+            .map(|(path, content)| (path, content.into_bytes()))
+            .collect();
+        let result = self
+            .raw
+            .create_commit_with_parents(
+                format!("{}\n\n{}", semantic_commit.title, semantic_commit.body),
+                files,
+                vec![work_commit],
+            )
+            .await?;
+        self.raw
+            .move_branch(
+                WORK_BRANCH_NAME.into(),
+                result,
+                true,
+                format!("create_extra_agenda_transaction: {}", semantic_commit.title),
+            )
+            .await?;
+        Ok(result)
     }
 }
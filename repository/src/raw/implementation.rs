@@ -1,4 +1,5 @@
 use super::*;
+use std::path::Path;
 
 type Error = super::Error;
 
@@ -12,6 +13,85 @@ pub(crate) struct RawRepositoryImplInner {
     repo: Repository,
 }
 
+/// The lock file that libgit2 and git create while writing the index.
+const INDEX_LOCK_FILE_NAME: &str = "index.lock";
+
+/// The delay before the first retry of a fetch, which grows linearly with the retries.
+const FETCH_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Whether the error may go away by retrying, such as a connection reset or a timeout.
+///
+/// A denied authentication or a missing repository is permanent.
+fn is_transient_error(error: &git2::Error) -> bool {
+    !matches!(
+        error.code(),
+        git2::ErrorCode::Auth | git2::ErrorCode::Certificate | git2::ErrorCode::NotFound
+    ) && matches!(error.class(), git2::ErrorClass::Net | git2::ErrorClass::Os)
+}
+
+/// The identity of the commits made in a repository that has no `user.name` or `user.email`.
+const DEFAULT_SIGNATURE_NAME: &str = "simperby";
+const DEFAULT_SIGNATURE_EMAIL: &str = "simperby@localhost";
+
+/// Returns the commit signature from the repository (or global) config,
+/// falling back to the default identity if it is not configured.
+fn signature_from_config(repo: &Repository) -> Result<git2::Signature<'static>, Error> {
+    match repo.signature() {
+        Ok(signature) => Ok(signature),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            git2::Signature::now(DEFAULT_SIGNATURE_NAME, DEFAULT_SIGNATURE_EMAIL).map_err(|e| {
+                Error::Unknown(format!(
+                    "failed to create the default commit signature: {}",
+                    e
+                ))
+            })
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The minimum length of a prefix that `resolve_short_hash()` accepts, the same as Git's.
+const MIN_SHORT_HASH_LEN: usize = 4;
+
+/// Follows the only child of each commit in the child index, starting from `commit_hash`.
+///
+/// It fails where a commit has multiple children.
+pub(crate) fn list_descendants_in(
+    child_index: &HashMap<CommitHash, Vec<CommitHash>>,
+    commit_hash: CommitHash,
+    max: Option<usize>,
+) -> Result<Vec<CommitHash>, Error> {
+    let mut descendants = Vec::new();
+    let mut commit_hash = commit_hash;
+    while max != Some(descendants.len()) {
+        commit_hash = match child_index.get(&commit_hash).map(Vec::as_slice) {
+            None | Some([]) => break,
+            Some([child]) => *child,
+            Some(_) => {
+                return Err(Error::InvalidRepository(format!(
+                    "commit {} has multiple children",
+                    commit_hash
+                )))
+            }
+        };
+        descendants.push(commit_hash);
+    }
+    Ok(descendants)
+}
+
+/// Converts the bytes of a Git object id into a `CommitHash`.
+///
+/// Only SHA-1 repositories, whose object ids are 20 bytes, are supported.
+pub(crate) fn commit_hash_from_bytes(bytes: &[u8]) -> Result<CommitHash, Error> {
+    let hash = <[u8; 20]>::try_from(bytes).map_err(|_| {
+        Error::InvalidRepository(format!(
+            "expected 20-byte (SHA-1) oid, got {} bytes; SHA-256 repos are unsupported",
+            bytes.len()
+        ))
+    })?;
+    Ok(CommitHash { hash })
+}
+
 /// TODO: Error handling and its messages
 impl RawRepositoryImplInner {
     pub(crate) fn init(
@@ -37,7 +117,7 @@ impl RawRepositoryImplInner {
                     config.set_str("user.email", "email")?; // TODO: user.email value
                     let mut index = repo.index()?;
                     let id = index.write_tree()?;
-                    let sig = repo.signature()?;
+                    let sig = signature_from_config(&repo)?;
                     let tree = repo.find_tree(id)?;
 
                     let _oid =
@@ -49,6 +129,20 @@ impl RawRepositoryImplInner {
         }
     }
 
+    pub(crate) fn clone_from(url: &str, directory: &str) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let repo = Repository::clone(url, directory)?;
+        {
+            let mut config = repo.config()?;
+            config.set_str("user.name", "name")?; // TODO: user.name value
+            config.set_str("user.email", "email")?; // TODO: user.email value
+        }
+
+        Ok(Self { repo })
+    }
+
     pub(crate) fn open(directory: &str) -> Result<Self, Error>
     where
         Self: Sized,
@@ -58,6 +152,58 @@ impl RawRepositoryImplInner {
         Ok(Self { repo })
     }
 
+    pub(crate) fn open_discover(directory: &str) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let repo = Repository::discover(directory)?;
+
+        Ok(Self { repo })
+    }
+
+    pub(crate) fn check_index_lock(&self) -> Result<(), Error> {
+        let path = self.repo.path().join(INDEX_LOCK_FILE_NAME);
+        if path.exists() {
+            return Err(Error::InvalidRepository(format!(
+                "the index is locked by {}; if no other process is using the repository, \
+                 remove it with force_unlock()",
+                path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn force_unlock(&self) -> Result<bool, Error> {
+        let lock_path = self.repo.path().join(crate::LOCK_FILE_NAME);
+        // A leftover lock file doesn't matter, only whether someone holds the lock on it.
+        if let Ok(lock_file) = std::fs::File::open(&lock_path) {
+            if lock_file.try_lock_exclusive().is_err() {
+                return Err(Error::InvalidRepository(format!(
+                    "the repository is in use: {} is locked",
+                    lock_path.display()
+                )));
+            }
+        }
+        let path = self.repo.path().join(INDEX_LOCK_FILE_NAME);
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(Error::Unknown(format!(
+                "failed to remove {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    pub(crate) fn get_git_directory(&self) -> Result<String, Error> {
+        self.repo
+            .path()
+            .to_str()
+            .map(|path| path.to_owned())
+            .ok_or_else(|| Error::Unknown("non-UTF-8 path".to_string()))
+    }
+
     pub(crate) fn list_branches(&self) -> Result<Vec<Branch>, Error> {
         let branches = self.repo.branches(Option::Some(BranchType::Local))?;
 
@@ -67,53 +213,114 @@ impl RawRepositoryImplInner {
                     .0
                     .name()?
                     .map(|name| name.to_string())
-                    .ok_or_else(|| Error::Unknown("err".to_string()))?;
+                    .ok_or_else(|| Error::Unknown("branch name is not valid UTF-8".to_string()))?;
 
                 Ok(branch_name)
             })
             .collect::<Result<Vec<Branch>, Error>>()
     }
 
+    pub(crate) fn list_branches_with_tips(&self) -> Result<Vec<(Branch, CommitHash)>, Error> {
+        let mut branches = Vec::new();
+        for branch in self.repo.branches(Some(BranchType::Local))? {
+            let (branch, _) = branch?;
+            // A symbolic branch has no target of its own.
+            let oid = match branch.get().target() {
+                Some(oid) => oid,
+                None => continue,
+            };
+            let branch_name = branch
+                .name()?
+                .ok_or_else(|| Error::Unknown("branch name is not valid UTF-8".to_string()))?
+                .to_string();
+            branches.push((branch_name, commit_hash_from_bytes(oid.as_bytes())?));
+        }
+        Ok(branches)
+    }
+
     pub(crate) fn create_branch(
         &self,
         branch_name: Branch,
         commit_hash: CommitHash,
-    ) -> Result<(), Error> {
+    ) -> Result<CommitHash, Error> {
+        if !git2::Reference::is_valid_name(&format!("refs/heads/{}", branch_name)) {
+            return Err(Error::InvalidRepository(format!(
+                "invalid branch name '{}'",
+                branch_name
+            )));
+        }
         let oid = Oid::from_bytes(&commit_hash.hash)?;
         let commit = self.repo.find_commit(oid)?;
 
         // TODO: Test if force true and verify new branch is created
-        self.repo.branch(branch_name.as_str(), &commit, false)?;
-
-        Ok(())
+        let branch = self.repo.branch(branch_name.as_str(), &commit, false)?;
+        let oid = branch
+            .get()
+            .target()
+            .ok_or_else(|| Error::NoTarget(format!("branch '{}'", branch_name)))?;
+        commit_hash_from_bytes(oid.as_bytes())
     }
 
     pub(crate) fn locate_branch(&self, branch: Branch) -> Result<CommitHash, Error> {
-        let branch = self.repo.find_branch(&branch, BranchType::Local)?;
-        let oid = branch
+        let git2_branch = match self.repo.find_branch(&branch, BranchType::Local) {
+            Ok(git2_branch) => git2_branch,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                return Err(Error::InvalidRepository(format!(
+                    "branch '{}' not found",
+                    branch
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let oid = git2_branch
             .get()
             .target()
-            .ok_or_else(|| Error::Unknown("err".to_string()))?;
-        let hash =
-            <[u8; 20]>::try_from(oid.as_bytes()).map_err(|_| Error::Unknown("err".to_string()))?;
-
-        Ok(CommitHash { hash })
+            .ok_or_else(|| Error::NoTarget(format!("branch '{}'", branch)))?;
+        commit_hash_from_bytes(oid.as_bytes())
     }
 
-    pub(crate) fn get_branches(&self, _commit_hash: CommitHash) -> Result<Vec<Branch>, Error> {
-        unimplemented!()
+    pub(crate) fn get_branches(
+        &self,
+        commit_hash: CommitHash,
+        mode: BranchMatch,
+    ) -> Result<Vec<Branch>, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let mut branches = Vec::new();
+        for branch in self.list_branches()? {
+            let tip = Oid::from_bytes(&self.locate_branch(branch.clone())?.hash)?;
+            let matched = match mode {
+                BranchMatch::TipOnly => tip == oid,
+                BranchMatch::Contains => tip == oid || self.repo.graph_descendant_of(tip, oid)?,
+            };
+            if matched {
+                branches.push(branch);
+            }
+        }
+        Ok(branches)
     }
 
     pub(crate) fn move_branch(
         &mut self,
         branch: Branch,
         commit_hash: CommitHash,
+        fast_forward_only: bool,
+        reason: String,
     ) -> Result<(), Error> {
         let mut git2_branch = self.repo.find_branch(&branch, BranchType::Local)?;
         let oid = Oid::from_bytes(&commit_hash.hash)?;
-        let reflog_msg = ""; // TODO: reflog_msg
+        if fast_forward_only {
+            let current_oid = git2_branch.get().target().ok_or_else(|| {
+                Error::NoTarget(format!("branch {} is a symbolic reference", branch))
+            })?;
+            if current_oid != oid && !self.repo.graph_descendant_of(oid, current_oid)? {
+                return Err(Error::InvalidRepository(format!(
+                    "cannot move branch {} from {} to {}: not a fast-forward",
+                    branch, current_oid, oid
+                )));
+            }
+        }
         let reference = git2_branch.get_mut();
-        let _set_branch = git2::Reference::set_target(reference, oid, reflog_msg)?;
+        let _set_branch = git2::Reference::set_target(reference, oid, &reason)?;
 
         Ok(())
     }
@@ -125,7 +332,7 @@ impl RawRepositoryImplInner {
             .repo
             .head()?
             .shorthand()
-            .ok_or_else(|| Error::Unknown("err".to_string()))?
+            .ok_or_else(|| Error::Unknown("HEAD name is not valid UTF-8".to_string()))?
             .to_string();
 
         if current_branch == branch {
@@ -137,6 +344,36 @@ impl RawRepositoryImplInner {
         }
     }
 
+    pub(crate) fn read_reflog(
+        &self,
+        branch: Branch,
+        max: Option<usize>,
+    ) -> Result<Vec<ReflogEntry>, Error> {
+        // Fails if there is no such branch, even if a stale reflog remains.
+        let git2_branch = self.repo.find_branch(&branch, BranchType::Local)?;
+        let reference_name = git2_branch
+            .get()
+            .name()
+            .ok_or_else(|| Error::Unknown("reference name is not valid UTF-8".to_string()))?;
+        let reflog = self.repo.reflog(reference_name)?;
+        reflog
+            .iter()
+            .take(max.unwrap_or(usize::MAX))
+            .map(|entry| {
+                let old_commit = if entry.id_old().is_zero() {
+                    None
+                } else {
+                    Some(commit_hash_from_bytes(entry.id_old().as_bytes())?)
+                };
+                Ok(ReflogEntry {
+                    old_commit,
+                    new_commit: commit_hash_from_bytes(entry.id_new().as_bytes())?,
+                    message: entry.message().unwrap_or_default().to_owned(),
+                })
+            })
+            .collect()
+    }
+
     pub(crate) fn list_tags(&self) -> Result<Vec<Tag>, Error> {
         let tag_array = self.repo.tag_names(None)?;
 
@@ -144,7 +381,7 @@ impl RawRepositoryImplInner {
             .iter()
             .map(|tag| {
                 let tag_name = tag
-                    .ok_or_else(|| Error::Unknown("err".to_string()))?
+                    .ok_or_else(|| Error::Unknown("tag name is not valid UTF-8".to_string()))?
                     .to_string();
 
                 Ok(tag_name)
@@ -154,9 +391,39 @@ impl RawRepositoryImplInner {
         tag_list
     }
 
-    pub(crate) fn create_tag(&mut self, tag: Tag, commit_hash: CommitHash) -> Result<(), Error> {
+    pub(crate) fn list_tags_with_targets(&self) -> Result<Vec<(Tag, CommitHash)>, Error> {
+        self.list_tags()?
+            .into_iter()
+            .map(|tag| {
+                let commit_hash = self.locate_tag(tag.clone())?;
+                Ok((tag, commit_hash))
+            })
+            .collect::<Result<Vec<(Tag, CommitHash)>, Error>>()
+    }
+
+    pub(crate) fn create_tag(
+        &mut self,
+        tag: Tag,
+        commit_hash: CommitHash,
+        expected_kind: Option<ObjectKind>,
+    ) -> Result<(), Error> {
         let oid = Oid::from_bytes(&commit_hash.hash)?;
-        let object = self.repo.find_object(oid, Some(ObjectType::Commit))?;
+        let object = self.repo.find_object(oid, None)?;
+        if let Some(expected_kind) = expected_kind {
+            let kind = match object.kind() {
+                Some(ObjectType::Commit) => Some(ObjectKind::Commit),
+                Some(ObjectType::Tree) => Some(ObjectKind::Tree),
+                Some(ObjectType::Blob) => Some(ObjectKind::Blob),
+                Some(ObjectType::Tag) => Some(ObjectKind::Tag),
+                _ => None,
+            };
+            if kind != Some(expected_kind) {
+                return Err(Error::InvalidRepository(format!(
+                    "cannot create tag {} on {}: expected {:?} but found {:?}",
+                    tag, oid, expected_kind, kind
+                )));
+            }
+        }
         self.repo.tag_lightweight(tag.as_str(), &object, true)?;
 
         Ok(())
@@ -168,10 +435,25 @@ impl RawRepositoryImplInner {
             .find_reference(&("refs/tags/".to_owned() + &tag))?;
         let object = reference.peel(ObjectType::Commit)?;
         let oid = object.id();
-        let hash =
-            <[u8; 20]>::try_from(oid.as_bytes()).map_err(|_| Error::Unknown("err".to_string()))?;
-        let commit_hash = CommitHash { hash };
-        Ok(commit_hash)
+        commit_hash_from_bytes(oid.as_bytes())
+    }
+
+    pub(crate) fn read_tag_message(&self, tag: Tag) -> Result<Option<String>, Error> {
+        let reference = self
+            .repo
+            .find_reference(&("refs/tags/".to_owned() + &tag))?;
+        let oid = reference
+            .target()
+            .ok_or_else(|| Error::Unknown(format!("tag {} is a symbolic reference", tag)))?;
+        // A lightweight tag points to the commit directly, not to a tag object.
+        let annotated_tag = match self.repo.find_object(oid, None)?.into_tag() {
+            Ok(annotated_tag) => annotated_tag,
+            Err(_) => return Ok(None),
+        };
+        let message = annotated_tag
+            .message()
+            .ok_or_else(|| Error::Unknown(format!("message of tag {} is not valid UTF-8", tag)))?;
+        Ok(Some(message.to_string()))
     }
 
     pub(crate) fn get_tag(&self, _commit_hash: CommitHash) -> Result<Vec<Tag>, Error> {
@@ -190,7 +472,7 @@ impl RawRepositoryImplInner {
         let mut index = self.repo.index().unwrap();
         let id = index.write_tree().unwrap();
 
-        let sig = self.repo.signature().unwrap();
+        let sig = signature_from_config(&self.repo)?;
         let tree = self.repo.find_tree(id).unwrap();
 
         let head = self.get_head()?;
@@ -206,37 +488,270 @@ impl RawRepositoryImplInner {
             &[&parent_commit],
         )?;
 
-        let hash =
-            <[u8; 20]>::try_from(oid.as_bytes()).map_err(|_| Error::Unknown("err".to_string()))?;
-
-        Ok(CommitHash { hash })
+        commit_hash_from_bytes(oid.as_bytes())
 
         // TODO: Change all to make commit using "diff"
     }
 
+    pub(crate) fn create_commit_with_parents(
+        &mut self,
+        commit_message: String,
+        files: Vec<(String, Vec<u8>)>,
+        parents: Vec<CommitHash>,
+    ) -> Result<CommitHash, Error> {
+        let parents = parents
+            .iter()
+            .map(|parent| Ok(self.repo.find_commit(Oid::from_bytes(&parent.hash)?)?))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // Build the tree with an in-memory index, not to touch the working tree.
+        let mut index = git2::Index::new()?;
+        if let Some(first_parent) = parents.first() {
+            index.read_tree(&first_parent.tree()?)?;
+        }
+        for (path, content) in files {
+            let entry = git2::IndexEntry {
+                ctime: git2::IndexTime::new(0, 0),
+                mtime: git2::IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: 0o100644,
+                uid: 0,
+                gid: 0,
+                file_size: content.len() as u32,
+                id: self.repo.blob(&content)?,
+                flags: 0,
+                flags_extended: 0,
+                path: path.into_bytes(),
+            };
+            index.add(&entry)?;
+        }
+        let tree_id = index.write_tree_to(&self.repo)?;
+        let tree = self.repo.find_tree(tree_id)?;
+
+        let sig = signature_from_config(&self.repo)?;
+        let oid = self.repo.commit(
+            None,
+            &sig,
+            &sig,
+            commit_message.as_str(),
+            &tree,
+            &parents.iter().collect::<Vec<_>>(),
+        )?;
+        commit_hash_from_bytes(oid.as_bytes())
+    }
+
+    pub(crate) fn create_commit_on_branch(
+        &mut self,
+        branch: Branch,
+        commit_message: String,
+        _diff: Option<String>,
+    ) -> Result<CommitHash, Error> {
+        let git2_branch = self.repo.find_branch(&branch, BranchType::Local)?;
+        let parent_commit = git2_branch.get().peel_to_commit()?;
+
+        // Build the tree with an in-memory index, not to touch the working tree.
+        // TODO: apply the diff on the index.
+        let mut index = git2::Index::new()?;
+        index.read_tree(&parent_commit.tree()?)?;
+        let tree_id = index.write_tree_to(&self.repo)?;
+        let tree = self.repo.find_tree(tree_id)?;
+
+        let sig = signature_from_config(&self.repo)?;
+        let oid = self.repo.commit(
+            Some(&format!("refs/heads/{}", branch)),
+            &sig,
+            &sig,
+            commit_message.as_str(),
+            &tree,
+            &[&parent_commit],
+        )?;
+        commit_hash_from_bytes(oid.as_bytes())
+    }
+
     pub(crate) fn create_semantic_commit(
         &mut self,
-        _commit: SemanticCommit,
+        commit: SemanticCommit,
+        branch: Branch,
     ) -> Result<CommitHash, Error> {
-        unimplemented!()
+        let head_reference = self.repo.head()?;
+        // `HEAD` may have been moved by another operation since the branch was checked out.
+        let current_branch = if head_reference.is_branch() {
+            head_reference.shorthand()
+        } else {
+            None
+        };
+        if current_branch != Some(branch.as_str()) {
+            return Err(Error::InvalidRepository(format!(
+                "cannot commit on branch {}: {}",
+                branch,
+                match current_branch {
+                    Some(current_branch) => format!("HEAD is on branch {}", current_branch),
+                    None => "HEAD is detached".to_owned(),
+                }
+            )));
+        }
+        let head = head_reference.peel_to_commit()?;
+        // The tree is always built from the tree of `HEAD`,
+        // so that the non-reserved files are carried forward unchanged.
+        let (tree, changed_paths) = match commit.diff {
+            Diff::None => (head.tree()?, Vec::new()),
+            Diff::Reserved(reserved_state, _) => {
+                let head_tree = head.tree()?;
+                let reserved_tree = match head_tree.get_name(reserved_state::RESERVED_DIRECTORY) {
+                    Some(entry) => Some(self.repo.find_tree(entry.id())?),
+                    None => None,
+                };
+                let mut reserved_builder = self.repo.treebuilder(reserved_tree.as_ref())?;
+                let mut changed_paths = Vec::new();
+                for (path, content) in reserved_state::to_files(&reserved_state) {
+                    let file_name = Path::new(&path)
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .ok_or_else(|| Error::Unknown(format!("invalid path: {}", path)))?;
+                    let blob = self.repo.blob(content.as_bytes())?;
+                    reserved_builder.insert(file_name, blob, 0o100644)?;
+                    changed_paths.push(path);
+                }
+                let reserved_tree_id = reserved_builder.write()?;
+
+                let mut builder = self.repo.treebuilder(Some(&head_tree))?;
+                builder.insert(
+                    reserved_state::RESERVED_DIRECTORY,
+                    reserved_tree_id,
+                    0o040000,
+                )?;
+                (self.repo.find_tree(builder.write()?)?, changed_paths)
+            }
+            // It has only the hash of the diff, not the content to apply.
+            Diff::General(_) => {
+                return Err(Error::InvalidRepository(
+                    "a semantic commit with a general diff can't be created".to_owned(),
+                ))
+            }
+        };
+        let sig = signature_from_config(&self.repo)?;
+        let oid = self.repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            &format!("{}\n\n{}", commit.title, commit.body),
+            &tree,
+            &[&head],
+        )?;
+
+        // Update the index and the working tree only for the reserved files that have changed.
+        if !changed_paths.is_empty() {
+            let mut checkout_builder = git2::build::CheckoutBuilder::new();
+            checkout_builder.force();
+            for path in &changed_paths {
+                checkout_builder.path(path);
+            }
+            self.repo.checkout_head(Some(&mut checkout_builder))?;
+        }
+        commit_hash_from_bytes(oid.as_bytes())
     }
 
     pub(crate) fn read_semantic_commit(
         &self,
-        _commit_hash: CommitHash,
+        commit_hash: CommitHash,
     ) -> Result<SemanticCommit, Error> {
-        unimplemented!()
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let commit = self.repo.find_commit(oid)?;
+        let title = commit
+            .summary()
+            .ok_or_else(|| Error::Unknown("commit message is not valid UTF-8".to_string()))?
+            .to_string();
+        let body = commit.body().unwrap_or_default().to_string();
+
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let reserved_tree_id = |tree: &git2::Tree| {
+            tree.get_name(reserved_state::RESERVED_DIRECTORY)
+                .map(|entry| entry.id())
+        };
+        let diff = if parent_tree.as_ref().map(|tree| tree.id()) == Some(tree.id()) {
+            Diff::None
+        } else if parent_tree.as_ref().and_then(reserved_tree_id) != reserved_tree_id(&tree) {
+            // The reserved area has changed, so the new reserved state is decoded.
+            Diff::Reserved(
+                Box::new(self.read_reserved_state_at_commit(commit_hash)?),
+                Hash256::hash(tree.id().as_bytes()),
+            )
+        } else {
+            Diff::General(Hash256::hash(tree.id().as_bytes()))
+        };
+
+        Ok(SemanticCommit { title, body, diff })
     }
 
     pub(crate) fn run_garbage_collection(&mut self) -> Result<(), Error> {
-        unimplemented!()
+        // libgit2 doesn't support garbage collection, so it runs the `git` command.
+        let output = std::process::Command::new("git")
+            .arg("--git-dir")
+            .arg(self.repo.path())
+            .args(["gc", "--prune=now", "--aggressive", "--quiet"])
+            .output()
+            .map_err(|e| Error::Unknown(format!("failed to run git gc: {}", e)))?;
+        if !output.status.success() {
+            return Err(Error::Unknown(format!(
+                "git gc failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
     }
 
-    pub(crate) fn checkout_clean(&mut self) -> Result<(), Error> {
-        unimplemented!()
+    pub(crate) fn checkout_clean(&mut self, paths: Option<Vec<String>>) -> Result<(), Error> {
+        let mut checkout_builder = git2::build::CheckoutBuilder::new();
+        checkout_builder.force().remove_untracked(true);
+        for path in paths.into_iter().flatten() {
+            checkout_builder.path(path);
+        }
+        self.repo.checkout_head(Some(&mut checkout_builder))?;
+
+        Ok(())
+    }
+
+    pub(crate) fn working_tree_status(&self) -> Result<Vec<(String, StatusKind)>, Error> {
+        let mut options = git2::StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false);
+        let statuses = self.repo.statuses(Some(&mut options))?;
+
+        let mut result = statuses
+            .iter()
+            .map(|entry| {
+                let path = entry
+                    .path()
+                    .ok_or_else(|| Error::Unknown("non-UTF-8 path".to_string()))?
+                    .to_string();
+                let status = entry.status();
+                let kind = if status.is_wt_new() {
+                    StatusKind::Untracked
+                } else if status.is_index_new() {
+                    StatusKind::Added
+                } else if status.is_wt_deleted() || status.is_index_deleted() {
+                    StatusKind::Deleted
+                } else {
+                    StatusKind::Modified
+                };
+                Ok((path, kind))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        result.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(result)
     }
 
     pub(crate) fn checkout(&mut self, branch: Branch) -> Result<(), Error> {
+        if self.repo.find_branch(&branch, BranchType::Local).is_err() {
+            self.create_tracking_branch(&branch)?;
+        }
         let obj = self
             .repo
             .revparse_single(&("refs/heads/".to_owned() + &branch))?;
@@ -246,6 +761,36 @@ impl RawRepositoryImplInner {
         Ok(())
     }
 
+    /// Creates a local branch from the remote tracking branch of the same name.
+    ///
+    /// It fails if there is no such remote tracking branch or if there are many.
+    fn create_tracking_branch(&mut self, branch: &str) -> Result<(), Error> {
+        let candidates = self
+            .list_remote_tracking_branches(None)?
+            .into_iter()
+            .filter(|(_, branch_name, _)| branch_name == branch)
+            .collect::<Vec<_>>();
+        let (remote_name, _, commit_hash) = match candidates.as_slice() {
+            [candidate] => candidate.clone(),
+            [] => {
+                return Err(Error::InvalidRepository(format!(
+                    "no such branch: {}",
+                    branch
+                )))
+            }
+            _ => {
+                return Err(Error::InvalidRepository(format!(
+                    "branch {} exists in multiple remotes",
+                    branch
+                )))
+            }
+        };
+        let commit = self.repo.find_commit(Oid::from_bytes(&commit_hash.hash)?)?;
+        let mut git2_branch = self.repo.branch(branch, &commit, false)?;
+        git2_branch.set_upstream(Some(&format!("{}/{}", remote_name, branch)))?;
+        Ok(())
+    }
+
     pub(crate) fn checkout_detach(&mut self, commit_hash: CommitHash) -> Result<(), Error> {
         let oid = Oid::from_bytes(&commit_hash.hash)?;
         self.repo.set_head_detached(oid)?;
@@ -254,49 +799,156 @@ impl RawRepositoryImplInner {
     }
 
     pub(crate) fn get_head(&self) -> Result<CommitHash, Error> {
-        let ref_head = self.repo.head()?;
+        let ref_head = self.repo.head().map_err(|e| match e.code() {
+            // HEAD points to a branch that doesn't exist yet.
+            git2::ErrorCode::UnbornBranch | git2::ErrorCode::NotFound => {
+                Error::InvalidRepository("repository has no commits yet".to_string())
+            }
+            _ => Error::from(e),
+        })?;
         let oid = ref_head
             .target()
-            .ok_or_else(|| Error::Unknown("err".to_string()))?;
-        let hash =
-            <[u8; 20]>::try_from(oid.as_bytes()).map_err(|_| Error::Unknown("err".to_string()))?;
-
-        Ok(CommitHash { hash })
+            .ok_or_else(|| Error::NoTarget("HEAD is a symbolic reference".to_string()))?;
+        commit_hash_from_bytes(oid.as_bytes())
     }
 
     pub(crate) fn get_initial_commit(&self) -> Result<CommitHash, Error> {
         // Check if the repository is empty
         // TODO: Replace this with repo.empty()
-        let _head = self
+        let head = self
             .repo
             .head()
             .map_err(|_| Error::InvalidRepository("repository is empty".to_string()))?;
 
-        let mut revwalk = self.repo.revwalk()?;
-        revwalk.push_head()?;
-        revwalk.set_sorting(git2::Sort::TIME)?;
-
-        let oids: Vec<Oid> = revwalk
-            .by_ref()
-            .collect::<Result<Vec<Oid>, git2::Error>>()?;
+        // Follow the first parents to the root, since the timestamps may not be monotonic.
+        let mut commit = head.peel_to_commit()?;
+        while let Some(parent) = commit.parents().next() {
+            commit = parent;
+        }
+        commit_hash_from_bytes(commit.id().as_bytes())
+    }
 
-        let initial_oid = if oids.len() == 1 { oids[0] } else { oids[1] };
-        let hash = <[u8; 20]>::try_from(initial_oid.as_bytes())
-            .map_err(|_| Error::Unknown("err".to_string()))?;
+    pub(crate) fn resolve_short_hash(&self, prefix: String) -> Result<CommitHash, Error> {
+        let prefix = prefix.to_lowercase();
+        if !(MIN_SHORT_HASH_LEN..=40).contains(&prefix.len())
+            || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(Error::Unknown(format!(
+                "invalid commit hash prefix (expected {} to 40 hex digits): {}",
+                MIN_SHORT_HASH_LEN, prefix
+            )));
+        }
 
-        Ok(CommitHash { hash })
+        let oid = self
+            .repo
+            .odb()?
+            .exists_prefix(Oid::from_str(&prefix)?, prefix.len())
+            .map_err(|e| match e.code() {
+                git2::ErrorCode::Ambiguous => Error::AmbiguousPrefix(prefix.clone()),
+                git2::ErrorCode::NotFound => {
+                    Error::InvalidRepository(format!("no object matches the prefix {}", prefix))
+                }
+                _ => Error::from(e),
+            })?;
+        let commit = self
+            .repo
+            .find_commit(oid)
+            .map_err(|_| Error::InvalidRepository(format!("{} is not a commit", oid)))?;
+        commit_hash_from_bytes(commit.id().as_bytes())
     }
 
     pub(crate) fn show_commit(&self, _commit_hash: CommitHash) -> Result<String, Error> {
         unimplemented!()
     }
 
+    pub(crate) fn diff(
+        &self,
+        from: CommitHash,
+        to: CommitHash,
+        detect_renames: bool,
+    ) -> Result<CommitDiff, Error> {
+        let from_tree = self
+            .repo
+            .find_commit(Oid::from_bytes(&from.hash)?)?
+            .tree()?;
+        let to_tree = self.repo.find_commit(Oid::from_bytes(&to.hash)?)?.tree()?;
+        let mut diff = self
+            .repo
+            .diff_tree_to_tree(Some(&from_tree), Some(&to_tree), None)?;
+        if detect_renames {
+            diff.find_similar(Some(
+                git2::DiffFindOptions::new().renames(true).copies(true),
+            ))?;
+        }
+
+        let get_path = |file: git2::DiffFile| {
+            file.path()
+                .and_then(|path| path.to_str())
+                .map(|path| path.to_owned())
+                .ok_or_else(|| Error::Unknown("non-UTF-8 path".to_string()))
+        };
+        // The deltas are already sorted by the path.
+        let files = diff
+            .deltas()
+            .map(|delta| {
+                let path = match delta.status() {
+                    git2::Delta::Deleted => get_path(delta.old_file())?,
+                    _ => get_path(delta.new_file())?,
+                };
+                match delta.status() {
+                    git2::Delta::Added => Ok(FileDiff::Added(path)),
+                    git2::Delta::Deleted => Ok(FileDiff::Deleted(path)),
+                    git2::Delta::Modified => Ok(FileDiff::Modified(path)),
+                    git2::Delta::Renamed => {
+                        Ok(FileDiff::Renamed(get_path(delta.old_file())?, path))
+                    }
+                    git2::Delta::Copied => Ok(FileDiff::Copied(get_path(delta.old_file())?, path)),
+                    status => Err(Error::Unknown(format!(
+                        "unsupported change {:?} on {}",
+                        status, path
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(CommitDiff { files })
+    }
+
+    pub(crate) fn get_commit_payload_size(&self, commit_hash: CommitHash) -> Result<usize, Error> {
+        let commit = self.repo.find_commit(Oid::from_bytes(&commit_hash.hash)?)?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let mut options = git2::DiffOptions::new();
+        options.pathspec(format!("{}/", reserved_state::RESERVED_DIRECTORY));
+        let diff = self.repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            Some(&mut options),
+        )?;
+
+        let mut size = commit.message_bytes().len();
+        for delta in diff.deltas() {
+            if let git2::Delta::Added | git2::Delta::Modified = delta.status() {
+                size += self.repo.find_blob(delta.new_file().id())?.size();
+            }
+        }
+        Ok(size)
+    }
+
     pub(crate) fn list_ancestors(
         &self,
         commit_hash: CommitHash,
         max: Option<usize>,
     ) -> Result<Vec<CommitHash>, Error> {
         let oid = Oid::from_bytes(&commit_hash.hash)?;
+        // The given commit itself must not be a merge commit either.
+        if self.repo.find_commit(oid)?.parent_count() > 1 {
+            return Err(Error::InvalidRepository(format!(
+                "There exists a merge commit, {}",
+                oid
+            )));
+        }
         let mut revwalk = self.repo.revwalk()?;
         revwalk.push(oid)?;
         revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
@@ -321,7 +973,7 @@ impl RawRepositoryImplInner {
                 }
                 // TODO: Should check current commit's parent == oids[next]
             }
-            oids[0..num_max].to_vec()
+            oids[0..num_max.min(oids.len())].to_vec()
         } else {
             // If max is None
             let mut i = 0;
@@ -348,28 +1000,107 @@ impl RawRepositoryImplInner {
 
         let ancestors = oids_ancestor
             .iter()
-            .map(|&oid| {
-                let hash: [u8; 20] = oid
-                    .as_bytes()
-                    .try_into()
-                    .map_err(|_| Error::Unknown("err".to_string()))?;
-                Ok(CommitHash { hash })
-            })
+            .map(|&oid| commit_hash_from_bytes(oid.as_bytes()))
             .collect::<Result<Vec<CommitHash>, Error>>();
 
         ancestors
     }
 
-    pub(crate) fn list_descendants(
+    pub(crate) fn list_first_parent_ancestors(
         &self,
-        _commit_hash: CommitHash,
-        _max: Option<usize>,
+        commit_hash: CommitHash,
+        max: Option<usize>,
     ) -> Result<Vec<CommitHash>, Error> {
-        unimplemented!()
+        let mut commit = self.repo.find_commit(Oid::from_bytes(&commit_hash.hash)?)?;
+        let mut ancestors = Vec::new();
+        while max != Some(ancestors.len()) {
+            commit = match commit.parents().next() {
+                Some(parent) => parent,
+                None => break,
+            };
+            ancestors.push(commit_hash_from_bytes(commit.id().as_bytes())?);
+        }
+        Ok(ancestors)
     }
 
-    pub(crate) fn list_children(&self, _commit_hash: CommitHash) -> Result<Vec<CommitHash>, Error> {
-        unimplemented!()
+    pub(crate) fn descendants_until(
+        &self,
+        from: CommitHash,
+        to: CommitHash,
+    ) -> Result<Vec<CommitHash>, Error> {
+        let from_oid = Oid::from_bytes(&from.hash)?;
+        let to_oid = Oid::from_bytes(&to.hash)?;
+        if from_oid == to_oid {
+            return Ok(vec![]);
+        }
+        if !self.repo.graph_descendant_of(to_oid, from_oid)? {
+            return Err(Error::InvalidRepository(format!(
+                "{} is not a descendant of {}",
+                to_oid, from_oid
+            )));
+        }
+
+        // Walk back from `to` following the only parent of each commit.
+        let mut oids = Vec::new();
+        let mut oid = to_oid;
+        while oid != from_oid {
+            let commit = self.repo.find_commit(oid)?;
+            if commit.parent_count() != 1 {
+                return Err(Error::InvalidRepository(format!(
+                    "the path from {} to {} diverges at {}",
+                    from_oid, to_oid, oid
+                )));
+            }
+            oids.push(oid);
+            oid = commit.parent_id(0)?;
+        }
+        oids.reverse();
+
+        oids.into_iter()
+            .map(|oid| commit_hash_from_bytes(oid.as_bytes()))
+            .collect()
+    }
+
+    /// Returns the targets of all the references, sorted by their names.
+    ///
+    /// The child index depends only on them, so they tell whether it is still valid.
+    pub(crate) fn list_reference_tips(&self) -> Result<Vec<(String, Oid)>, Error> {
+        let mut tips = Vec::new();
+        // Only the local branches are walked for the child index.
+        for reference in self.repo.references_glob("refs/heads/*")? {
+            let reference = reference?;
+            let name = reference
+                .name()
+                .ok_or_else(|| Error::Unknown("reference name is not valid UTF-8".to_string()))?
+                .to_string();
+            // Symbolic references move along with their targets, which are listed too.
+            if let Some(oid) = reference.target() {
+                tips.push((name, oid));
+            }
+        }
+        tips.sort();
+        Ok(tips)
+    }
+
+    pub(crate) fn build_child_index(&self) -> Result<HashMap<CommitHash, Vec<CommitHash>>, Error> {
+        // A single walk over the history of every local branch, instead of one per commit.
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push_glob("refs/heads/*")?;
+        let mut child_index: HashMap<CommitHash, Vec<CommitHash>> = HashMap::new();
+        for oid in revwalk {
+            let oid = oid?;
+            let commit_hash = commit_hash_from_bytes(oid.as_bytes())?;
+            for parent_id in self.repo.find_commit(oid)?.parent_ids() {
+                child_index
+                    .entry(commit_hash_from_bytes(parent_id.as_bytes())?)
+                    .or_default()
+                    .push(commit_hash);
+            }
+        }
+        for children in child_index.values_mut() {
+            children.sort();
+        }
+        Ok(child_index)
     }
 
     pub(crate) fn find_merge_base(
@@ -377,22 +1108,90 @@ impl RawRepositoryImplInner {
         commit_hash1: CommitHash,
         commit_hash2: CommitHash,
     ) -> Result<CommitHash, Error> {
+        // Trivial case; no need to walk the history.
+        if commit_hash1 == commit_hash2 {
+            return Ok(commit_hash1);
+        }
         let oid1 = Oid::from_bytes(&commit_hash1.hash)?;
         let oid2 = Oid::from_bytes(&commit_hash2.hash)?;
 
         let oid_merge = self.repo.merge_base(oid1, oid2)?;
-        let commit_hash_merge: [u8; 20] = oid_merge
-            .as_bytes()
-            .try_into()
-            .map_err(|_| Error::Unknown("err".to_string()))?;
+        commit_hash_from_bytes(oid_merge.as_bytes())
+    }
+
+    pub(crate) fn try_merge_base(
+        &self,
+        commit_hash1: CommitHash,
+        commit_hash2: CommitHash,
+    ) -> Result<Option<CommitHash>, Error> {
+        match self.find_merge_base(commit_hash1, commit_hash2) {
+            Ok(merge_base) => Ok(Some(merge_base)),
+            // A missing object is also `NotFound`, but of another class.
+            Err(Error::Git2Error(e))
+                if e.code() == git2::ErrorCode::NotFound
+                    && e.class() == git2::ErrorClass::Merge =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
 
-        Ok(CommitHash {
-            hash: commit_hash_merge,
-        })
+    pub(crate) fn is_ancestor(
+        &self,
+        ancestor: CommitHash,
+        descendant: CommitHash,
+    ) -> Result<bool, Error> {
+        if ancestor == descendant {
+            return Ok(true);
+        }
+        let ancestor = Oid::from_bytes(&ancestor.hash)?;
+        let descendant = Oid::from_bytes(&descendant.hash)?;
+        Ok(self.repo.graph_descendant_of(descendant, ancestor)?)
+    }
+
+    pub(crate) fn find_merge_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Option<CommitHash>, Error> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push(Oid::from_bytes(&commit_hash.hash)?)?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+        for oid in revwalk {
+            let oid = oid?;
+            if self.repo.find_commit(oid)?.parent_count() > 1 {
+                return Ok(Some(commit_hash_from_bytes(oid.as_bytes())?));
+            }
+        }
+        Ok(None)
     }
 
     pub(crate) fn read_reserved_state(&self) -> Result<ReservedState, Error> {
-        unimplemented!()
+        let head = self.get_head()?;
+        self.read_reserved_state_at_commit(head)
+    }
+
+    pub(crate) fn read_reserved_state_at_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<ReservedState, Error> {
+        let oid = Oid::from_bytes(&commit_hash.hash)?;
+        let tree = self.repo.find_commit(oid)?.tree()?;
+
+        let mut files = Vec::new();
+        for path in reserved_state::get_reserved_file_paths() {
+            let entry = match tree.get_path(Path::new(&path)) {
+                Ok(entry) => entry,
+                Err(e) if e.code() == git2::ErrorCode::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let blob = entry.to_object(&self.repo)?.peel_to_blob()?;
+            let content = str::from_utf8(blob.content())
+                .map_err(|_| Error::InvalidRepository(format!("{} is not valid UTF-8", path)))?
+                .to_string();
+            files.push((path, content));
+        }
+        reserved_state::from_files(&files).map_err(Error::InvalidRepository)
     }
 
     pub(crate) fn add_remote(
@@ -400,20 +1199,118 @@ impl RawRepositoryImplInner {
         remote_name: String,
         remote_url: String,
     ) -> Result<(), Error> {
-        self.repo
-            .remote(remote_name.as_str(), remote_url.as_str())?;
+        match self.repo.find_remote(remote_name.as_str()) {
+            Ok(remote) => {
+                if remote.url() != Some(remote_url.as_str()) {
+                    self.repo
+                        .remote_set_url(remote_name.as_str(), remote_url.as_str())?;
+                }
+            }
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                self.repo
+                    .remote(remote_name.as_str(), remote_url.as_str())?;
+            }
+            Err(e) => return Err(e.into()),
+        }
 
         Ok(())
     }
 
     pub(crate) fn remove_remote(&mut self, remote_name: String) -> Result<(), Error> {
-        self.repo.remote_delete(remote_name.as_str())?;
+        match self.repo.remote_delete(remote_name.as_str()) {
+            Ok(()) => Ok(()),
+            // Removing a remote that doesn't exist is not an error.
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
 
+    pub(crate) fn fetch_all(&mut self, retries: usize) -> Result<(), Error> {
+        for (remote_name, _) in self.list_remotes()? {
+            let mut attempts = 0;
+            loop {
+                match self.fetch_remote(&remote_name) {
+                    Err(e) if attempts < retries && is_transient_error(&e) => {
+                        attempts += 1;
+                        log::warn!(
+                            "retrying to fetch from {} ({}/{}): {}",
+                            remote_name,
+                            attempts,
+                            retries,
+                            e
+                        );
+                        std::thread::sleep(FETCH_RETRY_DELAY * attempts as u32);
+                    }
+                    result => break result?,
+                }
+            }
+        }
         Ok(())
     }
 
-    pub(crate) fn fetch_all(&mut self) -> Result<(), Error> {
-        unimplemented!()
+    fn fetch_remote(&self, remote_name: &str) -> Result<(), git2::Error> {
+        let mut remote = self.repo.find_remote(remote_name)?;
+        let mut options = git2::FetchOptions::new();
+        options.prune(git2::FetchPrune::On);
+        remote.fetch(
+            &[format!("+refs/heads/*:refs/remotes/{}/*", remote_name)],
+            Some(&mut options),
+            None,
+        )?;
+        // Tags are fetched separately since pruning them would remove the local ones.
+        // The refspec is not forced, so a tag that conflicts with a local one is skipped
+        // instead of replacing it.
+        remote.fetch(&["refs/tags/*:refs/tags/*"], None, None)
+    }
+
+    pub(crate) fn fetch_all_with_depth(&mut self, depth: usize) -> Result<(), Error> {
+        // libgit2 doesn't support shallow fetches, so it runs the `git` command.
+        for (remote_name, _) in self.list_remotes()? {
+            // Tags are never pruned, unlike the remote tracking branches, and the tag refspec
+            // is not forced, same as in `fetch_remote()`.
+            // (A separate fetch of the tags would fail if there were none.)
+            // It isn't `--quiet` because the rejected tags are only reported in the output.
+            let output = std::process::Command::new("git")
+                .env("LC_ALL", "C")
+                .arg("--git-dir")
+                .arg(self.repo.path())
+                .args(["fetch", "--prune", "--no-tags"])
+                .arg(format!("--depth={}", depth))
+                .arg(&remote_name)
+                .arg(format!("+refs/heads/*:refs/remotes/{}/*", remote_name))
+                .arg("refs/tags/*:refs/tags/*")
+                .output()
+                .map_err(|e| Error::Unknown(format!("failed to run git fetch: {}", e)))?;
+            if output.status.success() {
+                continue;
+            }
+            // A tag that conflicts with a local one is rejected, which fails the command
+            // even though everything else has been fetched.
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let (rejected_tags, other_lines): (Vec<&str>, Vec<&str>) = stderr
+                .lines()
+                .partition(|line| line.ends_with("(would clobber existing tag)"));
+            let failed = other_lines.iter().any(|line| {
+                line.starts_with("fatal:")
+                    || line.starts_with("error:")
+                    || line.trim_start().starts_with('!')
+            });
+            if rejected_tags.is_empty() || failed {
+                return Err(Error::Unknown(format!(
+                    "git fetch from {} failed: {}",
+                    remote_name,
+                    stderr.trim()
+                )));
+            }
+            for line in rejected_tags {
+                log::warn!(
+                    "skipped a tag from {} that conflicts with a local one: {}",
+                    remote_name,
+                    line.trim()
+                );
+            }
+        }
+        Ok(())
     }
 
     pub(crate) fn list_remotes(&self) -> Result<Vec<(String, String)>, Error> {
@@ -448,7 +1345,45 @@ impl RawRepositoryImplInner {
 
     pub(crate) fn list_remote_tracking_branches(
         &self,
+        remote_name: Option<String>,
     ) -> Result<Vec<(String, String, CommitHash)>, Error> {
-        unimplemented!()
+        let glob = match remote_name {
+            Some(remote_name) => format!("refs/remotes/{}/*", remote_name),
+            None => "refs/remotes/*".to_owned(),
+        };
+        let references = self.repo.references_glob(&glob)?;
+
+        let mut result = Vec::new();
+        for reference in references {
+            let reference = reference?;
+            // Skip symbolic references such as `<remote>/HEAD`.
+            let oid = match reference.target() {
+                Some(oid) => oid,
+                None => continue,
+            };
+            let name = reference
+                .shorthand()
+                .ok_or_else(|| Error::Unknown("non-UTF-8 branch name".to_string()))?;
+            let (remote_name, branch_name) = name
+                .split_once('/')
+                .ok_or_else(|| Error::Unknown(format!("invalid remote branch name: {}", name)))?;
+            result.push((
+                remote_name.to_string(),
+                branch_name.to_string(),
+                commit_hash_from_bytes(oid.as_bytes())?,
+            ));
+        }
+        Ok(result)
+    }
+
+    pub(crate) fn delete_remote_tracking_branch(
+        &mut self,
+        remote_name: String,
+        branch: Branch,
+    ) -> Result<(), Error> {
+        self.repo
+            .find_reference(&format!("refs/remotes/{}/{}", remote_name, branch))?
+            .delete()?;
+        Ok(())
     }
 }
@@ -1,4 +1,5 @@
 mod implementation;
+pub mod reserved_state;
 #[cfg(test)]
 mod tests;
 
@@ -19,6 +20,12 @@ pub enum Error {
     /// When the assumption of the method (e.g., there is no merge commit) is violated.
     #[error("the repository is invalid: {0}")]
     InvalidRepository(String),
+    /// When a reference is symbolic, or has no target for another reason.
+    #[error("the reference has no target: {0}")]
+    NoTarget(String),
+    /// When a prefix of a commit hash matches more than one object.
+    #[error("the prefix is ambiguous: {0}")]
+    AmbiguousPrefix(String),
     #[error("unknown error: {0}")]
     Unknown(String),
 }
@@ -37,6 +44,64 @@ pub struct SemanticCommit {
     pub diff: Diff,
 }
 
+/// A change of a single file, with the path relative to the root of the repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileDiff {
+    Added(String),
+    Deleted(String),
+    Modified(String),
+    /// Only with the rename detection; `(from, to)`.
+    Renamed(String, String),
+    /// Only with the rename detection; `(from, to)`.
+    Copied(String, String),
+}
+
+/// The changes of the files between two commits.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CommitDiff {
+    /// Sorted by the path.
+    pub files: Vec<FileDiff>,
+}
+
+/// How a branch should be related to a commit to be matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchMatch {
+    /// The branch points to the commit.
+    TipOnly,
+    /// The branch points to the commit or one of its descendants.
+    Contains,
+}
+
+/// The status of a file in the working tree, compared to `HEAD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusKind {
+    /// Added to the index, but not committed yet.
+    Added,
+    Modified,
+    Deleted,
+    /// Neither in the index nor in `HEAD`.
+    Untracked,
+}
+
+/// The kind of a git object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+    /// An annotated tag object.
+    Tag,
+}
+
+/// A single move of a branch, recorded in its reflog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// `None` if the branch was created by the move.
+    pub old_commit: Option<CommitHash>,
+    pub new_commit: CommitHash,
+    pub message: String,
+}
+
 #[async_trait]
 pub trait RawRepository: Send + Sync + 'static {
     /// Initialize the genesis repository from the genesis working tree.
@@ -51,49 +116,134 @@ pub trait RawRepository: Send + Sync + 'static {
         Self: Sized;
 
     // Loads an exisitng repository.
+    //
+    // It fails with `InvalidRepository` if the index is locked (`index.lock`),
+    // which a crashed process may have left; see `force_unlock()`.
     async fn open(directory: &str) -> Result<Self, Error>
     where
         Self: Sized;
 
+    /// Loads the repository that contains the given directory,
+    /// walking up the parent directories to find it.
+    ///
+    /// Unlike `open()`, the directory doesn't have to be the root of the repository.
+    async fn open_discover(directory: &str) -> Result<Self, Error>
+    where
+        Self: Sized;
+
+    /// Removes the index lock (`index.lock`) of the repository, which a crashed process may have left.
+    ///
+    /// It refuses if a `DistributedRepository` holds the lock on `LOCK_FILE_NAME`.
+    /// Other processes, such as a `git` command, can't be detected, so it must be called
+    /// only when no other process is using the repository.
+    ///
+    /// Returns whether there was a lock to remove.
+    async fn force_unlock(directory: &str) -> Result<bool, Error>
+    where
+        Self: Sized;
+
+    /// Clones the remote repository into the given directory and opens it.
+    ///
+    /// The remote is added as `origin`, and only its `HEAD` branch is created locally.
+    async fn clone_from(url: &str, directory: &str) -> Result<Self, Error>
+    where
+        Self: Sized;
+
+    /// Returns the path of the `.git` directory.
+    async fn get_git_directory(&self) -> Result<String, Error>;
+
     // ----------------------
     // Branch-related methods
     // ----------------------
 
-    /// Returns the list of branches.
+    /// Returns the list of branches, both loose and packed.
     async fn list_branches(&self) -> Result<Vec<Branch>, Error>;
 
+    /// Returns the list of branches with the commits that they point to,
+    /// which is cheaper than `locate_branch()` for each of them.
+    ///
+    /// Symbolic branches, which `locate_branch()` fails on, are left out.
+    async fn list_branches_with_tips(&self) -> Result<Vec<(Branch, CommitHash)>, Error>;
+
     /// Creates a branch on the commit.
+    ///
+    /// The name must be a valid git branch name; otherwise it fails with `InvalidRepository`.
+    ///
+    /// Returns the commit that the new branch points to.
     async fn create_branch(
         &self,
         branch_name: Branch,
         commit_hash: CommitHash,
-    ) -> Result<(), Error>;
+    ) -> Result<CommitHash, Error>;
 
     /// Gets the commit that the branch points to.
+    ///
+    /// It fails with `Error::InvalidRepository` if there is no such branch,
+    /// and with `Error::NoTarget` if the branch doesn't point to a commit directly.
     async fn locate_branch(&self, branch: Branch) -> Result<CommitHash, Error>;
 
-    /// Gets the list of branches from the commit.
-    async fn get_branches(&self, commit_hash: CommitHash) -> Result<Vec<Branch>, Error>;
+    /// Gets the list of branches related to the commit, according to `mode`.
+    async fn get_branches(
+        &self,
+        commit_hash: CommitHash,
+        mode: BranchMatch,
+    ) -> Result<Vec<Branch>, Error>;
 
     /// Moves the branch.
-    async fn move_branch(&mut self, branch: Branch, commit_hash: CommitHash) -> Result<(), Error>;
+    ///
+    /// If `fast_forward_only` is set, it fails unless the given commit is a descendant of
+    /// the current commit of the branch (i.e., the move never rewinds the branch).
+    ///
+    /// The `reason` is recorded in the reflog of the branch.
+    async fn move_branch(
+        &mut self,
+        branch: Branch,
+        commit_hash: CommitHash,
+        fast_forward_only: bool,
+        reason: String,
+    ) -> Result<(), Error>;
 
     /// Deletes the branch.
     async fn delete_branch(&mut self, branch: Branch) -> Result<(), Error>;
 
+    /// Reads the reflog of the branch, from the most recent move.
+    ///
+    /// If `max` is given, it returns at most that many entries.
+    async fn read_reflog(
+        &self,
+        branch: Branch,
+        max: Option<usize>,
+    ) -> Result<Vec<ReflogEntry>, Error>;
+
     // -------------------
     // Tag-related methods
     // -------------------
 
-    /// Returns the list of tags.
+    /// Returns the list of tags, both loose and packed.
     async fn list_tags(&self) -> Result<Vec<Tag>, Error>;
 
-    /// Creates a tag on the given commit.
-    async fn create_tag(&mut self, tag: Tag, commit_hash: CommitHash) -> Result<(), Error>;
+    /// Returns the list of tags with the commits that they point to.
+    async fn list_tags_with_targets(&self) -> Result<Vec<(Tag, CommitHash)>, Error>;
+
+    /// Creates a tag on the given object.
+    ///
+    /// If `expected_kind` is given, fails unless the object is of that kind.
+    /// Note that `locate_tag()` fails for a tag that is not on a commit.
+    async fn create_tag(
+        &mut self,
+        tag: Tag,
+        commit_hash: CommitHash,
+        expected_kind: Option<ObjectKind>,
+    ) -> Result<(), Error>;
 
     /// Gets the commit that the tag points to.
     async fn locate_tag(&self, tag: Tag) -> Result<CommitHash, Error>;
 
+    /// Reads the message of the given tag.
+    ///
+    /// Returns `None` if it is a lightweight tag.
+    async fn read_tag_message(&self, tag: Tag) -> Result<Option<String>, Error>;
+
     /// Gets the tags on the given commit.
     async fn get_tag(&self, commit_hash: CommitHash) -> Result<Vec<Tag>, Error>;
 
@@ -103,6 +253,8 @@ pub trait RawRepository: Send + Sync + 'static {
     // ----------------------
     // Commit-related methods
     // ----------------------
+    //
+    // If the identity (`user.name` and `user.email`) is not configured, a default one is used.
 
     /// Creates a commit from the currently checked out branch.
     async fn create_commit(
@@ -111,14 +263,55 @@ pub trait RawRepository: Send + Sync + 'static {
         diff: Option<String>,
     ) -> Result<CommitHash, Error>;
 
-    /// Creates a semantic commit from the currently checked out branch.
-    async fn create_semantic_commit(&mut self, commit: SemanticCommit)
-        -> Result<CommitHash, Error>;
+    /// Creates a commit on top of the given branch and moves the branch to it.
+    ///
+    /// Unlike `create_commit()`, it touches neither `HEAD` nor the working tree.
+    /// (If the branch is checked out, the working tree will be left behind.)
+    async fn create_commit_on_branch(
+        &mut self,
+        branch: Branch,
+        commit_message: String,
+        diff: Option<String>,
+    ) -> Result<CommitHash, Error>;
+
+    /// Creates a commit with the given parents, on top of the tree of the first parent
+    /// (or the empty tree if there is none) with the given files written.
+    ///
+    /// It touches no branch, `HEAD` nor the working tree. Any DAG can be built with this,
+    /// including merge commits.
+    async fn create_commit_with_parents(
+        &mut self,
+        commit_message: String,
+        files: Vec<(String, Vec<u8>)>,
+        parents: Vec<CommitHash>,
+    ) -> Result<CommitHash, Error>;
 
-    /// Reads the reserved state from the current working tree.
+    /// Creates a semantic commit on the given branch, which must be the checked out one.
+    ///
+    /// Its parent is `HEAD`. With `Diff::None`, the commit is empty; with `Diff::Reserved`,
+    /// the reserved files are written and the rest of the tree is kept.
+    /// `Diff::General` carries only a hash, so it is rejected.
+    ///
+    /// It fails if `HEAD` is on another branch or detached, instead of committing there.
+    async fn create_semantic_commit(
+        &mut self,
+        commit: SemanticCommit,
+        branch: Branch,
+    ) -> Result<CommitHash, Error>;
+
+    /// Reads the commit as a semantic commit.
+    ///
+    /// If the commit changes the reserved area, the diff is `Diff::Reserved` with the new
+    /// reserved state; otherwise it is `Diff::None` for an empty commit and `Diff::General`.
+    /// The hash of the diff is that of the tree, not the one given to `create_semantic_commit()`.
     async fn read_semantic_commit(&self, commit_hash: CommitHash) -> Result<SemanticCommit, Error>;
 
     /// Removes orphaned commits. Same as `git gc --prune=now --aggressive`
+    ///
+    /// It holds the repository exclusively while running, so no other operation can be in flight;
+    /// objects written by an operation are referenced by the time it returns.
+    /// The exception is `create_commit_with_parents()`, whose commit must be referenced
+    /// (e.g., by `create_branch()`) before this runs, or it is removed.
     async fn run_garbage_collection(&mut self) -> Result<(), Error>;
 
     // ----------------------------
@@ -127,9 +320,19 @@ pub trait RawRepository: Send + Sync + 'static {
 
     /// Checkouts and cleans the current working tree.
     /// This is same as `git checkout . && git clean -fd`.
-    async fn checkout_clean(&mut self) -> Result<(), Error>;
+    ///
+    /// If `paths` is given, only those paths (files or directories) are cleaned,
+    /// as in `git checkout -- <paths> && git clean -fd -- <paths>`.
+    async fn checkout_clean(&mut self, paths: Option<Vec<String>>) -> Result<(), Error>;
+
+    /// Returns the paths in the working tree that differ from `HEAD`, sorted by the path.
+    /// This is same as `git status`, excluding the ignored files.
+    async fn working_tree_status(&self) -> Result<Vec<(String, StatusKind)>, Error>;
 
     /// Checkouts to the branch.
+    ///
+    /// If there is no such local branch but a remote tracking branch of the same name,
+    /// it creates the local branch tracking it first.
     async fn checkout(&mut self, branch: Branch) -> Result<(), Error>;
 
     /// Checkouts to the commit and make `HEAD` in a detached mode.
@@ -140,6 +343,8 @@ pub trait RawRepository: Send + Sync + 'static {
     // ---------------
 
     /// Returns the commit hash of the current HEAD.
+    ///
+    /// It fails with `Error::InvalidRepository` if the repository has no commits yet.
     async fn get_head(&self) -> Result<CommitHash, Error>;
 
     /// Returns the commit hash of the initial commit.
@@ -147,9 +352,35 @@ pub trait RawRepository: Send + Sync + 'static {
     /// Fails if the repository is empty.
     async fn get_initial_commit(&self) -> Result<CommitHash, Error>;
 
+    /// Resolves a hex prefix of a commit hash (e.g., an abbreviated one) to the commit.
+    ///
+    /// The prefix must be at least 4 hex digits long, as in Git.
+    /// It fails with `Error::AmbiguousPrefix` if more than one object in the database matches,
+    /// and fails if none matches or the matching one is not a commit.
+    async fn resolve_short_hash(&self, prefix: String) -> Result<CommitHash, Error>;
+
     /// Returns the diff of the given commit.
     async fn show_commit(&self, commit_hash: CommitHash) -> Result<String, Error>;
 
+    /// Returns the changes of the files from `from` to `to`, comparing their trees.
+    ///
+    /// The two commits don't have to be adjacent or even related.
+    /// If `detect_renames` is set, renamed and copied files are reported as such,
+    /// instead of as deleted and added ones.
+    async fn diff(
+        &self,
+        from: CommitHash,
+        to: CommitHash,
+        detect_renames: bool,
+    ) -> Result<CommitDiff, Error>;
+
+    /// Returns the size of the payload of the given commit in bytes,
+    /// which is the size of its message plus the size of the reserved files
+    /// that it adds or modifies (compared to its first parent).
+    ///
+    /// It is cheap enough to be used to reject an oversized commit before parsing it.
+    async fn get_commit_payload_size(&self, commit_hash: CommitHash) -> Result<usize, Error>;
+
     /// Lists the ancestor commits of the given commit (The first element is the direct parent).
     ///
     /// It fails if there is a merge commit.
@@ -160,6 +391,14 @@ pub trait RawRepository: Send + Sync + 'static {
         max: Option<usize>,
     ) -> Result<Vec<CommitHash>, Error>;
 
+    /// Same as `list_ancestors()`, but follows only the first parent of each commit
+    /// instead of failing on a merge commit (same as `git log --first-parent`).
+    async fn list_first_parent_ancestors(
+        &self,
+        commit_hash: CommitHash,
+        max: Option<usize>,
+    ) -> Result<Vec<CommitHash>, Error>;
+
     /// Lists the descendant commits of the given commit (The first element is the direct child).
     ///
     /// It fails if there are diverged commits (i.e., having multiple children commit)
@@ -170,9 +409,27 @@ pub trait RawRepository: Send + Sync + 'static {
         max: Option<usize>,
     ) -> Result<Vec<CommitHash>, Error>;
 
+    /// Returns the linear path of commits from `from` (exclusive) to `to` (inclusive).
+    ///
+    /// It fails if `to` is not a descendant of `from` or if there is a merge commit on the path.
+    async fn descendants_until(
+        &self,
+        from: CommitHash,
+        to: CommitHash,
+    ) -> Result<Vec<CommitHash>, Error>;
+
     /// Returns the children commits of the given commit.
     async fn list_children(&self, commit_hash: CommitHash) -> Result<Vec<CommitHash>, Error>;
 
+    /// Returns the children of every commit reachable from any local branch, keyed by the parent.
+    ///
+    /// The remote tracking branches and the tags are not walked, since their history may be
+    /// cut by a shallow fetch (see `fetch_all_with_depth()`).
+    /// The children are sorted, and the commits without children are omitted.
+    /// The index is kept until a reference moves, and `list_children()` and `list_descendants()`
+    /// look it up instead of walking the history on every call.
+    async fn build_child_index(&self) -> Result<HashMap<CommitHash, Vec<CommitHash>>, Error>;
+
     /// Returns the merge base of the two commits.
     async fn find_merge_base(
         &self,
@@ -180,43 +437,186 @@ pub trait RawRepository: Send + Sync + 'static {
         commit_hash2: CommitHash,
     ) -> Result<CommitHash, Error>;
 
+    /// Same as `find_merge_base()`, but returns `None` if the two commits have no common ancestor.
+    ///
+    /// `Err` is only for the other failures (e.g., a missing commit).
+    async fn try_merge_base(
+        &self,
+        commit_hash1: CommitHash,
+        commit_hash2: CommitHash,
+    ) -> Result<Option<CommitHash>, Error>;
+
+    /// Checks whether `ancestor` is an ancestor of `descendant` or the same commit.
+    ///
+    /// Prefer this to comparing `find_merge_base()` with a known commit.
+    async fn is_ancestor(
+        &self,
+        ancestor: CommitHash,
+        descendant: CommitHash,
+    ) -> Result<bool, Error>;
+
+    /// Finds a merge commit in the history of the given commit, including itself.
+    ///
+    /// Returns the nearest one, or `None` if the history is linear.
+    async fn find_merge_commit(&self, commit_hash: CommitHash)
+        -> Result<Option<CommitHash>, Error>;
+
     /// Reads the reserved state from the currently checked out branch.
     async fn read_reserved_state(&self) -> Result<ReservedState, Error>;
 
+    /// Reads the reserved state from the tree of the given commit.
+    async fn read_reserved_state_at_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<ReservedState, Error>;
+
     // ----------------------
     // Remote-related methods
     // ----------------------
 
     /// Adds a remote repository.
+    ///
+    /// If there is already a remote of the same name, it updates the URL of it.
     async fn add_remote(&mut self, remote_name: String, remote_url: String) -> Result<(), Error>;
 
     /// Removes a remote repository.
+    ///
+    /// It succeeds even if there is no such remote.
     async fn remove_remote(&mut self, remote_name: String) -> Result<(), Error>;
 
     /// Fetches the remote repository. Same as `git fetch --all -j <LARGE NUMBER>`.
+    ///
+    /// It fetches both the branches (as remote tracking branches) and the tags.
+    /// Remote tracking branches whose upstream branches are gone are pruned, but tags never are.
+    /// A fetched tag never replaces a local tag of the same name; it is skipped instead.
+    ///
+    /// A remote is retried on a transient network error (e.g., a connection reset),
+    /// but not on a permanent one (e.g., a denied authentication or a missing repository).
     async fn fetch_all(&mut self) -> Result<(), Error>;
 
+    /// Same as `fetch_all()`, but fetches only the last `depth` commits of each branch and tag
+    /// (same as `git fetch --depth <depth>`), which makes the repository shallow.
+    ///
+    /// The commits beyond the depth are not downloaded, so walking the history of a fetched
+    /// commit fails there unless it reaches a commit that was already in the repository.
+    async fn fetch_all_with_depth(&mut self, depth: usize) -> Result<(), Error>;
+
     /// Lists all the remote repositories.
     ///
     /// Returns `(remote_name, remote_url)`.
     async fn list_remotes(&self) -> Result<Vec<(String, String)>, Error>;
 
-    /// Lists all the remote tracking branches.
+    /// Lists the remote tracking branches of the given remote, or of all the remotes if `None`.
     ///
-    /// Returns `(remote_name, remote_url, commit_hash)`
+    /// Returns `(remote_name, branch_name, commit_hash)`
     async fn list_remote_tracking_branches(
         &self,
+        remote_name: Option<String>,
     ) -> Result<Vec<(String, String, CommitHash)>, Error>;
+
+    /// Deletes a remote tracking branch (e.g., one whose history is cut by a shallow fetch).
+    ///
+    /// The next fetch brings it back if the remote still has the branch.
+    async fn delete_remote_tracking_branch(
+        &mut self,
+        remote_name: String,
+        branch: Branch,
+    ) -> Result<(), Error>;
 }
 
 #[derive(Debug)]
 pub struct RawRepositoryImpl {
     inner: tokio::sync::Mutex<Option<RawRepositoryImplInner>>,
+    /// The spare handles for the `&self` operations, so that they don't wait for each other.
+    ///
+    /// At most `MAX_SPARE_READERS` of them are kept. They survive the writes, since libgit2
+    /// reads the references and the objects from the disk on every lookup;
+    /// only `run_garbage_collection()`, which rewrites the object storage, drops them.
+    readers: std::sync::Mutex<Vec<RawRepositoryImplInner>>,
+    git_directory: String,
+    /// The number of retries of `fetch_all()` for each remote on a transient error.
+    fetch_retries: usize,
+    /// The last child index, with the reference tips that it was built from.
+    child_index: std::sync::Mutex<Option<(ReferenceTips, ChildIndex)>>,
 }
 
-async fn helper_0<R: Send + Sync + 'static>(
+type ChildIndex = std::sync::Arc<HashMap<CommitHash, Vec<CommitHash>>>;
+type ReferenceTips = Vec<(String, Oid)>;
+
+/// The default number of retries of `fetch_all()` for each remote on a transient error.
+pub const DEFAULT_FETCH_RETRIES: usize = 2;
+
+impl RawRepositoryImpl {
+    fn new(inner: RawRepositoryImplInner) -> Result<Self, Error> {
+        let git_directory = inner.get_git_directory()?;
+        Ok(Self {
+            inner: tokio::sync::Mutex::new(Some(inner)),
+            readers: std::sync::Mutex::new(Vec::new()),
+            git_directory,
+            fetch_retries: DEFAULT_FETCH_RETRIES,
+            child_index: std::sync::Mutex::new(None),
+        })
+    }
+
+    /// Returns the child index, rebuilding it only if a reference has moved since the last one.
+    async fn child_index(&self) -> Result<ChildIndex, Error> {
+        let tips = read(self, RawRepositoryImplInner::list_reference_tips).await?;
+        if let Some((cached_tips, child_index)) = &*self.child_index.lock().unwrap() {
+            if *cached_tips == tips {
+                return Ok(std::sync::Arc::clone(child_index));
+            }
+        }
+        // The tips are read again along with the index, so that they match each other.
+        let (tips, child_index) = read(self, |inner| {
+            Ok::<_, Error>((inner.list_reference_tips()?, inner.build_child_index()?))
+        })
+        .await?;
+        let child_index = std::sync::Arc::new(child_index);
+        *self.child_index.lock().unwrap() = Some((tips, std::sync::Arc::clone(&child_index)));
+        Ok(child_index)
+    }
+
+    /// Sets the number of retries of `fetch_all()` for each remote on a transient network error,
+    /// such as a connection reset. Zero disables them.
+    pub fn set_fetch_retries(&mut self, retries: usize) {
+        self.fetch_retries = retries;
+    }
+}
+
+/// The maximum number of spare handles kept for the `&self` operations.
+///
+/// More reads than this can still run at once, but the extra handles are closed afterwards.
+const MAX_SPARE_READERS: usize = 8;
+
+/// Runs a `&self` operation on a spare handle of the repository, opening one if there is none.
+///
+/// If it fails to open one, it waits for the main handle instead.
+async fn read<R: Send + Sync + 'static>(
     s: &RawRepositoryImpl,
-    f: impl Fn(&RawRepositoryImplInner) -> R + Send + 'static,
+    f: impl FnOnce(&RawRepositoryImplInner) -> R + Send + 'static,
+) -> R {
+    let reader = s.readers.lock().unwrap().pop();
+    let reader = match reader
+        .map(Ok)
+        .unwrap_or_else(|| RawRepositoryImplInner::open(&s.git_directory))
+    {
+        Ok(reader) => reader,
+        Err(_) => return exclusive(s, f).await,
+    };
+    let (result, reader) = tokio::task::spawn_blocking(move || (f(&reader), reader))
+        .await
+        .unwrap();
+    let mut readers = s.readers.lock().unwrap();
+    if readers.len() < MAX_SPARE_READERS {
+        readers.push(reader);
+    }
+    result
+}
+
+/// Runs a `&self` operation on the main handle of the repository.
+async fn exclusive<R: Send + Sync + 'static>(
+    s: &RawRepositoryImpl,
+    f: impl FnOnce(&RawRepositoryImplInner) -> R + Send + 'static,
 ) -> R {
     let mut lock = s.inner.lock().await;
     let inner = lock.take().expect("RawRepoImpl invariant violated");
@@ -227,6 +627,13 @@ async fn helper_0<R: Send + Sync + 'static>(
     result
 }
 
+async fn helper_0<R: Send + Sync + 'static>(
+    s: &RawRepositoryImpl,
+    f: impl Fn(&RawRepositoryImplInner) -> R + Send + 'static,
+) -> R {
+    read(s, move |inner| f(inner)).await
+}
+
 async fn helper_0_mut<R: Send + Sync + 'static>(
     s: &mut RawRepositoryImpl,
     f: impl Fn(&mut RawRepositoryImplInner) -> R + Send + 'static,
@@ -245,13 +652,7 @@ async fn helper_1<T1: Send + Sync + 'static + Clone, R: Send + Sync + 'static>(
     f: impl Fn(&RawRepositoryImplInner, T1) -> R + Send + 'static,
     a1: T1,
 ) -> R {
-    let mut lock = s.inner.lock().await;
-    let inner = lock.take().expect("RawRepoImpl invariant violated");
-    let (result, inner) = tokio::task::spawn_blocking(move || (f(&inner, a1), inner))
-        .await
-        .unwrap();
-    lock.replace(inner);
-    result
+    read(s, move |inner| f(inner, a1)).await
 }
 
 async fn helper_1_mut<T1: Send + Sync + 'static + Clone, R: Send + Sync + 'static>(
@@ -277,35 +678,89 @@ async fn helper_2<
     f: impl Fn(&RawRepositoryImplInner, T1, T2) -> R + Send + 'static,
     a1: T1,
     a2: T2,
+) -> R {
+    read(s, move |inner| f(inner, a1, a2)).await
+}
+
+async fn helper_2_mut<
+    T1: Send + Sync + 'static + Clone,
+    T2: Send + Sync + 'static + Clone,
+    R: Send + Sync + 'static,
+>(
+    s: &mut RawRepositoryImpl,
+    f: impl Fn(&mut RawRepositoryImplInner, T1, T2) -> R + Send + 'static,
+    a1: T1,
+    a2: T2,
 ) -> R {
     let mut lock = s.inner.lock().await;
-    let inner = lock.take().expect("RawRepoImpl invariant violated");
-    let (result, inner) = tokio::task::spawn_blocking(move || (f(&inner, a1, a2), inner))
+    let mut inner = lock.take().expect("RawRepoImpl invariant violated");
+    let (result, inner) = tokio::task::spawn_blocking(move || (f(&mut inner, a1, a2), inner))
         .await
         .unwrap();
     lock.replace(inner);
     result
 }
 
-async fn helper_2_mut<
+async fn helper_3<
     T1: Send + Sync + 'static + Clone,
     T2: Send + Sync + 'static + Clone,
+    T3: Send + Sync + 'static + Clone,
+    R: Send + Sync + 'static,
+>(
+    s: &RawRepositoryImpl,
+    f: impl Fn(&RawRepositoryImplInner, T1, T2, T3) -> R + Send + 'static,
+    a1: T1,
+    a2: T2,
+    a3: T3,
+) -> R {
+    read(s, move |inner| f(inner, a1, a2, a3)).await
+}
+
+async fn helper_3_mut<
+    T1: Send + Sync + 'static + Clone,
+    T2: Send + Sync + 'static + Clone,
+    T3: Send + Sync + 'static + Clone,
     R: Send + Sync + 'static,
 >(
     s: &mut RawRepositoryImpl,
-    f: impl Fn(&mut RawRepositoryImplInner, T1, T2) -> R + Send + 'static,
+    f: impl Fn(&mut RawRepositoryImplInner, T1, T2, T3) -> R + Send + 'static,
     a1: T1,
     a2: T2,
+    a3: T3,
 ) -> R {
     let mut lock = s.inner.lock().await;
     let mut inner = lock.take().expect("RawRepoImpl invariant violated");
-    let (result, inner) = tokio::task::spawn_blocking(move || (f(&mut inner, a1, a2), inner))
+    let (result, inner) = tokio::task::spawn_blocking(move || (f(&mut inner, a1, a2, a3), inner))
         .await
         .unwrap();
     lock.replace(inner);
     result
 }
 
+async fn helper_4_mut<
+    T1: Send + Sync + 'static + Clone,
+    T2: Send + Sync + 'static + Clone,
+    T3: Send + Sync + 'static + Clone,
+    T4: Send + Sync + 'static + Clone,
+    R: Send + Sync + 'static,
+>(
+    s: &mut RawRepositoryImpl,
+    f: impl Fn(&mut RawRepositoryImplInner, T1, T2, T3, T4) -> R + Send + 'static,
+    a1: T1,
+    a2: T2,
+    a3: T3,
+    a4: T4,
+) -> R {
+    let mut lock = s.inner.lock().await;
+    let mut inner = lock.take().expect("RawRepoImpl invariant violated");
+    let (result, inner) =
+        tokio::task::spawn_blocking(move || (f(&mut inner, a1, a2, a3, a4), inner))
+            .await
+            .unwrap();
+    lock.replace(inner);
+    result
+}
+
 #[async_trait]
 impl RawRepository for RawRepositoryImpl {
     async fn init(
@@ -318,9 +773,7 @@ impl RawRepository for RawRepositoryImpl {
     {
         let repo =
             RawRepositoryImplInner::init(directory, init_commit_message, init_commit_branch)?;
-        let inner = tokio::sync::Mutex::new(Some(repo));
-
-        Ok(Self { inner })
+        Self::new(repo)
     }
 
     async fn open(directory: &str) -> Result<Self, Error>
@@ -328,26 +781,55 @@ impl RawRepository for RawRepositoryImpl {
         Self: Sized,
     {
         let repo = RawRepositoryImplInner::open(directory)?;
-        let inner = tokio::sync::Mutex::new(Some(repo));
+        repo.check_index_lock()?;
+        Self::new(repo)
+    }
 
-        Ok(Self { inner })
+    async fn open_discover(directory: &str) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let repo = RawRepositoryImplInner::open_discover(directory)?;
+        repo.check_index_lock()?;
+        Self::new(repo)
+    }
+
+    async fn force_unlock(directory: &str) -> Result<bool, Error>
+    where
+        Self: Sized,
+    {
+        RawRepositoryImplInner::open(directory)?.force_unlock()
+    }
+
+    async fn clone_from(url: &str, directory: &str) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let repo = RawRepositoryImplInner::clone_from(url, directory)?;
+        Self::new(repo)
+    }
+
+    async fn get_git_directory(&self) -> Result<String, Error> {
+        helper_0(self, RawRepositoryImplInner::get_git_directory).await
     }
 
     async fn list_branches(&self) -> Result<Vec<Branch>, Error> {
         helper_0(self, RawRepositoryImplInner::list_branches).await
     }
 
+    async fn list_branches_with_tips(&self) -> Result<Vec<(Branch, CommitHash)>, Error> {
+        helper_0(self, RawRepositoryImplInner::list_branches_with_tips).await
+    }
+
     async fn create_branch(
         &self,
         branch_name: Branch,
         commit_hash: CommitHash,
-    ) -> Result<(), Error> {
-        helper_2(
-            self,
-            RawRepositoryImplInner::create_branch,
-            branch_name,
-            commit_hash,
-        )
+    ) -> Result<CommitHash, Error> {
+        // It writes a reference, so it runs on the main handle, like the `&mut self` ones.
+        exclusive(self, move |inner| {
+            inner.create_branch(branch_name, commit_hash)
+        })
         .await
     }
 
@@ -355,16 +837,34 @@ impl RawRepository for RawRepositoryImpl {
         helper_1(self, RawRepositoryImplInner::locate_branch, branch).await
     }
 
-    async fn get_branches(&self, commit_hash: CommitHash) -> Result<Vec<Branch>, Error> {
-        helper_1(self, RawRepositoryImplInner::get_branches, commit_hash).await
+    async fn get_branches(
+        &self,
+        commit_hash: CommitHash,
+        mode: BranchMatch,
+    ) -> Result<Vec<Branch>, Error> {
+        helper_2(
+            self,
+            RawRepositoryImplInner::get_branches,
+            commit_hash,
+            mode,
+        )
+        .await
     }
 
-    async fn move_branch(&mut self, branch: Branch, commit_hash: CommitHash) -> Result<(), Error> {
-        helper_2_mut(
+    async fn move_branch(
+        &mut self,
+        branch: Branch,
+        commit_hash: CommitHash,
+        fast_forward_only: bool,
+        reason: String,
+    ) -> Result<(), Error> {
+        helper_4_mut(
             self,
             RawRepositoryImplInner::move_branch,
             branch,
             commit_hash,
+            fast_forward_only,
+            reason,
         )
         .await
     }
@@ -373,18 +873,46 @@ impl RawRepository for RawRepositoryImpl {
         helper_1_mut(self, RawRepositoryImplInner::delete_branch, branch).await
     }
 
+    async fn read_reflog(
+        &self,
+        branch: Branch,
+        max: Option<usize>,
+    ) -> Result<Vec<ReflogEntry>, Error> {
+        helper_2(self, RawRepositoryImplInner::read_reflog, branch, max).await
+    }
+
     async fn list_tags(&self) -> Result<Vec<Tag>, Error> {
         helper_0(self, RawRepositoryImplInner::list_tags).await
     }
 
-    async fn create_tag(&mut self, tag: Tag, commit_hash: CommitHash) -> Result<(), Error> {
-        helper_2_mut(self, RawRepositoryImplInner::create_tag, tag, commit_hash).await
+    async fn list_tags_with_targets(&self) -> Result<Vec<(Tag, CommitHash)>, Error> {
+        helper_0(self, RawRepositoryImplInner::list_tags_with_targets).await
+    }
+
+    async fn create_tag(
+        &mut self,
+        tag: Tag,
+        commit_hash: CommitHash,
+        expected_kind: Option<ObjectKind>,
+    ) -> Result<(), Error> {
+        helper_3_mut(
+            self,
+            RawRepositoryImplInner::create_tag,
+            tag,
+            commit_hash,
+            expected_kind,
+        )
+        .await
     }
 
     async fn locate_tag(&self, tag: Tag) -> Result<CommitHash, Error> {
         helper_1(self, RawRepositoryImplInner::locate_tag, tag).await
     }
 
+    async fn read_tag_message(&self, tag: Tag) -> Result<Option<String>, Error> {
+        helper_1(self, RawRepositoryImplInner::read_tag_message, tag).await
+    }
+
     async fn get_tag(&self, commit_hash: CommitHash) -> Result<Vec<Tag>, Error> {
         helper_1(self, RawRepositoryImplInner::get_tag, commit_hash).await
     }
@@ -407,11 +935,50 @@ impl RawRepository for RawRepositoryImpl {
         .await
     }
 
+    async fn create_commit_on_branch(
+        &mut self,
+        branch: Branch,
+        commit_message: String,
+        diff: Option<String>,
+    ) -> Result<CommitHash, Error> {
+        helper_3_mut(
+            self,
+            RawRepositoryImplInner::create_commit_on_branch,
+            branch,
+            commit_message,
+            diff,
+        )
+        .await
+    }
+
+    async fn create_commit_with_parents(
+        &mut self,
+        commit_message: String,
+        files: Vec<(String, Vec<u8>)>,
+        parents: Vec<CommitHash>,
+    ) -> Result<CommitHash, Error> {
+        helper_3_mut(
+            self,
+            RawRepositoryImplInner::create_commit_with_parents,
+            commit_message,
+            files,
+            parents,
+        )
+        .await
+    }
+
     async fn create_semantic_commit(
         &mut self,
         commit: SemanticCommit,
+        branch: Branch,
     ) -> Result<CommitHash, Error> {
-        helper_1_mut(self, RawRepositoryImplInner::create_semantic_commit, commit).await
+        helper_2_mut(
+            self,
+            RawRepositoryImplInner::create_semantic_commit,
+            commit,
+            branch,
+        )
+        .await
     }
 
     async fn read_semantic_commit(&self, commit_hash: CommitHash) -> Result<SemanticCommit, Error> {
@@ -424,11 +991,17 @@ impl RawRepository for RawRepositoryImpl {
     }
 
     async fn run_garbage_collection(&mut self) -> Result<(), Error> {
+        // The spare handles may have the removed pack files open.
+        self.readers.get_mut().unwrap().clear();
         helper_0_mut(self, RawRepositoryImplInner::run_garbage_collection).await
     }
 
-    async fn checkout_clean(&mut self) -> Result<(), Error> {
-        helper_0_mut(self, RawRepositoryImplInner::checkout_clean).await
+    async fn checkout_clean(&mut self, paths: Option<Vec<String>>) -> Result<(), Error> {
+        helper_1_mut(self, RawRepositoryImplInner::checkout_clean, paths).await
+    }
+
+    async fn working_tree_status(&self) -> Result<Vec<(String, StatusKind)>, Error> {
+        helper_0(self, RawRepositoryImplInner::working_tree_status).await
     }
 
     async fn checkout(&mut self, branch: Branch) -> Result<(), Error> {
@@ -447,10 +1020,32 @@ impl RawRepository for RawRepositoryImpl {
         helper_0(self, RawRepositoryImplInner::get_initial_commit).await
     }
 
+    async fn resolve_short_hash(&self, prefix: String) -> Result<CommitHash, Error> {
+        helper_1(self, RawRepositoryImplInner::resolve_short_hash, prefix).await
+    }
+
     async fn show_commit(&self, commit_hash: CommitHash) -> Result<String, Error> {
         helper_1(self, RawRepositoryImplInner::show_commit, commit_hash).await
     }
 
+    async fn diff(
+        &self,
+        from: CommitHash,
+        to: CommitHash,
+        detect_renames: bool,
+    ) -> Result<CommitDiff, Error> {
+        helper_3(self, RawRepositoryImplInner::diff, from, to, detect_renames).await
+    }
+
+    async fn get_commit_payload_size(&self, commit_hash: CommitHash) -> Result<usize, Error> {
+        helper_1(
+            self,
+            RawRepositoryImplInner::get_commit_payload_size,
+            commit_hash,
+        )
+        .await
+    }
+
     async fn list_ancestors(
         &self,
         commit_hash: CommitHash,
@@ -465,22 +1060,47 @@ impl RawRepository for RawRepositoryImpl {
         .await
     }
 
-    async fn list_descendants(
+    async fn list_first_parent_ancestors(
         &self,
         commit_hash: CommitHash,
         max: Option<usize>,
     ) -> Result<Vec<CommitHash>, Error> {
         helper_2(
             self,
-            RawRepositoryImplInner::list_descendants,
+            RawRepositoryImplInner::list_first_parent_ancestors,
             commit_hash,
             max,
         )
         .await
     }
 
+    async fn list_descendants(
+        &self,
+        commit_hash: CommitHash,
+        max: Option<usize>,
+    ) -> Result<Vec<CommitHash>, Error> {
+        implementation::list_descendants_in(&*self.child_index().await?, commit_hash, max)
+    }
+
+    async fn descendants_until(
+        &self,
+        from: CommitHash,
+        to: CommitHash,
+    ) -> Result<Vec<CommitHash>, Error> {
+        helper_2(self, RawRepositoryImplInner::descendants_until, from, to).await
+    }
+
     async fn list_children(&self, commit_hash: CommitHash) -> Result<Vec<CommitHash>, Error> {
-        helper_1(self, RawRepositoryImplInner::list_children, commit_hash).await
+        Ok(self
+            .child_index()
+            .await?
+            .get(&commit_hash)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn build_child_index(&self) -> Result<HashMap<CommitHash, Vec<CommitHash>>, Error> {
+        Ok((*self.child_index().await?).clone())
     }
 
     async fn find_merge_base(
@@ -497,10 +1117,57 @@ impl RawRepository for RawRepositoryImpl {
         .await
     }
 
+    async fn try_merge_base(
+        &self,
+        commit_hash1: CommitHash,
+        commit_hash2: CommitHash,
+    ) -> Result<Option<CommitHash>, Error> {
+        helper_2(
+            self,
+            RawRepositoryImplInner::try_merge_base,
+            commit_hash1,
+            commit_hash2,
+        )
+        .await
+    }
+
+    async fn is_ancestor(
+        &self,
+        ancestor: CommitHash,
+        descendant: CommitHash,
+    ) -> Result<bool, Error> {
+        helper_2(
+            self,
+            RawRepositoryImplInner::is_ancestor,
+            ancestor,
+            descendant,
+        )
+        .await
+    }
+
+    async fn find_merge_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<Option<CommitHash>, Error> {
+        helper_1(self, RawRepositoryImplInner::find_merge_commit, commit_hash).await
+    }
+
     async fn read_reserved_state(&self) -> Result<ReservedState, Error> {
         helper_0(self, RawRepositoryImplInner::read_reserved_state).await
     }
 
+    async fn read_reserved_state_at_commit(
+        &self,
+        commit_hash: CommitHash,
+    ) -> Result<ReservedState, Error> {
+        helper_1(
+            self,
+            RawRepositoryImplInner::read_reserved_state_at_commit,
+            commit_hash,
+        )
+        .await
+    }
+
     async fn add_remote(&mut self, remote_name: String, remote_url: String) -> Result<(), Error> {
         helper_2_mut(
             self,
@@ -516,7 +1183,12 @@ impl RawRepository for RawRepositoryImpl {
     }
 
     async fn fetch_all(&mut self) -> Result<(), Error> {
-        helper_0_mut(self, RawRepositoryImplInner::fetch_all).await
+        let retries = self.fetch_retries;
+        helper_1_mut(self, RawRepositoryImplInner::fetch_all, retries).await
+    }
+
+    async fn fetch_all_with_depth(&mut self, depth: usize) -> Result<(), Error> {
+        helper_1_mut(self, RawRepositoryImplInner::fetch_all_with_depth, depth).await
     }
 
     async fn list_remotes(&self) -> Result<Vec<(String, String)>, Error> {
@@ -525,7 +1197,27 @@ impl RawRepository for RawRepositoryImpl {
 
     async fn list_remote_tracking_branches(
         &self,
+        remote_name: Option<String>,
     ) -> Result<Vec<(String, String, CommitHash)>, Error> {
-        helper_0(self, RawRepositoryImplInner::list_remote_tracking_branches).await
+        helper_1(
+            self,
+            RawRepositoryImplInner::list_remote_tracking_branches,
+            remote_name,
+        )
+        .await
+    }
+
+    async fn delete_remote_tracking_branch(
+        &mut self,
+        remote_name: String,
+        branch: Branch,
+    ) -> Result<(), Error> {
+        helper_2_mut(
+            self,
+            RawRepositoryImplInner::delete_remote_tracking_branch,
+            remote_name,
+            branch,
+        )
+        .await
     }
 }
@@ -0,0 +1,70 @@
+//! The file layout of the reserved state in the repository.
+//!
+//! ```text
+//! reserved/
+//! ├── genesis_info.json
+//! ├── members.json
+//! ├── consensus_leader_order.json
+//! └── version
+//! ```
+use simperby_common::reserved::ReservedState;
+use std::collections::HashMap;
+
+/// The directory of the reserved state, relative to the root of the repository.
+pub const RESERVED_DIRECTORY: &str = "reserved";
+
+const GENESIS_INFO_FILE: &str = "genesis_info.json";
+const MEMBERS_FILE: &str = "members.json";
+const CONSENSUS_LEADER_ORDER_FILE: &str = "consensus_leader_order.json";
+const VERSION_FILE: &str = "version";
+
+/// Returns the paths of the reserved files, relative to the root of the repository.
+pub fn get_reserved_file_paths() -> Vec<String> {
+    [
+        GENESIS_INFO_FILE,
+        MEMBERS_FILE,
+        CONSENSUS_LEADER_ORDER_FILE,
+        VERSION_FILE,
+    ]
+    .iter()
+    .map(|file| format!("{}/{}", RESERVED_DIRECTORY, file))
+    .collect()
+}
+
+/// Converts the reserved state into the reserved files.
+///
+/// Returns `(path, content)` where the path is relative to the root of the repository.
+pub fn to_files(reserved_state: &ReservedState) -> Vec<(String, String)> {
+    let contents = vec![
+        serde_json::to_string_pretty(&reserved_state.genesis_info).unwrap(),
+        serde_json::to_string_pretty(&reserved_state.members).unwrap(),
+        serde_json::to_string_pretty(&reserved_state.consensus_leader_order).unwrap(),
+        reserved_state.version.clone(),
+    ];
+    get_reserved_file_paths()
+        .into_iter()
+        .zip(contents)
+        .collect()
+}
+
+/// Reads the reserved state from the reserved files.
+///
+/// `files` is `(path, content)` where the path is relative to the root of the repository.
+pub fn from_files(files: &[(String, String)]) -> Result<ReservedState, String> {
+    let files = files.iter().cloned().collect::<HashMap<_, _>>();
+    let read = |file: &str| {
+        files
+            .get(&format!("{}/{}", RESERVED_DIRECTORY, file))
+            .ok_or_else(|| format!("missing reserved file: {}", file))
+    };
+    let parse_error = |file: &str, e: serde_json::Error| format!("invalid {}: {}", file, e);
+    Ok(ReservedState {
+        genesis_info: serde_json::from_str(read(GENESIS_INFO_FILE)?)
+            .map_err(|e| parse_error(GENESIS_INFO_FILE, e))?,
+        members: serde_json::from_str(read(MEMBERS_FILE)?)
+            .map_err(|e| parse_error(MEMBERS_FILE, e))?,
+        consensus_leader_order: serde_json::from_str(read(CONSENSUS_LEADER_ORDER_FILE)?)
+            .map_err(|e| parse_error(CONSENSUS_LEADER_ORDER_FILE, e))?,
+        version: read(VERSION_FILE)?.clone(),
+    })
+}
@@ -1,5 +1,11 @@
+use crate::raw::implementation::commit_hash_from_bytes;
 use crate::raw::Error;
-use crate::raw::{RawRepository, RawRepositoryImpl};
+use crate::raw::{
+    read, BranchMatch, CommitDiff, FileDiff, ObjectKind, RawRepository, RawRepositoryImpl,
+    StatusKind, MAX_SPARE_READERS,
+};
+use crate::CommitHash;
+use crate::FINALIZED_BRANCH_NAME;
 use std::path::Path;
 use tempfile::TempDir;
 
@@ -7,6 +13,8 @@ const MAIN: &str = "main";
 const BRANCH_A: &str = "branch_a";
 const BRANCH_B: &str = "branch_b";
 const TAG_A: &str = "tag_a";
+const TAG_B: &str = "tag_b";
+const TAG_C: &str = "tag_c";
 
 /// Make a repository which includes one initial commit at "main" branch.
 /// This returns RawRepositoryImpl containing the repository.
@@ -18,6 +26,39 @@ async fn init_repository_with_initial_commit(path: &Path) -> Result<RawRepositor
     Ok(repo)
 }
 
+/// Write the files (or remove them if the content is `None`) and commit them on top of HEAD.
+///
+/// This uses git2 directly, so it must be called while no RawRepositoryImpl is open.
+fn commit_files(path: &Path, message: &str, files: &[(&str, Option<&str>)]) -> git2::Oid {
+    let repo = git2::Repository::open(path).unwrap();
+    let mut index = repo.index().unwrap();
+    for (file, content) in files {
+        match content {
+            Some(content) => {
+                std::fs::write(path.join(file), content).unwrap();
+                index.add_path(Path::new(file)).unwrap();
+            }
+            None => {
+                std::fs::remove_file(path.join(file)).unwrap();
+                index.remove_path(Path::new(file)).unwrap();
+            }
+        }
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = repo.signature().unwrap();
+    let parent = repo.head().unwrap().peel_to_commit().unwrap();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&parent],
+    )
+    .unwrap()
+}
+
 /// Initialize repository with empty commit and empty branch.
 #[tokio::test]
 async fn init() {
@@ -35,6 +76,34 @@ async fn init() {
         .unwrap_err();
 }
 
+/// Initialize repository directly on the `finalized` branch.
+#[tokio::test]
+async fn init_with_initial_branch() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+
+    let mut repo = RawRepositoryImpl::init(
+        path.to_str().unwrap(),
+        "initial",
+        &FINALIZED_BRANCH_NAME.into(),
+    )
+    .await
+    .unwrap();
+    let branch_list = repo.list_branches().await.unwrap();
+    assert_eq!(branch_list, vec![FINALIZED_BRANCH_NAME.to_owned()]);
+
+    // HEAD is attached to the initial branch, so a new commit moves it
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    let finalized_commit_hash = repo
+        .locate_branch(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    assert_eq!(finalized_commit_hash, second_commit_hash);
+}
+
 /// Open existed repository and verifies whether it opens well.
 #[tokio::test]
 async fn open() {
@@ -52,6 +121,29 @@ async fn open() {
     assert_eq!(branch_list_init, branch_list_open);
 }
 
+/// Opens the repository from a nested subdirectory, which only `open_discover()` allows.
+#[tokio::test]
+async fn open_discover() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+
+    let init_repo = init_repository_with_initial_commit(path).await.unwrap();
+    let nested_path = path.join("a").join("b");
+    std::fs::create_dir_all(&nested_path).unwrap();
+    let nested_path = nested_path.to_str().unwrap();
+
+    RawRepositoryImpl::open(nested_path).await.unwrap_err();
+    let discovered_repo = RawRepositoryImpl::open_discover(nested_path).await.unwrap();
+    assert_eq!(
+        discovered_repo.get_git_directory().await.unwrap(),
+        init_repo.get_git_directory().await.unwrap()
+    );
+    assert_eq!(
+        discovered_repo.get_head().await.unwrap(),
+        init_repo.get_head().await.unwrap()
+    );
+}
+
 /*
    c2 (HEAD -> main)      c2 (HEAD -> main, branch_a)     c2 (HEAD -> main)
    |                -->   |                          -->  |
@@ -71,7 +163,8 @@ async fn branch() {
 
     // git branch branch_a
     let head = repo.get_head().await.unwrap();
-    repo.create_branch(BRANCH_A.into(), head).await.unwrap();
+    let branch_a_commit_hash = repo.create_branch(BRANCH_A.into(), head).await.unwrap();
+    assert_eq!(branch_a_commit_hash, head);
 
     // "branch_list" is sorted by the name of the branches in an alphabetic order
     let branch_list = repo.list_branches().await.unwrap();
@@ -87,7 +180,7 @@ async fn branch() {
 
     // Move "branch_a" head to "main" head
     let main_commit_hash = repo.locate_branch(MAIN.into()).await.unwrap();
-    repo.move_branch(BRANCH_A.into(), main_commit_hash)
+    repo.move_branch(BRANCH_A.into(), main_commit_hash, false, "move".to_owned())
         .await
         .unwrap();
     let branch_a_commit_hash = repo.locate_branch(BRANCH_A.into()).await.unwrap();
@@ -102,6 +195,370 @@ async fn branch() {
     repo.delete_branch(MAIN.into()).await.unwrap_err();
 }
 
+/*
+   c2 (HEAD -> main)      c2 (HEAD -> main, branch_a)
+   |                -->   |
+   c1 (branch_a)          c1
+*/
+/// Move "branch_a" forward from c1 to c2 in the fast-forward-only mode,
+/// and then fail to rewind it back to c1.
+#[tokio::test]
+async fn move_branch_fast_forward_only() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    repo.create_branch(BRANCH_A.into(), first_commit_hash)
+        .await
+        .unwrap();
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+
+    // Moving forward is allowed
+    repo.move_branch(
+        BRANCH_A.into(),
+        second_commit_hash,
+        true,
+        "move forward".to_owned(),
+    )
+    .await
+    .unwrap();
+    let branch_a_commit_hash = repo.locate_branch(BRANCH_A.into()).await.unwrap();
+    assert_eq!(branch_a_commit_hash, second_commit_hash);
+
+    // Moving to the same commit is a trivial fast-forward
+    repo.move_branch(
+        BRANCH_A.into(),
+        second_commit_hash,
+        true,
+        "move forward".to_owned(),
+    )
+    .await
+    .unwrap();
+
+    // Rewinding is rejected and the branch stays
+    repo.move_branch(
+        BRANCH_A.into(),
+        first_commit_hash,
+        true,
+        "rewind".to_owned(),
+    )
+    .await
+    .unwrap_err();
+    let branch_a_commit_hash = repo.locate_branch(BRANCH_A.into()).await.unwrap();
+    assert_eq!(branch_a_commit_hash, second_commit_hash);
+
+    // Rewinding is allowed without the fast-forward-only mode
+    repo.move_branch(
+        BRANCH_A.into(),
+        first_commit_hash,
+        false,
+        "rewind".to_owned(),
+    )
+    .await
+    .unwrap();
+    let branch_a_commit_hash = repo.locate_branch(BRANCH_A.into()).await.unwrap();
+    assert_eq!(branch_a_commit_hash, first_commit_hash);
+}
+
+/// Locate a branch that doesn't exist and a symbolic branch, which fail differently.
+#[tokio::test]
+async fn locate_branch_errors() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let error = repo.locate_branch(BRANCH_A.into()).await.unwrap_err();
+    assert!(
+        matches!(&error, Error::InvalidRepository(message) if message.contains(BRANCH_A)),
+        "{}",
+        error
+    );
+
+    git2::Repository::open(path)
+        .unwrap()
+        .reference_symbolic(
+            &format!("refs/heads/{}", BRANCH_B),
+            &format!("refs/heads/{}", MAIN),
+            false,
+            "symbolic",
+        )
+        .unwrap();
+    let error = repo.locate_branch(BRANCH_B.into()).await.unwrap_err();
+    assert!(matches!(error, Error::NoTarget(_)), "{}", error);
+}
+
+/// Open a repository with a stray index lock, which fails until `force_unlock()` removes it.
+#[tokio::test]
+async fn force_unlock() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    drop(init_repository_with_initial_commit(path).await.unwrap());
+    let directory = path.to_str().unwrap();
+    let index_lock = path.join(".git").join("index.lock");
+    std::fs::write(&index_lock, "").unwrap();
+
+    let error = RawRepositoryImpl::open(directory).await.unwrap_err();
+    assert!(
+        matches!(&error, Error::InvalidRepository(message) if message.contains("index.lock")),
+        "{}",
+        error
+    );
+
+    // It refuses while the repository is in use.
+    let simperby_lock =
+        std::fs::File::create(path.join(".git").join(crate::LOCK_FILE_NAME)).unwrap();
+    fs2::FileExt::try_lock_exclusive(&simperby_lock).unwrap();
+    RawRepositoryImpl::force_unlock(directory)
+        .await
+        .unwrap_err();
+    assert!(index_lock.exists());
+    // The lock file is left, but nobody holds the lock on it.
+    drop(simperby_lock);
+
+    assert!(RawRepositoryImpl::force_unlock(directory).await.unwrap());
+    assert!(!RawRepositoryImpl::force_unlock(directory).await.unwrap());
+    let mut repo = RawRepositoryImpl::open(directory).await.unwrap();
+    let head = repo.get_head().await.unwrap();
+    repo.create_commit_with_parents("c1".to_owned(), vec![], vec![head])
+        .await
+        .unwrap();
+}
+
+/// Create branches with invalid names, which fail before reaching libgit2.
+#[tokio::test]
+async fn create_branch_invalid_name() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let repo = init_repository_with_initial_commit(path).await.unwrap();
+    let head = repo.get_head().await.unwrap();
+
+    for name in ["a b", "a..b", "/a", "a.lock", "a/"] {
+        let error = repo.create_branch(name.into(), head).await.unwrap_err();
+        assert!(
+            matches!(&error, Error::InvalidRepository(message)
+                if message == &format!("invalid branch name '{}'", name)),
+            "{}",
+            error
+        );
+    }
+    assert_eq!(repo.list_branches().await.unwrap(), vec![MAIN.to_owned()]);
+
+    repo.create_branch("a-1".into(), head).await.unwrap();
+}
+
+/*
+   c2 (HEAD -> main)      c2 (HEAD -> main)      c2 (HEAD -> main, branch_a)
+   |                -->   |                -->   |
+   c1 (branch_a)          c1 (branch_a)          c1
+*/
+/// Move "branch_a" forward to c2 and back to c1, and read the reflog with the reasons of the moves.
+#[tokio::test]
+async fn read_reflog() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    repo.create_branch(BRANCH_A.into(), first_commit_hash)
+        .await
+        .unwrap();
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    repo.move_branch(
+        BRANCH_A.into(),
+        second_commit_hash,
+        true,
+        "move forward".to_owned(),
+    )
+    .await
+    .unwrap();
+    repo.move_branch(
+        BRANCH_A.into(),
+        first_commit_hash,
+        false,
+        "rewind".to_owned(),
+    )
+    .await
+    .unwrap();
+
+    let reflog = repo.read_reflog(BRANCH_A.into(), Some(2)).await.unwrap();
+    assert_eq!(reflog.len(), 2);
+    assert_eq!(reflog[0].old_commit, Some(second_commit_hash));
+    assert_eq!(reflog[0].new_commit, first_commit_hash);
+    assert_eq!(reflog[1].old_commit, Some(first_commit_hash));
+    assert_eq!(reflog[1].new_commit, second_commit_hash);
+    assert_eq!(reflog[0].message, "rewind");
+    assert_eq!(reflog[1].message, "move forward");
+
+    // The creation is the oldest one.
+    let reflog = repo.read_reflog(BRANCH_A.into(), None).await.unwrap();
+    assert_eq!(reflog.len(), 3);
+    assert_eq!(reflog[2].old_commit, None);
+    assert_eq!(reflog[2].new_commit, first_commit_hash);
+
+    repo.read_reflog(BRANCH_B.into(), None).await.unwrap_err();
+}
+
+/*
+   c2 (HEAD -> main, branch_a)
+   |
+   c1 (branch_b)
+*/
+/// Get the branches of c1 and c2 in both of the matching modes.
+#[tokio::test]
+async fn get_branches() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    repo.create_branch(BRANCH_B.into(), first_commit_hash)
+        .await
+        .unwrap();
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    repo.create_branch(BRANCH_A.into(), second_commit_hash)
+        .await
+        .unwrap();
+
+    // Only "branch_b" points at c1, but every branch contains it
+    let mut branches = repo
+        .get_branches(first_commit_hash, BranchMatch::TipOnly)
+        .await
+        .unwrap();
+    branches.sort();
+    assert_eq!(branches, vec![BRANCH_B.to_owned()]);
+    let mut branches = repo
+        .get_branches(first_commit_hash, BranchMatch::Contains)
+        .await
+        .unwrap();
+    branches.sort();
+    assert_eq!(
+        branches,
+        vec![BRANCH_A.to_owned(), BRANCH_B.to_owned(), MAIN.to_owned()]
+    );
+
+    // "branch_b" doesn't contain c2
+    let mut branches = repo
+        .get_branches(second_commit_hash, BranchMatch::TipOnly)
+        .await
+        .unwrap();
+    branches.sort();
+    assert_eq!(branches, vec![BRANCH_A.to_owned(), MAIN.to_owned()]);
+    let mut branches = repo
+        .get_branches(second_commit_hash, BranchMatch::Contains)
+        .await
+        .unwrap();
+    branches.sort();
+    assert_eq!(branches, vec![BRANCH_A.to_owned(), MAIN.to_owned()]);
+}
+
+/*
+   c2 (HEAD -> main, branch_a)
+   |
+   c1 (branch_b)
+*/
+/// List the branches with their tips, which are the same as what `locate_branch()` returns.
+///
+/// A symbolic branch is left out.
+#[tokio::test]
+async fn list_branches_with_tips() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    repo.create_branch(BRANCH_B.into(), first_commit_hash)
+        .await
+        .unwrap();
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    repo.create_branch(BRANCH_A.into(), second_commit_hash)
+        .await
+        .unwrap();
+    git2::Repository::open(path)
+        .unwrap()
+        .reference_symbolic(
+            "refs/heads/symbolic",
+            &format!("refs/heads/{}", MAIN),
+            false,
+            "symbolic",
+        )
+        .unwrap();
+
+    let mut branches = repo.list_branches_with_tips().await.unwrap();
+    branches.sort();
+    assert_eq!(
+        branches,
+        vec![
+            (BRANCH_A.to_owned(), second_commit_hash),
+            (BRANCH_B.to_owned(), first_commit_hash),
+            (MAIN.to_owned(), second_commit_hash),
+        ]
+    );
+    for (branch, tip) in branches {
+        assert_eq!(repo.locate_branch(branch).await.unwrap(), tip);
+    }
+}
+
+/// Create branches and tags, move all of them into the packed refs, and list them.
+#[tokio::test]
+async fn packed_refs() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    repo.create_branch(BRANCH_A.into(), first_commit_hash)
+        .await
+        .unwrap();
+    repo.create_branch(BRANCH_B.into(), first_commit_hash)
+        .await
+        .unwrap();
+    repo.create_tag(TAG_A.into(), first_commit_hash, None)
+        .await
+        .unwrap();
+
+    // Same as `git pack-refs --all`, which `git2` doesn't provide.
+    let oid = git2::Oid::from_bytes(&first_commit_hash.hash).unwrap();
+    let mut packed_refs = "# pack-refs with: peeled fully-peeled sorted \n".to_owned();
+    for reference in [
+        format!("refs/heads/{}", BRANCH_A),
+        format!("refs/heads/{}", BRANCH_B),
+        format!("refs/heads/{}", MAIN),
+        format!("refs/tags/{}", TAG_A),
+    ] {
+        packed_refs.push_str(&format!("{} {}\n", oid, reference));
+        std::fs::remove_file(path.join(".git").join(&reference)).unwrap();
+    }
+    std::fs::write(path.join(".git/packed-refs"), packed_refs).unwrap();
+
+    let repo = RawRepositoryImpl::open(path.to_str().unwrap())
+        .await
+        .unwrap();
+    let mut branches = repo.list_branches().await.unwrap();
+    branches.sort();
+    assert_eq!(
+        branches,
+        vec![BRANCH_A.to_owned(), BRANCH_B.to_owned(), MAIN.to_owned()]
+    );
+    assert_eq!(repo.list_tags().await.unwrap(), vec![TAG_A.to_owned()]);
+    assert_eq!(
+        repo.locate_branch(BRANCH_A.into()).await.unwrap(),
+        first_commit_hash
+    );
+}
+
 /// Create a tag and remove it.
 #[tokio::test]
 async fn tag() {
@@ -115,7 +572,7 @@ async fn tag() {
 
     // Create "tag_1" at first commit
     let first_commit_hash = repo.locate_branch(MAIN.into()).await.unwrap();
-    repo.create_tag(TAG_A.into(), first_commit_hash)
+    repo.create_tag(TAG_A.into(), first_commit_hash, None)
         .await
         .unwrap();
     let tag_list = repo.list_tags().await.unwrap();
@@ -130,6 +587,113 @@ async fn tag() {
     assert!(tag_list.is_empty());
 }
 
+/// Create an annotated tag and a lightweight tag, and read their messages.
+#[tokio::test]
+async fn read_tag_message() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    let git2_repo = git2::Repository::open(path).unwrap();
+    let object = git2_repo
+        .find_object(
+            git2::Oid::from_bytes(&first_commit_hash.hash).unwrap(),
+            None,
+        )
+        .unwrap();
+    let signature = git2_repo.signature().unwrap();
+    git2_repo
+        .tag("vote-0", &object, &signature, "signature\n", false)
+        .unwrap();
+    repo.create_tag(TAG_A.into(), first_commit_hash, None)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        repo.read_tag_message("vote-0".into()).await.unwrap(),
+        Some("signature\n".to_owned())
+    );
+    assert_eq!(repo.read_tag_message(TAG_A.into()).await.unwrap(), None);
+    assert!(repo.read_tag_message(TAG_B.into()).await.is_err());
+}
+
+/*
+    c2 (HEAD -> main, tag_b, tag_c)
+    |
+    c1 (tag_a)
+*/
+/// Create three tags and list them with their targets at once.
+#[tokio::test]
+async fn tag_with_targets() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    repo.create_tag(TAG_A.into(), first_commit_hash, None)
+        .await
+        .unwrap();
+    repo.create_tag(TAG_B.into(), second_commit_hash, None)
+        .await
+        .unwrap();
+    repo.create_tag(TAG_C.into(), second_commit_hash, None)
+        .await
+        .unwrap();
+
+    let tag_list = repo.list_tags_with_targets().await.unwrap();
+    assert_eq!(
+        tag_list,
+        vec![
+            (TAG_A.to_owned(), first_commit_hash),
+            (TAG_B.to_owned(), second_commit_hash),
+            (TAG_C.to_owned(), second_commit_hash),
+        ]
+    );
+}
+
+/// Tags a blob and a tree, and checks that it fails only when a commit is expected.
+#[tokio::test]
+async fn tag_expected_kind() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let commit_hash = repo.get_head().await.unwrap();
+    let git2_repo = git2::Repository::open(path).unwrap();
+    let blob_oid = git2_repo.blob(b"not a commit").unwrap();
+    let blob_hash = commit_hash_from_bytes(blob_oid.as_bytes()).unwrap();
+    let tree_oid = git2_repo
+        .find_commit(git2::Oid::from_bytes(&commit_hash.hash).unwrap())
+        .unwrap()
+        .tree_id();
+    let tree_hash = commit_hash_from_bytes(tree_oid.as_bytes()).unwrap();
+
+    for hash in [blob_hash, tree_hash] {
+        let error = repo
+            .create_tag(TAG_A.into(), hash, Some(ObjectKind::Commit))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidRepository(_)), "{}", error);
+    }
+    assert!(repo.list_tags().await.unwrap().is_empty());
+
+    repo.create_tag(TAG_A.into(), blob_hash, Some(ObjectKind::Blob))
+        .await
+        .unwrap();
+    repo.create_tag(TAG_B.into(), tree_hash, None)
+        .await
+        .unwrap();
+    repo.create_tag(TAG_C.into(), commit_hash, Some(ObjectKind::Commit))
+        .await
+        .unwrap();
+    assert_eq!(repo.locate_tag(TAG_C.into()).await.unwrap(), commit_hash);
+}
+
 /*
     c3 (HEAD -> main)   c3 (HEAD -> main)     c3 (main)                   c3 (HEAD -> main)
     |                   |                     |                           |
@@ -217,29 +781,145 @@ async fn checkout_detach() {
     // assert_eq!(cur_head_name, "HEAD");
 }
 
-/*
-    c3 (HEAD -> main)
-    |
-    c2
-    |
-    c1
-*/
-/// Get initial commit.
+/// Modify, delete and create files in the working tree, and check their statuses.
 #[tokio::test]
-async fn initial_commit() {
+async fn working_tree_status() {
     let td = TempDir::new().unwrap();
     let path = td.path();
-    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+    init_repository_with_initial_commit(path).await.unwrap();
+    commit_files(path, "c2", &[("a", Some("a")), ("b", Some("b"))]);
 
-    // Create branch_a, branch_b and commits
-    let first_commit_hash = repo.locate_branch(MAIN.into()).await.unwrap();
-    repo.create_commit("second".to_owned(), Some("".to_owned()))
-        .await
-        .unwrap();
-    repo.create_commit("third".to_owned(), Some("".to_owned()))
+    let repo = RawRepositoryImpl::open(path.to_str().unwrap())
         .await
         .unwrap();
+    assert!(repo.working_tree_status().await.unwrap().is_empty());
 
+    std::fs::write(path.join("a"), "a2").unwrap();
+    std::fs::remove_file(path.join("b")).unwrap();
+    std::fs::write(path.join("c"), "c").unwrap();
+    assert_eq!(
+        repo.working_tree_status().await.unwrap(),
+        vec![
+            ("a".to_owned(), StatusKind::Modified),
+            ("b".to_owned(), StatusKind::Deleted),
+            ("c".to_owned(), StatusKind::Untracked),
+        ]
+    );
+}
+
+/// Dirty two directories, and clean them one by one.
+#[tokio::test]
+async fn checkout_clean_paths() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    init_repository_with_initial_commit(path).await.unwrap();
+    std::fs::create_dir_all(path.join("reserved")).unwrap();
+    std::fs::create_dir_all(path.join("scratch")).unwrap();
+    commit_files(
+        path,
+        "c2",
+        &[("reserved/a", Some("a")), ("scratch/b", Some("b"))],
+    );
+
+    let mut repo = RawRepositoryImpl::open(path.to_str().unwrap())
+        .await
+        .unwrap();
+    std::fs::write(path.join("reserved/a"), "a2").unwrap();
+    std::fs::write(path.join("reserved/c"), "c").unwrap();
+    std::fs::write(path.join("scratch/b"), "b2").unwrap();
+    std::fs::write(path.join("scratch/d"), "d").unwrap();
+
+    repo.checkout_clean(Some(vec!["reserved".to_owned()]))
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.working_tree_status().await.unwrap(),
+        vec![
+            ("scratch/b".to_owned(), StatusKind::Modified),
+            ("scratch/d".to_owned(), StatusKind::Untracked),
+        ]
+    );
+    assert_eq!(
+        std::fs::read_to_string(path.join("reserved/a")).unwrap(),
+        "a"
+    );
+
+    repo.checkout_clean(None).await.unwrap();
+    assert!(repo.working_tree_status().await.unwrap().is_empty());
+}
+
+/*
+    c3 (HEAD -> main)
+    |
+    c2
+    |
+    c1
+*/
+/// Get initial commit.
+#[tokio::test]
+async fn initial_commit() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    // Create branch_a, branch_b and commits
+    let first_commit_hash = repo.locate_branch(MAIN.into()).await.unwrap();
+    repo.create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    repo.create_commit("third".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+
+    let initial_commit_hash = repo.get_initial_commit().await.unwrap();
+    assert_eq!(initial_commit_hash, first_commit_hash);
+}
+
+/*
+    c3 (HEAD -> main)
+    |
+    c2 (committed in 1970)
+    |
+    c1
+*/
+/// Get the initial commit, even though c2 is older than c1 by its timestamp.
+#[tokio::test]
+async fn initial_commit_with_skewed_time() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let repo = init_repository_with_initial_commit(path).await.unwrap();
+    let first_commit_hash = repo.get_head().await.unwrap();
+    drop(repo);
+
+    let git2_repo = git2::Repository::open(path).unwrap();
+    let c1 = git2_repo.head().unwrap().peel_to_commit().unwrap();
+    let old_signature = git2::Signature::new("name", "email", &git2::Time::new(1000, 0)).unwrap();
+    let c2 = git2_repo
+        .commit(
+            Some("HEAD"),
+            &old_signature,
+            &old_signature,
+            "second",
+            &c1.tree().unwrap(),
+            &[&c1],
+        )
+        .unwrap();
+    let c2 = git2_repo.find_commit(c2).unwrap();
+    let signature = git2_repo.signature().unwrap();
+    git2_repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "third",
+            &c2.tree().unwrap(),
+            &[&c2],
+        )
+        .unwrap();
+
+    let repo = RawRepositoryImpl::open(path.to_str().unwrap())
+        .await
+        .unwrap();
     let initial_commit_hash = repo.get_initial_commit().await.unwrap();
     assert_eq!(initial_commit_hash, first_commit_hash);
 }
@@ -291,74 +971,1278 @@ async fn ancestor() {
 }
 
 /*
-    c3 (HEAD -> branch_b)
-     |  c2 (branch_a)
-     | /
-    c1 (main)
+    c4
+    | \
+    c2  c3
+    | /
+    c1 (HEAD -> main)
 */
-/// Make three commits at different branches and the merge base of (c2,c3) would be c1.
+/// Build a merge commit c4 without touching any branch, and fail to list its ancestors.
 #[tokio::test]
-async fn merge_base() {
+async fn create_commit_with_parents() {
     let td = TempDir::new().unwrap();
     let path = td.path();
     let mut repo = init_repository_with_initial_commit(path).await.unwrap();
 
-    // Create "branch_a" and "branch_b" branches at c1
-    {
-        let commit_hash1 = repo.locate_branch(MAIN.into()).await.unwrap();
-        repo.create_branch(BRANCH_A.into(), commit_hash1)
-            .await
-            .unwrap();
-        repo.create_branch(BRANCH_B.into(), commit_hash1)
+    let c1 = repo.get_head().await.unwrap();
+    let c2 = repo
+        .create_commit_with_parents(
+            "c2".to_owned(),
+            vec![("a".to_owned(), b"a".to_vec())],
+            vec![c1],
+        )
+        .await
+        .unwrap();
+    let c3 = repo
+        .create_commit_with_parents(
+            "c3".to_owned(),
+            vec![("dir/b".to_owned(), b"b".to_vec())],
+            vec![c1],
+        )
+        .await
+        .unwrap();
+    let c4 = repo
+        .create_commit_with_parents("c4".to_owned(), vec![], vec![c2, c3])
+        .await
+        .unwrap();
+    assert_eq!(repo.get_head().await.unwrap(), c1);
+
+    // The tree of c4 is the one of its first parent.
+    let diff = repo.diff(c1, c4, false).await.unwrap();
+    assert_eq!(diff.files, vec![FileDiff::Added("a".to_owned())]);
+    repo.list_ancestors(c2, None).await.unwrap();
+    let error = repo.list_ancestors(c4, None).await.unwrap_err();
+    assert!(matches!(error, Error::InvalidRepository(_)), "{}", error);
+}
+
+/*
+    c3
+    |
+    c2
+    |
+    c1 (HEAD -> main)
+*/
+/// Get the payload sizes of c2, which only has a message,
+/// and of c3, which also adds a reserved file and a non-reserved one.
+#[tokio::test]
+async fn get_commit_payload_size() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let c1 = repo.get_head().await.unwrap();
+    let message = "c2\n\nbody".to_owned();
+    let c2 = repo
+        .create_commit_with_parents(message.clone(), vec![], vec![c1])
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.get_commit_payload_size(c2).await.unwrap(),
+        message.len()
+    );
+
+    let c3 = repo
+        .create_commit_with_parents(
+            "c3".to_owned(),
+            vec![
+                ("reserved/a".to_owned(), b"abcd".to_vec()),
+                ("b".to_owned(), b"ignored".to_vec()),
+            ],
+            vec![c2],
+        )
+        .await
+        .unwrap();
+    assert_eq!(repo.get_commit_payload_size(c3).await.unwrap(), 2 + 4);
+}
+
+/*
+    c5
+    |
+    c4
+    | \
+    c2  c3
+    | /
+    c1 (HEAD -> main)
+*/
+/// Find the merge commit c4 from c5 and from itself, and find none from c2.
+#[tokio::test]
+async fn find_merge_commit() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let c1 = repo.get_head().await.unwrap();
+    let c2 = repo
+        .create_commit_with_parents("c2".to_owned(), vec![], vec![c1])
+        .await
+        .unwrap();
+    let c3 = repo
+        .create_commit_with_parents("c3".to_owned(), vec![], vec![c1])
+        .await
+        .unwrap();
+    let c4 = repo
+        .create_commit_with_parents("c4".to_owned(), vec![], vec![c2, c3])
+        .await
+        .unwrap();
+    let c5 = repo
+        .create_commit_with_parents("c5".to_owned(), vec![], vec![c4])
+        .await
+        .unwrap();
+
+    assert_eq!(repo.find_merge_commit(c5).await.unwrap(), Some(c4));
+    assert_eq!(repo.find_merge_commit(c4).await.unwrap(), Some(c4));
+    assert_eq!(repo.find_merge_commit(c2).await.unwrap(), None);
+}
+
+/// Open a repository without any commits, and fail to get its HEAD.
+#[tokio::test]
+async fn get_head_empty_repository() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    git2::Repository::init(path).unwrap();
+
+    let repo = RawRepositoryImpl::open(path.to_str().unwrap())
+        .await
+        .unwrap();
+    let error = repo.get_head().await.unwrap_err();
+    assert!(
+        matches!(&error, Error::InvalidRepository(message) if message == "repository has no commits yet"),
+        "{}",
+        error
+    );
+}
+
+/*
+    c4 (HEAD -> main)
+    | \
+    c2  c3
+    | /
+    c1
+*/
+/// Get the first-parent ancestors of the merge commit c4, which are [c2, c1].
+#[tokio::test]
+async fn first_parent_ancestor() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    init_repository_with_initial_commit(path).await.unwrap();
+
+    let c2 = commit_files(path, "c2", &[("a", Some("a"))]);
+    let git2_repo = git2::Repository::open(path).unwrap();
+    let c2 = git2_repo.find_commit(c2).unwrap();
+    let c1 = c2.parent(0).unwrap();
+    let signature = git2_repo.signature().unwrap();
+    let c3 = git2_repo
+        .commit(
+            None,
+            &signature,
+            &signature,
+            "c3",
+            &c1.tree().unwrap(),
+            &[&c1],
+        )
+        .unwrap();
+    let c3 = git2_repo.find_commit(c3).unwrap();
+    let c4 = git2_repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "c4",
+            &c2.tree().unwrap(),
+            &[&c2, &c3],
+        )
+        .unwrap();
+    let to_commit_hash = |oid: git2::Oid| CommitHash {
+        hash: oid.as_bytes().try_into().unwrap(),
+    };
+
+    let repo = RawRepositoryImpl::open(path.to_str().unwrap())
+        .await
+        .unwrap();
+    let ancestors = repo
+        .list_first_parent_ancestors(to_commit_hash(c4), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        ancestors,
+        vec![to_commit_hash(c2.id()), to_commit_hash(c1.id())]
+    );
+    let ancestors = repo
+        .list_first_parent_ancestors(to_commit_hash(c4), Some(1))
+        .await
+        .unwrap();
+    assert_eq!(ancestors, vec![to_commit_hash(c2.id())]);
+}
+
+/// Make 17 commits and resolve their prefixes, then write blobs until two of them
+/// share a 4-digit prefix and resolve ambiguous, unknown and non-commit prefixes.
+#[tokio::test]
+async fn resolve_short_hash() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let mut commit_hashes = vec![repo.get_head().await.unwrap()];
+    for i in 0..16 {
+        let commit_hash = repo
+            .create_commit(format!("commit {}", i), Some("".to_owned()))
             .await
             .unwrap();
+        commit_hashes.push(commit_hash);
     }
-    // Make a commit at "branch_a" branch
-    repo.checkout(BRANCH_A.into()).await.unwrap();
-    let _commit = repo
-        .create_commit("branch_a".to_owned(), Some("".to_owned()))
+    let full_hashes = commit_hashes
+        .iter()
+        .map(|commit_hash| format!("{:#}", commit_hash))
+        .collect::<Vec<_>>();
+
+    // The abbreviated, the 4-digit and the full hashes resolve to the commit
+    for (commit_hash, full_hash) in commit_hashes.iter().zip(full_hashes.iter()) {
+        let short_hash = commit_hash.to_string();
+        assert!(full_hash.starts_with(&short_hash));
+        assert_eq!(
+            repo.resolve_short_hash(short_hash).await.unwrap(),
+            *commit_hash
+        );
+        assert_eq!(
+            repo.resolve_short_hash(full_hash.to_uppercase())
+                .await
+                .unwrap(),
+            *commit_hash
+        );
+        if full_hashes
+            .iter()
+            .filter(|h| h[..4] == full_hash[..4])
+            .count()
+            == 1
+        {
+            assert_eq!(
+                repo.resolve_short_hash(full_hash[..4].to_owned())
+                    .await
+                    .unwrap(),
+                *commit_hash
+            );
+        }
+    }
+
+    // There are 65536 prefixes of four hex digits, so a collision comes after a few hundred blobs
+    let git2_repo = git2::Repository::open(path).unwrap();
+    let mut blobs = std::collections::HashMap::new();
+    let (blob, other_blob) = (0..)
+        .find_map(|i| {
+            let oid = git2_repo.blob(format!("blob {}", i).as_bytes()).unwrap();
+            blobs
+                .insert(oid.to_string()[..4].to_owned(), oid)
+                .map(|other| (oid, other))
+        })
+        .unwrap();
+    let error = repo
+        .resolve_short_hash(blob.to_string()[..4].to_owned())
         .await
+        .unwrap_err();
+    assert!(matches!(error, Error::AmbiguousPrefix(_)), "{}", error);
+
+    // A longer prefix of the blob is unique, but it is not a commit
+    let unique_len = (5..=40)
+        .find(|&len| blob.to_string()[..len] != other_blob.to_string()[..len])
         .unwrap();
-    // Make a commit at "branch_b" branch
-    repo.checkout(BRANCH_B.into()).await.unwrap();
-    let _commit = repo
-        .create_commit("branch_b".to_owned(), Some("".to_owned()))
+    let error = repo
+        .resolve_short_hash(blob.to_string()[..unique_len].to_owned())
         .await
+        .unwrap_err();
+    assert!(matches!(error, Error::InvalidRepository(_)), "{}", error);
+
+    // Not every prefix of four hex digits is taken
+    let mut objects = Vec::new();
+    git2_repo
+        .odb()
+        .unwrap()
+        .foreach(|oid| {
+            objects.push(oid.to_string());
+            true
+        })
         .unwrap();
+    let unknown_prefix = (0..=u16::MAX)
+        .map(|n| format!("{:04x}", n))
+        .find(|prefix| !objects.iter().any(|h| h.starts_with(prefix)))
+        .unwrap();
+    let error = repo.resolve_short_hash(unknown_prefix).await.unwrap_err();
+    assert!(matches!(error, Error::InvalidRepository(_)), "{}", error);
 
-    // Make merge base of (c2,c3)
-    let commit_hash_main = repo.locate_branch(MAIN.into()).await.unwrap();
-    let commit_hash_a = repo.locate_branch(BRANCH_A.into()).await.unwrap();
-    let commit_hash_b = repo.locate_branch(BRANCH_B.into()).await.unwrap();
-    let merge_base = repo
-        .find_merge_base(commit_hash_a, commit_hash_b)
+    // Too short
+    repo.resolve_short_hash(full_hashes[0][..3].to_owned())
         .await
-        .unwrap();
+        .unwrap_err();
 
-    // The merge base of (c2,c3) should be c1
-    assert_eq!(merge_base, commit_hash_main);
+    // Not a hex
+    repo.resolve_short_hash(MAIN.to_owned()).await.unwrap_err();
 }
 
-/// add remote repository and remove it.
+/*
+    c4 (HEAD -> main)
+    |
+    c3  c5 (branch_a)
+    |  /
+    c2
+    |
+    c1
+*/
+/// Get the path from c1 to c4, and fail to get the ones to non-descendants.
 #[tokio::test]
-async fn remote() {
+async fn descendants_until() {
     let td = TempDir::new().unwrap();
     let path = td.path();
     let mut repo = init_repository_with_initial_commit(path).await.unwrap();
 
-    // Add dummy remote
-    repo.add_remote("origin".to_owned(), "/path/to/nowhere".to_owned())
+    let first_commit_hash = repo.get_head().await.unwrap();
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    let third_commit_hash = repo
+        .create_commit("third".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    let fourth_commit_hash = repo
+        .create_commit("fourth".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    repo.create_branch(BRANCH_A.into(), second_commit_hash)
+        .await
+        .unwrap();
+    let fifth_commit_hash = repo
+        .create_commit_on_branch(BRANCH_A.into(), "fifth".to_owned(), None)
         .await
         .unwrap();
 
-    let remote_list = repo.list_remotes().await.unwrap();
+    let descendants = repo
+        .descendants_until(first_commit_hash, fourth_commit_hash)
+        .await
+        .unwrap();
     assert_eq!(
-        remote_list,
-        vec![("origin".to_owned(), "/path/to/nowhere".to_owned())]
+        descendants,
+        vec![second_commit_hash, third_commit_hash, fourth_commit_hash]
     );
+    let descendants = repo
+        .descendants_until(fourth_commit_hash, fourth_commit_hash)
+        .await
+        .unwrap();
+    assert!(descendants.is_empty());
 
-    // Remove dummy remote
-    repo.remove_remote("origin".to_owned()).await.unwrap();
-    let remote_list = repo.list_remotes().await.unwrap();
-    assert!(remote_list.is_empty());
+    // c5 is not a descendant of c3, and c1 is not a descendant of c4
+    repo.descendants_until(third_commit_hash, fifth_commit_hash)
+        .await
+        .unwrap_err();
+    repo.descendants_until(fourth_commit_hash, first_commit_hash)
+        .await
+        .unwrap_err();
+}
+
+/*
+    c4 (HEAD -> main)
+    |
+    c3  c5 (branch_a)
+    |  /
+    c2
+    |
+    c1
+*/
+/// Build the child index and check that it agrees with `list_children()` and `list_descendants()`,
+/// also after a reference moves.
+#[tokio::test]
+async fn build_child_index() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    let third_commit_hash = repo
+        .create_commit("third".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    let fourth_commit_hash = repo
+        .create_commit("fourth".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    repo.create_branch(BRANCH_A.into(), second_commit_hash)
+        .await
+        .unwrap();
+    let fifth_commit_hash = repo
+        .create_commit_on_branch(BRANCH_A.into(), "fifth".to_owned(), None)
+        .await
+        .unwrap();
+
+    let child_index = repo.build_child_index().await.unwrap();
+    let mut children_of_second = vec![third_commit_hash, fifth_commit_hash];
+    children_of_second.sort();
+    assert_eq!(child_index[&second_commit_hash], children_of_second);
+    for commit_hash in [
+        first_commit_hash,
+        second_commit_hash,
+        third_commit_hash,
+        fourth_commit_hash,
+        fifth_commit_hash,
+    ] {
+        assert_eq!(
+            repo.list_children(commit_hash).await.unwrap(),
+            child_index.get(&commit_hash).cloned().unwrap_or_default()
+        );
+    }
+    assert!(!child_index.contains_key(&fourth_commit_hash));
+
+    assert_eq!(
+        repo.list_descendants(third_commit_hash, None)
+            .await
+            .unwrap(),
+        vec![fourth_commit_hash]
+    );
+    assert_eq!(
+        repo.list_descendants(first_commit_hash, Some(1))
+            .await
+            .unwrap(),
+        vec![second_commit_hash]
+    );
+    // c2 has two children.
+    repo.list_descendants(first_commit_hash, None)
+        .await
+        .unwrap_err();
+
+    // The index is reused until a reference moves.
+    let cached = repo.child_index().await.unwrap();
+    assert!(std::sync::Arc::ptr_eq(
+        &cached,
+        &repo.child_index().await.unwrap()
+    ));
+    let sixth_commit_hash = repo
+        .create_commit_on_branch(BRANCH_A.into(), "sixth".to_owned(), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.list_children(fifth_commit_hash).await.unwrap(),
+        vec![sixth_commit_hash]
+    );
+}
+
+/*
+    c2 (branch_a)
+    |
+    c1 (HEAD -> main)
+*/
+/// Create a commit on "branch_a" while "main" is checked out; HEAD stays on c1.
+#[tokio::test]
+async fn create_commit_on_branch() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    repo.create_branch(BRANCH_A.into(), first_commit_hash)
+        .await
+        .unwrap();
+    let second_commit_hash = repo
+        .create_commit_on_branch(BRANCH_A.into(), "second".to_owned(), None)
+        .await
+        .unwrap();
+
+    let branch_a_commit_hash = repo.locate_branch(BRANCH_A.into()).await.unwrap();
+    assert_eq!(branch_a_commit_hash, second_commit_hash);
+    let ancestors = repo.list_ancestors(second_commit_hash, None).await.unwrap();
+    assert_eq!(ancestors, vec![first_commit_hash]);
+    let main_commit_hash = repo.locate_branch(MAIN.into()).await.unwrap();
+    assert_eq!(main_commit_hash, first_commit_hash);
+    let head = repo.get_head().await.unwrap();
+    assert_eq!(head, first_commit_hash);
+
+    // The branch must exist
+    repo.create_commit_on_branch(BRANCH_B.into(), "third".to_owned(), None)
+        .await
+        .unwrap_err();
+}
+
+/*
+    c4 (HEAD -> main): remove b
+    |
+    c3: modify a, add c
+    |
+    c2: add a, b
+    |
+    c1
+*/
+/// Diff c2 and c4, which are not adjacent, and get the aggregated changes.
+#[tokio::test]
+async fn diff() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    init_repository_with_initial_commit(path).await.unwrap();
+
+    let c2 = commit_files(path, "c2", &[("a", Some("a")), ("b", Some("b"))]);
+    commit_files(path, "c3", &[("a", Some("a2")), ("c", Some("c"))]);
+    let c4 = commit_files(path, "c4", &[("b", None)]);
+    let to_commit_hash = |oid: git2::Oid| CommitHash {
+        hash: oid.as_bytes().try_into().unwrap(),
+    };
+
+    let repo = RawRepositoryImpl::open(path.to_str().unwrap())
+        .await
+        .unwrap();
+    let diff = repo
+        .diff(to_commit_hash(c2), to_commit_hash(c4), false)
+        .await
+        .unwrap();
+    assert_eq!(
+        diff,
+        CommitDiff {
+            files: vec![
+                FileDiff::Modified("a".to_owned()),
+                FileDiff::Deleted("b".to_owned()),
+                FileDiff::Added("c".to_owned()),
+            ]
+        }
+    );
+
+    // The same commit has no diff
+    let diff = repo
+        .diff(to_commit_hash(c4), to_commit_hash(c4), false)
+        .await
+        .unwrap();
+    assert_eq!(diff, CommitDiff::default());
+}
+
+/*
+    c3 (HEAD -> main): rename a to b
+    |
+    c2: add a
+    |
+    c1
+*/
+/// Diff c2 and c3 with and without the rename detection.
+#[tokio::test]
+async fn diff_renames() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    init_repository_with_initial_commit(path).await.unwrap();
+
+    let content = "the content of the file, which is long enough to be compared";
+    let c2 = commit_files(path, "c2", &[("a", Some(content))]);
+    let c3 = commit_files(path, "c3", &[("a", None), ("b", Some(content))]);
+    let to_commit_hash = |oid: git2::Oid| CommitHash {
+        hash: oid.as_bytes().try_into().unwrap(),
+    };
+
+    let repo = RawRepositoryImpl::open(path.to_str().unwrap())
+        .await
+        .unwrap();
+    let diff = repo
+        .diff(to_commit_hash(c2), to_commit_hash(c3), false)
+        .await
+        .unwrap();
+    assert_eq!(
+        diff.files,
+        vec![
+            FileDiff::Deleted("a".to_owned()),
+            FileDiff::Added("b".to_owned()),
+        ]
+    );
+    let diff = repo
+        .diff(to_commit_hash(c2), to_commit_hash(c3), true)
+        .await
+        .unwrap();
+    assert_eq!(
+        diff.files,
+        vec![FileDiff::Renamed("a".to_owned(), "b".to_owned())]
+    );
+}
+
+/*
+    c3 (HEAD -> branch_b)
+     |  c2 (branch_a)
+     | /
+    c1 (main)
+*/
+/// Make three commits at different branches and the merge base of (c2,c3) would be c1.
+#[tokio::test]
+async fn merge_base() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    // Create "branch_a" and "branch_b" branches at c1
+    {
+        let commit_hash1 = repo.locate_branch(MAIN.into()).await.unwrap();
+        repo.create_branch(BRANCH_A.into(), commit_hash1)
+            .await
+            .unwrap();
+        repo.create_branch(BRANCH_B.into(), commit_hash1)
+            .await
+            .unwrap();
+    }
+    // Make a commit at "branch_a" branch
+    repo.checkout(BRANCH_A.into()).await.unwrap();
+    let _commit = repo
+        .create_commit("branch_a".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    // Make a commit at "branch_b" branch
+    repo.checkout(BRANCH_B.into()).await.unwrap();
+    let _commit = repo
+        .create_commit("branch_b".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+
+    // Make merge base of (c2,c3)
+    let commit_hash_main = repo.locate_branch(MAIN.into()).await.unwrap();
+    let commit_hash_a = repo.locate_branch(BRANCH_A.into()).await.unwrap();
+    let commit_hash_b = repo.locate_branch(BRANCH_B.into()).await.unwrap();
+    let merge_base = repo
+        .find_merge_base(commit_hash_a, commit_hash_b)
+        .await
+        .unwrap();
+
+    // The merge base of (c2,c3) should be c1
+    assert_eq!(merge_base, commit_hash_main);
+}
+
+/*
+    c2 (HEAD -> main)   c3 (no parent)
+    |
+    c1
+*/
+/// Get no merge base of two unrelated commits, unlike the ones of the related commits.
+#[tokio::test]
+async fn try_merge_base() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let first_commit_hash = repo.get_head().await.unwrap();
+    let second_commit_hash = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    let unrelated_commit_hash = repo
+        .create_commit_with_parents("unrelated".to_owned(), vec![], vec![])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        repo.try_merge_base(second_commit_hash, unrelated_commit_hash)
+            .await
+            .unwrap(),
+        None
+    );
+    repo.find_merge_base(second_commit_hash, unrelated_commit_hash)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        repo.try_merge_base(second_commit_hash, first_commit_hash)
+            .await
+            .unwrap(),
+        Some(first_commit_hash)
+    );
+    // A commit that doesn't exist is still an error.
+    repo.try_merge_base(second_commit_hash, CommitHash { hash: [1; 20] })
+        .await
+        .unwrap_err();
+}
+
+/*
+    c2 (HEAD -> main)
+    |
+    c1
+*/
+/// Check the ancestry of two linear commits; the merge base is the ancestor itself.
+#[tokio::test]
+async fn is_ancestor() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    let c1 = repo.get_head().await.unwrap();
+    let c2 = repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+
+    assert!(repo.is_ancestor(c1, c2).await.unwrap());
+    assert!(!repo.is_ancestor(c2, c1).await.unwrap());
+    assert!(repo.is_ancestor(c1, c1).await.unwrap());
+    assert_eq!(repo.find_merge_base(c1, c2).await.unwrap(), c1);
+    assert_eq!(repo.find_merge_base(c2, c2).await.unwrap(), c2);
+}
+
+/// add remote repository and remove it.
+#[tokio::test]
+async fn remote() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    // Add dummy remote
+    repo.add_remote("origin".to_owned(), "/path/to/nowhere".to_owned())
+        .await
+        .unwrap();
+
+    let remote_list = repo.list_remotes().await.unwrap();
+    assert_eq!(
+        remote_list,
+        vec![("origin".to_owned(), "/path/to/nowhere".to_owned())]
+    );
+
+    // Remove dummy remote
+    repo.remove_remote("origin".to_owned()).await.unwrap();
+    let remote_list = repo.list_remotes().await.unwrap();
+    assert!(remote_list.is_empty());
+}
+
+/// Add the same remote twice with different URLs; the second one updates the URL.
+#[tokio::test]
+async fn add_remote_twice() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    repo.add_remote("origin".to_owned(), "/path/to/nowhere".to_owned())
+        .await
+        .unwrap();
+    repo.add_remote("origin".to_owned(), "/path/to/elsewhere".to_owned())
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.list_remotes().await.unwrap(),
+        vec![("origin".to_owned(), "/path/to/elsewhere".to_owned())]
+    );
+}
+
+/// Remove the same remote twice; the second removal is a no-op.
+#[tokio::test]
+async fn remove_remote_twice() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+
+    repo.add_remote("origin".to_owned(), "/path/to/nowhere".to_owned())
+        .await
+        .unwrap();
+    repo.remove_remote("origin".to_owned()).await.unwrap();
+    repo.remove_remote("origin".to_owned()).await.unwrap();
+    assert!(repo.list_remotes().await.unwrap().is_empty());
+}
+
+/// Fetch "branch_a" from a remote repository and checkout to it by its name.
+#[tokio::test]
+async fn checkout_remote_tracking_branch() {
+    let td_remote = TempDir::new().unwrap();
+    let remote_path = td_remote.path();
+    let mut remote_repo = init_repository_with_initial_commit(remote_path)
+        .await
+        .unwrap();
+    let second_commit_hash = remote_repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    remote_repo
+        .create_branch(BRANCH_A.into(), second_commit_hash)
+        .await
+        .unwrap();
+
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+    repo.add_remote(
+        "origin".to_owned(),
+        remote_path.to_str().unwrap().to_owned(),
+    )
+    .await
+    .unwrap();
+    // Not fetched yet
+    repo.checkout(BRANCH_A.into()).await.unwrap_err();
+
+    repo.fetch_all().await.unwrap();
+    repo.checkout(BRANCH_A.into()).await.unwrap();
+    assert_eq!(repo.get_head().await.unwrap(), second_commit_hash);
+    assert_eq!(
+        repo.locate_branch(BRANCH_A.into()).await.unwrap(),
+        second_commit_hash
+    );
+}
+
+/*
+    c2 (HEAD -> main, branch_a)
+    |
+    c1 (tag_a)
+*/
+/// Fetch "branch_a" and "tag_a" from a remote repository, and then fetch a tag that conflicts with a local one.
+#[tokio::test]
+async fn fetch_all() {
+    let td_remote = TempDir::new().unwrap();
+    let remote_path = td_remote.path();
+    let mut remote_repo = init_repository_with_initial_commit(remote_path)
+        .await
+        .unwrap();
+    let first_commit_hash = remote_repo.get_head().await.unwrap();
+    remote_repo
+        .create_tag(TAG_A.into(), first_commit_hash, None)
+        .await
+        .unwrap();
+    let second_commit_hash = remote_repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    remote_repo
+        .create_branch(BRANCH_A.into(), second_commit_hash)
+        .await
+        .unwrap();
+
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+    repo.add_remote(
+        "origin".to_owned(),
+        remote_path.to_str().unwrap().to_owned(),
+    )
+    .await
+    .unwrap();
+    repo.fetch_all().await.unwrap();
+
+    let git2_repo = git2::Repository::open(path).unwrap();
+    let branch_a_oid = git2_repo
+        .refname_to_id(&format!("refs/remotes/origin/{}", BRANCH_A))
+        .unwrap();
+    assert_eq!(branch_a_oid.as_bytes(), second_commit_hash.hash);
+    let tag_a_commit_hash = repo.locate_tag(TAG_A.into()).await.unwrap();
+    assert_eq!(tag_a_commit_hash, first_commit_hash);
+
+    // A tag that conflicts with a local one doesn't replace it, but the others still arrive.
+    let local_commit_hash = repo.get_head().await.unwrap();
+    repo.create_tag(TAG_B.into(), local_commit_hash, None)
+        .await
+        .unwrap();
+    remote_repo
+        .create_tag(TAG_B.into(), second_commit_hash, None)
+        .await
+        .unwrap();
+    remote_repo
+        .create_tag(TAG_C.into(), second_commit_hash, None)
+        .await
+        .unwrap();
+    repo.fetch_all().await.unwrap();
+    assert_eq!(
+        repo.locate_tag(TAG_B.into()).await.unwrap(),
+        local_commit_hash
+    );
+    assert_eq!(
+        repo.locate_tag(TAG_C.into()).await.unwrap(),
+        second_commit_hash
+    );
+}
+
+/// Same as `fetch_all`, but with a shallow fetch.
+#[tokio::test]
+async fn fetch_all_with_depth_skips_conflicting_tags() {
+    let td_remote = TempDir::new().unwrap();
+    let remote_path = td_remote.path();
+    let mut remote_repo = init_repository_with_initial_commit(remote_path)
+        .await
+        .unwrap();
+    let remote_commit_hash = remote_repo.get_head().await.unwrap();
+    remote_repo
+        .create_tag(TAG_A.into(), remote_commit_hash, None)
+        .await
+        .unwrap();
+    remote_repo
+        .create_tag(TAG_B.into(), remote_commit_hash, None)
+        .await
+        .unwrap();
+
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+    let local_commit_hash = repo.get_head().await.unwrap();
+    repo.create_tag(TAG_A.into(), local_commit_hash, None)
+        .await
+        .unwrap();
+    repo.add_remote(
+        "origin".to_owned(),
+        remote_path.to_str().unwrap().to_owned(),
+    )
+    .await
+    .unwrap();
+    repo.fetch_all_with_depth(1).await.unwrap();
+
+    assert_eq!(
+        repo.locate_tag(TAG_A.into()).await.unwrap(),
+        local_commit_hash
+    );
+    assert_eq!(
+        repo.locate_tag(TAG_B.into()).await.unwrap(),
+        remote_commit_hash
+    );
+}
+
+/// Make commits in a repository that has no identity configured.
+#[tokio::test]
+async fn commit_without_signature_config() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    init_repository_with_initial_commit(path).await.unwrap();
+    {
+        let mut config = git2::Repository::open(path).unwrap().config().unwrap();
+        config.remove("user.name").unwrap();
+        config.remove("user.email").unwrap();
+    }
+    let mut repo = RawRepositoryImpl::open(path.to_str().unwrap())
+        .await
+        .unwrap();
+
+    repo.create_commit("commit".to_owned(), None).await.unwrap();
+    let head = repo.get_head().await.unwrap();
+    repo.create_branch(BRANCH_A.into(), head).await.unwrap();
+    repo.create_commit_on_branch(BRANCH_A.into(), "commit on branch".to_owned(), None)
+        .await
+        .unwrap();
+}
+
+/*
+    c2 (HEAD -> main, branch_a)
+    |
+    c1 (tag_a)
+*/
+/// Fetch "branch_a" from a remote repository, delete it there, and fetch again.
+/// The remote tracking branch is pruned while the tags, including a local one, remain.
+#[tokio::test]
+async fn fetch_all_prune() {
+    let td_remote = TempDir::new().unwrap();
+    let remote_path = td_remote.path();
+    let mut remote_repo = init_repository_with_initial_commit(remote_path)
+        .await
+        .unwrap();
+    let first_commit_hash = remote_repo.get_head().await.unwrap();
+    remote_repo
+        .create_tag(TAG_A.into(), first_commit_hash, None)
+        .await
+        .unwrap();
+    let second_commit_hash = remote_repo
+        .create_commit("second".to_owned(), Some("".to_owned()))
+        .await
+        .unwrap();
+    remote_repo
+        .create_branch(BRANCH_A.into(), second_commit_hash)
+        .await
+        .unwrap();
+
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+    repo.add_remote(
+        "origin".to_owned(),
+        remote_path.to_str().unwrap().to_owned(),
+    )
+    .await
+    .unwrap();
+    repo.fetch_all().await.unwrap();
+    let local_commit_hash = repo.get_head().await.unwrap();
+    repo.create_tag(TAG_B.into(), local_commit_hash, None)
+        .await
+        .unwrap();
+
+    let git2_repo = git2::Repository::open(path).unwrap();
+    let tracking_branch = format!("refs/remotes/origin/{}", BRANCH_A);
+    git2_repo.refname_to_id(&tracking_branch).unwrap();
+
+    remote_repo.delete_branch(BRANCH_A.into()).await.unwrap();
+    repo.fetch_all().await.unwrap();
+    git2_repo.refname_to_id(&tracking_branch).unwrap_err();
+    assert_eq!(
+        repo.locate_tag(TAG_A.into()).await.unwrap(),
+        first_commit_hash
+    );
+    assert_eq!(
+        repo.locate_tag(TAG_B.into()).await.unwrap(),
+        local_commit_hash
+    );
+}
+
+/// Fetch from two remotes, one with "branch_a" and the other with "branch_b",
+/// and list the remote tracking branches of each of them.
+#[tokio::test]
+async fn list_remote_tracking_branches_of_remote() {
+    let td_remote_a = TempDir::new().unwrap();
+    let remote_repo_a = init_repository_with_initial_commit(td_remote_a.path())
+        .await
+        .unwrap();
+    let commit_hash_a = remote_repo_a.get_head().await.unwrap();
+    remote_repo_a
+        .create_branch(BRANCH_A.into(), commit_hash_a)
+        .await
+        .unwrap();
+    let td_remote_b = TempDir::new().unwrap();
+    let remote_repo_b = init_repository_with_initial_commit(td_remote_b.path())
+        .await
+        .unwrap();
+    let commit_hash_b = remote_repo_b.get_head().await.unwrap();
+    remote_repo_b
+        .create_branch(BRANCH_B.into(), commit_hash_b)
+        .await
+        .unwrap();
+
+    let td = TempDir::new().unwrap();
+    let mut repo = init_repository_with_initial_commit(td.path())
+        .await
+        .unwrap();
+    for (remote_name, td_remote) in [("remote_a", &td_remote_a), ("remote_b", &td_remote_b)] {
+        repo.add_remote(
+            remote_name.to_owned(),
+            td_remote.path().to_str().unwrap().to_owned(),
+        )
+        .await
+        .unwrap();
+    }
+    repo.fetch_all().await.unwrap();
+
+    let branches = repo
+        .list_remote_tracking_branches(Some("remote_a".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(
+        branches,
+        vec![
+            ("remote_a".to_owned(), BRANCH_A.to_owned(), commit_hash_a),
+            ("remote_a".to_owned(), MAIN.to_owned(), commit_hash_a),
+        ]
+    );
+    let branches = repo
+        .list_remote_tracking_branches(Some("remote_b".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(
+        branches,
+        vec![
+            ("remote_b".to_owned(), BRANCH_B.to_owned(), commit_hash_b),
+            ("remote_b".to_owned(), MAIN.to_owned(), commit_hash_b),
+        ]
+    );
+    let branches = repo.list_remote_tracking_branches(None).await.unwrap();
+    assert_eq!(branches.len(), 4);
+}
+
+/// Convert object ids of the SHA-1 and SHA-256 lengths, where only the former is supported.
+#[test]
+fn commit_hash_from_oid_bytes() {
+    assert_eq!(
+        commit_hash_from_bytes(&[1; 20]).unwrap(),
+        CommitHash { hash: [1; 20] }
+    );
+    let error = commit_hash_from_bytes(&[1; 32]).unwrap_err();
+    assert!(
+        matches!(&error, Error::InvalidRepository(message) if message
+            == "expected 20-byte (SHA-1) oid, got 32 bytes; SHA-256 repos are unsupported"),
+        "{}",
+        error
+    );
+}
+
+/*
+    c3 (branch_a)
+     |  c2
+     | /
+    c1 (HEAD -> main)
+*/
+/// Run the garbage collection while another task creates c3 and "branch_a" on it.
+/// c3 survives, whereas c2, which no branch refers to, is removed.
+#[tokio::test]
+async fn run_garbage_collection() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+    let c1 = repo.get_head().await.unwrap();
+    let c2 = repo
+        .create_commit_with_parents("c2".to_owned(), vec![], vec![c1])
+        .await
+        .unwrap();
+
+    let repo = std::sync::Arc::new(tokio::sync::Mutex::new(repo));
+    let gc = {
+        let repo = std::sync::Arc::clone(&repo);
+        tokio::spawn(async move { repo.lock().await.run_garbage_collection().await })
+    };
+    let commit = {
+        let repo = std::sync::Arc::clone(&repo);
+        tokio::spawn(async move {
+            let mut repo = repo.lock().await;
+            let c3 = repo
+                .create_commit_with_parents("c3".to_owned(), vec![], vec![c1])
+                .await?;
+            repo.create_branch(BRANCH_A.into(), c3).await?;
+            Ok::<_, Error>(c3)
+        })
+    };
+    gc.await.unwrap().unwrap();
+    let c3 = commit.await.unwrap().unwrap();
+    repo.lock().await.run_garbage_collection().await.unwrap();
+
+    // Reopen the repository so that no object is served from the cache.
+    let repo = RawRepositoryImpl::open(path.to_str().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(repo.locate_branch(BRANCH_A.into()).await.unwrap(), c3);
+    repo.read_semantic_commit(c3).await.unwrap();
+    repo.read_semantic_commit(c2).await.unwrap_err();
+}
+
+/// Reads through `&self` don't wait for each other: each one gets its own handle.
+///
+/// Every read waits inside its handle until all of them have started, which can't happen
+/// if they are serialized. Only `MAX_SPARE_READERS` of the handles are kept afterwards,
+/// and they survive a write but not a garbage collection.
+#[tokio::test]
+async fn concurrent_reads() {
+    let td = TempDir::new().unwrap();
+    let path = td.path();
+    let mut repo = init_repository_with_initial_commit(path).await.unwrap();
+    let head = repo.get_head().await.unwrap();
+
+    let count = MAX_SPARE_READERS + 2;
+    let rendezvous = std::sync::Arc::new((std::sync::Mutex::new(0), std::sync::Condvar::new()));
+    let reads = (0..count).map(|_| {
+        let rendezvous = std::sync::Arc::clone(&rendezvous);
+        read(&repo, move |inner| {
+            let (started, condvar) = &*rendezvous;
+            let mut started = started.lock().unwrap();
+            *started += 1;
+            condvar.notify_all();
+            let (_started, timeout) = condvar
+                .wait_timeout_while(started, std::time::Duration::from_secs(10), |started| {
+                    *started < count
+                })
+                .unwrap();
+            (!timeout.timed_out(), inner.get_head().unwrap())
+        })
+    });
+    for (overlapped, h) in futures::future::join_all(reads).await {
+        assert!(overlapped, "the reads were serialized");
+        assert_eq!(h, head);
+    }
+    assert_eq!(repo.readers.lock().unwrap().len(), MAX_SPARE_READERS);
+
+    let c1 = repo
+        .create_commit_with_parents("c1".to_owned(), vec![], vec![head])
+        .await
+        .unwrap();
+    repo.create_branch(BRANCH_A.into(), c1).await.unwrap();
+    assert_eq!(repo.readers.lock().unwrap().len(), MAX_SPARE_READERS);
+    assert_eq!(repo.locate_branch(BRANCH_A.into()).await.unwrap(), c1);
+
+    repo.run_garbage_collection().await.unwrap();
+    assert!(repo.readers.lock().unwrap().is_empty());
+    assert_eq!(repo.locate_branch(BRANCH_A.into()).await.unwrap(), c1);
+}
+
+/// A child process that is killed when dropped, even if the test fails.
+struct ChildGuard(std::process::Child);
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Fetch through a proxy that drops the first connection, which the retry recovers from.
+///
+/// A missing repository is a permanent error, which is not retried.
+#[tokio::test]
+async fn fetch_all_retry() {
+    let td_remote = TempDir::new().unwrap();
+    let remote_repo = init_repository_with_initial_commit(td_remote.path())
+        .await
+        .unwrap();
+    let head = remote_repo.get_head().await.unwrap();
+    remote_repo
+        .create_branch(BRANCH_A.into(), head)
+        .await
+        .unwrap();
+
+    let daemon_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    // `git daemon` would run it as a child process, which would survive the kill.
+    let exec_path = std::process::Command::new("git")
+        .arg("--exec-path")
+        .output()
+        .unwrap()
+        .stdout;
+    let _daemon = ChildGuard(
+        std::process::Command::new(
+            Path::new(String::from_utf8(exec_path).unwrap().trim()).join("git-daemon"),
+        )
+        .args(["--export-all", "--reuseaddr", "--listen=127.0.0.1"])
+        .arg(format!("--port={}", daemon_port))
+        .arg(format!("--base-path={}", td_remote.path().display()))
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap(),
+    );
+    for _ in 0..100 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", daemon_port))
+            .await
+            .is_ok()
+        {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+
+    let proxy = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_port = proxy.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        // The first connection is reset; the rest are forwarded to the daemon.
+        drop(proxy.accept().await.unwrap());
+        loop {
+            let (mut inbound, _) = proxy.accept().await.unwrap();
+            let mut outbound = tokio::net::TcpStream::connect(("127.0.0.1", daemon_port))
+                .await
+                .unwrap();
+            tokio::spawn(async move {
+                let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+            });
+        }
+    });
+
+    let td = TempDir::new().unwrap();
+    let mut repo = init_repository_with_initial_commit(td.path())
+        .await
+        .unwrap();
+    repo.add_remote(
+        "origin".to_owned(),
+        format!("git://127.0.0.1:{}/", proxy_port),
+    )
+    .await
+    .unwrap();
+    repo.fetch_all().await.unwrap();
+    assert_eq!(
+        repo.list_remote_tracking_branches(None).await.unwrap(),
+        vec![
+            ("origin".to_owned(), BRANCH_A.to_owned(), head),
+            ("origin".to_owned(), MAIN.to_owned(), head)
+        ]
+    );
+
+    repo.remove_remote("origin".to_owned()).await.unwrap();
+    // A directory that is not a repository.
+    let td_empty = TempDir::new().unwrap();
+    repo.add_remote(
+        "empty".to_owned(),
+        td_empty.path().to_str().unwrap().to_owned(),
+    )
+    .await
+    .unwrap();
+    let started = std::time::Instant::now();
+    repo.fetch_all().await.unwrap_err();
+    assert!(started.elapsed() < std::time::Duration::from_millis(200));
 }
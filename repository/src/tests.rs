@@ -0,0 +1,3518 @@
+use super::*;
+use raw::{reserved_state, RawRepositoryImpl};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::Path;
+use tempfile::TempDir;
+
+fn generate_keys(size: u8) -> Vec<(PublicKey, PrivateKey)> {
+    (0..size).map(|i| generate_keypair([i])).collect()
+}
+
+fn generate_genesis_header(keys: &[(PublicKey, PrivateKey)]) -> BlockHeader {
+    BlockHeader {
+        author: keys[0].0.clone(),
+        prev_block_finalization_proof: vec![],
+        previous_hash: Hash256::zero(),
+        height: 0,
+        timestamp: 0,
+        commit_merkle_root: BlockHeader::calculate_commit_merkle_root(&[]),
+        repository_merkle_root: Hash256::zero(),
+        validator_set: keys
+            .iter()
+            .map(|(public_key, _)| (public_key.clone(), 1))
+            .collect(),
+        version: "0.0.0".to_owned(),
+    }
+}
+
+fn generate_reserved_state(keys: &[(PublicKey, PrivateKey)]) -> ReservedState {
+    ReservedState {
+        genesis_info: GenesisInfo {
+            header: generate_genesis_header(keys),
+            genesis_proof: keys
+                .iter()
+                .map(|(_, private_key)| {
+                    TypedSignature::sign(&generate_genesis_header(keys), private_key).unwrap()
+                })
+                .collect(),
+            chain_name: "test".to_owned(),
+        },
+        members: keys
+            .iter()
+            .enumerate()
+            .map(|(i, (public_key, _))| Member {
+                public_key: public_key.clone(),
+                name: format!("member{}", i),
+                governance_voting_power: 1,
+                consensus_voting_power: 1,
+                governance_delegations: None,
+                consensus_delegations: None,
+            })
+            .collect(),
+        consensus_leader_order: (0..keys.len()).collect(),
+        version: "0.0.0".to_owned(),
+    }
+}
+
+/// Writes the given files and commits them on top of `HEAD`, directly with `git2`.
+fn commit_files(path: &str, message: &str, files: &[(String, String)]) {
+    let repo = git2::Repository::open(path).unwrap();
+    let mut index = repo.index().unwrap();
+    for (file, content) in files {
+        let file_path = Path::new(path).join(file);
+        std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+        std::fs::write(&file_path, content).unwrap();
+        index.add_path(Path::new(file)).unwrap();
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = repo.signature().unwrap();
+    let parent = repo.head().unwrap().peel_to_commit().unwrap();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&parent],
+    )
+    .unwrap();
+}
+
+/// Creates a commit of the given one on top of `HEAD`.
+async fn create_commit(
+    raw: &mut RawRepositoryImpl,
+    commit: &Commit,
+    last_header: &BlockHeader,
+) -> CommitHash {
+    let semantic_commit = to_semantic_commit(commit, last_header);
+    raw.create_commit(
+        format!("{}\n\n{}", semantic_commit.title, semantic_commit.body),
+        None,
+    )
+    .await
+    .unwrap()
+}
+
+/// Commits the given reserved state on `finalized` of a new repository, without a genesis block.
+async fn setup_reserved_state(path: &str, reserved_state: &ReservedState) -> RawRepositoryImpl {
+    RawRepositoryImpl::init(path, "initial", &FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    commit_files(
+        path,
+        "reserved state",
+        &reserved_state::to_files(reserved_state),
+    );
+    RawRepositoryImpl::open(path).await.unwrap()
+}
+
+/// Initializes a repository of which `finalized` branch is on the genesis block.
+async fn setup_genesis(path: &str, keys: &[(PublicKey, PrivateKey)]) -> RawRepositoryImpl {
+    RawRepositoryImpl::init(path, "initial", &FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    let reserved_state = generate_reserved_state(keys);
+    commit_files(path, "genesis", &reserved_state::to_files(&reserved_state));
+    // Reopen so that the index written by `commit_files` is loaded.
+    let mut raw = RawRepositoryImpl::open(path).await.unwrap();
+    let genesis_header = reserved_state.genesis_info.header;
+    create_commit(
+        &mut raw,
+        &Commit::Block(genesis_header.clone()),
+        &genesis_header,
+    )
+    .await;
+    raw
+}
+
+/// Generates the commits for the next block: an agenda, its proof and the block itself.
+fn generate_next_block_commits(
+    keys: &[(PublicKey, PrivateKey)],
+    last_header: &BlockHeader,
+) -> Vec<Commit> {
+    let agenda = Agenda {
+        author: keys[0].0.clone(),
+        timestamp: last_header.timestamp + 1,
+        hash: Agenda::calculate_hash(last_header.height, &[]),
+    };
+    let agenda_proof = AgendaProof {
+        agenda_hash: agenda.hash,
+        proof: keys
+            .iter()
+            .map(|(_, private_key)| TypedSignature::sign(&agenda, private_key).unwrap())
+            .collect(),
+    };
+    let commits = vec![Commit::Agenda(agenda), Commit::AgendaProof(agenda_proof)];
+    let block_header = BlockHeader {
+        author: keys[0].0.clone(),
+        prev_block_finalization_proof: keys
+            .iter()
+            .map(|(_, private_key)| TypedSignature::sign(last_header, private_key).unwrap())
+            .collect(),
+        previous_hash: last_header.to_hash256(),
+        height: last_header.height + 1,
+        timestamp: last_header.timestamp + 2,
+        commit_merkle_root: BlockHeader::calculate_commit_merkle_root(&commits),
+        repository_merkle_root: Hash256::zero(),
+        validator_set: last_header.validator_set.clone(),
+        version: last_header.version.clone(),
+    };
+    [commits, vec![Commit::Block(block_header)]].concat()
+}
+
+fn generate_peer(seed: u8, port: u16) -> Peer {
+    let (public_key, _) = generate_keypair([seed]);
+    Peer {
+        public_key,
+        address: SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0),
+        ports: vec![(REPOSITORY_PORT_KEY.to_owned(), port)]
+            .into_iter()
+            .collect::<HashMap<_, _>>(),
+        message: "".to_owned(),
+        recently_seen_timestamp: 0,
+    }
+}
+
+/// Adds the remotes of the peers twice and checks that each peer is mapped to exactly one remote.
+#[tokio::test]
+async fn remote_per_peer() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let raw = RawRepositoryImpl::init(path, "initial", &FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    let peers = vec![generate_peer(1, 1001), generate_peer(2, 1002)];
+    drepo.add_remotes(&peers[0..1]).await.unwrap();
+    drepo.add_remotes(&peers).await.unwrap();
+
+    let mut remotes = drepo.raw.list_remotes().await.unwrap();
+    remotes.sort();
+    let mut expected = vec![
+        (
+            get_remote_name(&peers[0]),
+            "git://127.0.0.1:1001/".to_owned(),
+        ),
+        (
+            get_remote_name(&peers[1]),
+            "git://127.0.0.1:1002/".to_owned(),
+        ),
+    ];
+    expected.sort();
+    assert_eq!(remotes, expected);
+    assert_ne!(get_remote_name(&peers[0]), get_remote_name(&peers[1]));
+}
+
+/// Initializes a repository and opens an existing one by their directories.
+#[tokio::test]
+async fn open_and_init() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().join("open");
+    let path = path.to_str().unwrap();
+    let keys = generate_keys(4);
+    setup_genesis(path, &keys).await;
+    let drepo = DistributedRepository::open(path).await.unwrap();
+    assert_eq!(
+        drepo
+            .get_last_finalized_block_header()
+            .await
+            .unwrap()
+            .height,
+        0
+    );
+
+    let path = td.path().join("init");
+    let path = path.to_str().unwrap();
+    let drepo = DistributedRepository::init(path).await.unwrap();
+    assert_eq!(
+        drepo.raw.list_branches().await.unwrap(),
+        vec![FINALIZED_BRANCH_NAME.to_owned()]
+    );
+}
+
+/// Clones a repository into another directory and opens it.
+#[tokio::test]
+async fn clone_from() {
+    let td = TempDir::new().unwrap();
+    let source_path = td.path().join("source");
+    let source_path = source_path.to_str().unwrap();
+    let path = td.path().join("clone");
+    let path = path.to_str().unwrap();
+    let keys = generate_keys(4);
+    let source_raw = setup_genesis(source_path, &keys).await;
+    let genesis_commit = source_raw.get_head().await.unwrap();
+
+    let raw = RawRepositoryImpl::clone_from(source_path, path)
+        .await
+        .unwrap();
+    drop(raw);
+    let drepo = DistributedRepository::open(path).await.unwrap();
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        genesis_commit
+    );
+    assert_eq!(
+        drepo.get_last_finalized_block_header().await.unwrap(),
+        generate_genesis_header(&keys)
+    );
+}
+
+/// Creates a commit of every kind and reads each of them back.
+#[tokio::test]
+async fn get_commit() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let raw = RawRepositoryImpl::init(path, "initial", &FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    let keys = generate_keys(1);
+    let (public_key, private_key) = keys[0].clone();
+    let last_header = generate_genesis_header(&keys);
+    let agenda = Agenda {
+        author: public_key.clone(),
+        timestamp: 2,
+        hash: Agenda::calculate_hash(1, &[]),
+    };
+    let agenda_signature = TypedSignature::sign(&agenda, &private_key).unwrap();
+    let commits = vec![
+        Commit::Transaction(Transaction {
+            author: public_key.clone(),
+            timestamp: 1,
+            head: "transaction".to_owned(),
+            body: "body".to_owned(),
+            diff: Diff::None,
+        }),
+        Commit::Agenda(agenda.clone()),
+        Commit::AgendaProof(AgendaProof {
+            agenda_hash: agenda.hash,
+            proof: vec![agenda_signature.clone()],
+        }),
+        Commit::ExtraAgendaTransaction(ExtraAgendaTransaction::Undelegate(TxUndelegate {
+            delegator: public_key.clone(),
+            // `(PublicKey, BlockHeight)` can't be signed directly, so reuse a signature's format.
+            proof: serde_json::from_value(serde_json::to_value(&agenda_signature).unwrap())
+                .unwrap(),
+            timestamp: 3,
+        })),
+        Commit::ChatLog(ChatLog {}),
+        Commit::Block(last_header.clone()),
+    ];
+
+    for commit in commits {
+        let commit_hash = create_commit(&mut drepo.raw, &commit, &last_header).await;
+        assert_eq!(drepo.get_commit(&commit_hash).await.unwrap(), commit);
+    }
+}
+
+/// Reads the reserved state twice with the same `finalized` and checks that the second one is cached.
+#[tokio::test]
+async fn reserved_state_cache() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let raw = RawRepositoryImpl::init(path, "initial", &FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    let keys = generate_keys(4);
+    let reserved_state = generate_reserved_state(&keys);
+    commit_files(path, "genesis", &reserved_state::to_files(&reserved_state));
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+
+    assert_eq!(drepo.get_reserved_state().await.unwrap(), reserved_state);
+
+    // Replace the cached one; it must be returned instead of reading the repository again.
+    let mut cached_reserved_state = reserved_state.clone();
+    cached_reserved_state.version = "0.0.1".to_owned();
+    let finalized_commit_hash = drepo
+        .raw
+        .locate_branch(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    *drepo.reserved_state_cache.lock().unwrap() =
+        Some((finalized_commit_hash, cached_reserved_state.clone()));
+    assert_eq!(
+        drepo.get_reserved_state().await.unwrap(),
+        cached_reserved_state
+    );
+
+    // Once invalidated, it is read again.
+    drepo.invalidate_cache();
+    assert_eq!(drepo.get_reserved_state().await.unwrap(), reserved_state);
+
+    // Once `finalized` moves, it is read again.
+    *drepo.reserved_state_cache.lock().unwrap() =
+        Some((finalized_commit_hash, cached_reserved_state));
+    commit_files(path, "next", &[]);
+    assert_eq!(drepo.get_reserved_state().await.unwrap(), reserved_state);
+}
+
+/// Reads a reserved state without members, which is rejected as malformed.
+#[tokio::test]
+async fn reserved_state_without_members() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let mut reserved_state = generate_reserved_state(&generate_keys(4));
+    reserved_state.members.clear();
+    let raw = setup_reserved_state(path, &reserved_state).await;
+    let commit_hash = raw.get_head().await.unwrap();
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let error = drepo.get_reserved_state().await.unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<InvalidReservedStateError>(),
+        Some(&InvalidReservedStateError {
+            commit_hash,
+            reason: "there is no member".to_owned()
+        })
+    );
+}
+
+/// Reads a reserved state whose leader order refers to a missing member.
+#[tokio::test]
+async fn reserved_state_leader_out_of_range() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let mut reserved_state = generate_reserved_state(&generate_keys(4));
+    reserved_state.consensus_leader_order.push(4);
+    let raw = setup_reserved_state(path, &reserved_state).await;
+    let commit_hash = raw.get_head().await.unwrap();
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let error = drepo.get_reserved_state_at(&commit_hash).await.unwrap_err();
+    let error = error.downcast_ref::<InvalidReservedStateError>().unwrap();
+    assert!(error.reason.contains("member 4"), "{}", error);
+}
+
+/// Reads the last finalized header twice with the same `finalized`
+/// and checks that the second one is cached.
+#[tokio::test]
+async fn last_header_cache() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let mut block_commit = genesis_commit;
+    let mut block_header = last_header.clone();
+    for commit in generate_next_block_commits(&keys, &last_header) {
+        block_commit = create_commit(&mut raw, &commit, &last_header).await;
+        if let Commit::Block(header) = commit {
+            block_header = header;
+        }
+    }
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    assert_eq!(
+        drepo.get_last_finalized_block_header().await.unwrap(),
+        last_header
+    );
+
+    // Replace the cached one; it must be returned instead of parsing the commit again.
+    let mut cached_header = last_header.clone();
+    cached_header.version = "0.0.1".to_owned();
+    *drepo.last_header_cache.lock().unwrap() = Some((genesis_commit, cached_header.clone()));
+    for _ in 0..3 {
+        assert_eq!(
+            drepo.get_last_finalized_block_header().await.unwrap(),
+            cached_header
+        );
+    }
+
+    // Once invalidated, it is read again.
+    drepo.invalidate_cache();
+    assert_eq!(
+        drepo.get_last_finalized_block_header().await.unwrap(),
+        last_header
+    );
+
+    // Once `finalized` moves, it is read again.
+    *drepo.last_header_cache.lock().unwrap() = Some((genesis_commit, cached_header));
+    drepo
+        .raw
+        .move_branch(
+            FINALIZED_BRANCH_NAME.into(),
+            block_commit,
+            true,
+            "test".to_owned(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        drepo.get_last_finalized_block_header().await.unwrap(),
+        block_header
+    );
+}
+
+/// Creates a semantic commit that changes the reserved state,
+/// and checks that the non-reserved file of the parent survives into the new commit.
+#[tokio::test]
+async fn create_semantic_commit_preserves_non_reserved_files() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    RawRepositoryImpl::init(path, "initial", &FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    let keys = generate_keys(4);
+    let reserved_state = generate_reserved_state(&keys);
+    let mut files = reserved_state::to_files(&reserved_state);
+    files.push(("README.md".to_owned(), "readme".to_owned()));
+    commit_files(path, "genesis", &files);
+    let mut raw = RawRepositoryImpl::open(path).await.unwrap();
+
+    let mut next_reserved_state = reserved_state.clone();
+    next_reserved_state.version = "0.0.1".to_owned();
+    let commit_hash = raw
+        .create_semantic_commit(
+            raw::SemanticCommit {
+                title: "tx-delegate".to_owned(),
+                body: "".to_owned(),
+                diff: Diff::Reserved(Box::new(next_reserved_state.clone()), Hash256::zero()),
+            },
+            FINALIZED_BRANCH_NAME.into(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        raw.read_reserved_state_at_commit(commit_hash)
+            .await
+            .unwrap(),
+        next_reserved_state
+    );
+    let repo = git2::Repository::open(path).unwrap();
+    let tree = repo
+        .find_commit(git2::Oid::from_bytes(&commit_hash.hash).unwrap())
+        .unwrap()
+        .tree()
+        .unwrap();
+    let readme = tree
+        .get_path(Path::new("README.md"))
+        .unwrap()
+        .to_object(&repo)
+        .unwrap()
+        .peel_to_blob()
+        .unwrap();
+    assert_eq!(readme.content(), b"readme");
+
+    // The index follows the new commit, so the next commit keeps the reserved state.
+    let next_commit_hash = raw.create_commit("next".to_owned(), None).await.unwrap();
+    assert_eq!(
+        raw.read_reserved_state_at_commit(next_commit_hash)
+            .await
+            .unwrap(),
+        next_reserved_state
+    );
+}
+
+/// Creates semantic commits with and without a reserved state change, and reads them back.
+#[tokio::test]
+async fn read_semantic_commit_reserved_state() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let reserved_state = generate_reserved_state(&keys);
+    let mut raw = setup_reserved_state(path, &reserved_state).await;
+
+    let mut next_reserved_state = reserved_state.clone();
+    next_reserved_state.version = "0.0.1".to_owned();
+    let commits = [
+        raw::SemanticCommit {
+            title: "tx-delegate".to_owned(),
+            body: "body".to_owned(),
+            diff: Diff::Reserved(Box::new(next_reserved_state.clone()), Hash256::zero()),
+        },
+        raw::SemanticCommit {
+            title: "empty".to_owned(),
+            body: "".to_owned(),
+            diff: Diff::None,
+        },
+    ];
+    for commit in commits {
+        let head = raw.get_head().await.unwrap();
+        let commit_hash = raw
+            .create_semantic_commit(commit.clone(), FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap();
+        assert_eq!(
+            raw.list_ancestors(commit_hash, Some(1)).await.unwrap(),
+            vec![head]
+        );
+        let read_commit = raw.read_semantic_commit(commit_hash).await.unwrap();
+        assert_eq!(read_commit.title, commit.title);
+        assert_eq!(read_commit.body, commit.body);
+        match (read_commit.diff, commit.diff) {
+            (Diff::Reserved(read_state, _), Diff::Reserved(state, _)) => {
+                assert_eq!(read_state, state)
+            }
+            (read_diff, diff) => assert_eq!(read_diff, diff),
+        }
+    }
+
+    // A general diff has no content to commit.
+    let head = raw.get_head().await.unwrap();
+    let error = raw
+        .create_semantic_commit(
+            raw::SemanticCommit {
+                title: "general".to_owned(),
+                body: "".to_owned(),
+                diff: Diff::General(Hash256::zero()),
+            },
+            FINALIZED_BRANCH_NAME.into(),
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, raw::Error::InvalidRepository(_)),
+        "{}",
+        error
+    );
+    assert_eq!(raw.get_head().await.unwrap(), head);
+
+    // A change outside of the reserved area is a general one.
+    commit_files(
+        path,
+        "readme",
+        &[("README.md".to_owned(), "readme".to_owned())],
+    );
+    let commit_hash = raw.get_head().await.unwrap();
+    assert!(matches!(
+        raw.read_semantic_commit(commit_hash).await.unwrap().diff,
+        Diff::General(_)
+    ));
+}
+
+/// Detaches `HEAD` and checks that a semantic commit for `finalized` is refused
+/// instead of being made on the detached `HEAD`.
+#[tokio::test]
+async fn create_semantic_commit_detached_head() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.checkout_detach(genesis_commit).await.unwrap();
+
+    let semantic_commit = raw::SemanticCommit {
+        title: "chat".to_owned(),
+        body: "".to_owned(),
+        diff: Diff::None,
+    };
+    let error = raw
+        .create_semantic_commit(semantic_commit.clone(), FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("HEAD is detached"), "{}", error);
+    assert_eq!(raw.get_head().await.unwrap(), genesis_commit);
+
+    raw.checkout(FINALIZED_BRANCH_NAME.into()).await.unwrap();
+    raw.create_semantic_commit(semantic_commit.clone(), WORK_BRANCH_NAME.into())
+        .await
+        .unwrap_err();
+    let commit_hash = raw
+        .create_semantic_commit(semantic_commit, FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    assert_eq!(
+        raw.locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        commit_hash
+    );
+}
+
+/// Changes the members after an agenda is approved,
+/// and checks that the agenda proof is still verified against the old members.
+#[tokio::test]
+async fn verify_agenda_proof_with_old_members() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    // Commits on the `finalized` branch directly, bypassing the verification.
+    let mut raw = setup_genesis(path, &keys).await;
+    let last_header = generate_genesis_header(&keys);
+    let commits = generate_next_block_commits(&keys, &last_header);
+    create_commit(&mut raw, &commits[0], &last_header).await;
+    let agenda_proof_commit = create_commit(&mut raw, &commits[1], &last_header).await;
+    create_commit(&mut raw, &commits[2], &last_header).await;
+
+    let new_keys = (10..14).map(|i| generate_keypair([i])).collect::<Vec<_>>();
+    let mut new_reserved_state = generate_reserved_state(&keys);
+    new_reserved_state.members = generate_reserved_state(&new_keys).members;
+    let new_commit = raw
+        .create_semantic_commit(
+            raw::SemanticCommit {
+                title: "tx-delegate".to_owned(),
+                body: "".to_owned(),
+                diff: Diff::Reserved(Box::new(new_reserved_state.clone()), Hash256::zero()),
+            },
+            FINALIZED_BRANCH_NAME.into(),
+        )
+        .await
+        .unwrap();
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    assert_eq!(
+        drepo.get_reserved_state_at(&new_commit).await.unwrap(),
+        new_reserved_state
+    );
+    drepo
+        .verify_agenda_proof(&agenda_proof_commit)
+        .await
+        .unwrap();
+}
+
+/// Repeats a signature in an agenda proof and a finalization proof,
+/// which would have enough voting power if the signer were counted repeatedly.
+#[tokio::test]
+async fn duplicate_signers() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let mut commits = generate_next_block_commits(&keys, &last_header);
+    let mut agenda_proof_commit = genesis_commit;
+    let mut block_commit = genesis_commit;
+    for commit in &commits {
+        let commit_hash = create_commit(&mut raw, commit, &last_header).await;
+        match commit {
+            Commit::AgendaProof(_) => agenda_proof_commit = commit_hash,
+            Commit::Block(_) => block_commit = commit_hash,
+            _ => (),
+        }
+    }
+    let block_header = match commits.pop() {
+        Some(Commit::Block(block_header)) => block_header,
+        _ => panic!("the last commit must be a block"),
+    };
+
+    // An agenda proof on the same agenda, with two distinct signers out of four
+    raw.create_branch("duplicate".to_owned(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout("duplicate".to_owned()).await.unwrap();
+    create_commit(&mut raw, &commits[0], &last_header).await;
+    if let Commit::AgendaProof(agenda_proof) = &mut commits[1] {
+        agenda_proof.proof.truncate(2);
+        let signature = agenda_proof.proof[1].clone();
+        agenda_proof.proof.push(signature.clone());
+        agenda_proof.proof.push(signature);
+    }
+    let duplicate_agenda_proof_commit = create_commit(&mut raw, &commits[1], &last_header).await;
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    drepo
+        .verify_agenda_proof(&agenda_proof_commit)
+        .await
+        .unwrap();
+    let error = drepo
+        .verify_agenda_proof(&duplicate_agenda_proof_commit)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("duplicate signer"), "{}", error);
+
+    let signature = TypedSignature::sign(&block_header, &keys[1].1).unwrap();
+    let proof = vec![
+        TypedSignature::sign(&block_header, &keys[0].1).unwrap(),
+        signature.clone(),
+        signature.clone(),
+        signature,
+    ];
+    let error = drepo.finalize(&block_commit, &proof).await.unwrap_err();
+    assert!(error.to_string().contains("duplicate signer"), "{}", error);
+}
+
+/*
+    b3 (work)
+    |
+    ... (agendas and agenda proofs)
+    |
+    b1
+    |
+    b0 (finalized)
+*/
+/// Syncs to the third block and checks that `finalized` moves to the second one,
+/// reporting the progress of each block.
+#[tokio::test]
+async fn sync() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commits = Vec::new();
+    for _ in 0..3 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_commits.push(commit_hash);
+    }
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let mut heights = Vec::new();
+    drepo
+        .sync_with_progress(&block_commits[2], false, |height, target_height| {
+            heights.push((height, target_height))
+        })
+        .await
+        .unwrap();
+    assert_eq!(heights, vec![(1, 3), (2, 3), (3, 3)]);
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        block_commits[1]
+    );
+    assert_eq!(
+        drepo
+            .get_last_finalized_block_header()
+            .await
+            .unwrap()
+            .height,
+        2
+    );
+
+    // Syncing backward is rejected.
+    drepo.sync(&block_commits[0]).await.unwrap_err();
+}
+
+/// Syncs without and then with the `fp` repair, and checks that only the latter updates `fp`.
+#[tokio::test]
+async fn sync_repair_fp() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commits = Vec::new();
+    for _ in 0..3 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_commits.push((commit_hash, last_header.clone()));
+    }
+    // The `fp` branch is stale: it is on the genesis block.
+    raw.create_branch(FP_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    drepo
+        .sync_with_progress(&block_commits[1].0, false, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FP_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        genesis_commit
+    );
+    drepo.get_last_finalization_proof().await.unwrap_err();
+
+    drepo
+        .sync_with_progress(&block_commits[2].0, true, |_, _| {})
+        .await
+        .unwrap();
+    let fp_commit = drepo
+        .raw
+        .locate_branch(FP_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    assert_eq!(
+        drepo.raw.list_ancestors(fp_commit, Some(1)).await.unwrap(),
+        vec![block_commits[1].0]
+    );
+    assert_eq!(
+        drepo.get_last_finalization_proof().await.unwrap(),
+        block_commits[2].1.prev_block_finalization_proof
+    );
+}
+
+/*
+    b4' (invalid: a second block at height 4)
+    |
+    b4
+    |
+    b3
+    |
+    b2
+    |
+    b1
+    |
+    b0 (finalized)
+*/
+/// Syncs to an invalid block with a checkpoint interval of 2, and checks that `finalized` and
+/// `fp` stay on the checkpoint so that the next sync resumes from there.
+/// The checkpoint is reported to the `on_finalize` callback and the subscribers.
+#[tokio::test]
+async fn sync_checkpoint() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut last_header = generate_genesis_header(&keys);
+    let mut headers = vec![last_header.clone()];
+    let mut block_commits = Vec::new();
+    for _ in 0..4 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        headers.push(last_header.clone());
+        block_commits.push(commit_hash);
+    }
+    let invalid_block = generate_next_block_commits(&keys, &headers[3])
+        .pop()
+        .unwrap();
+    let invalid_commit = create_commit(&mut raw, &invalid_block, &last_header).await;
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    drepo.set_sync_checkpoint_interval(std::num::NonZeroU64::new(2).unwrap());
+    let finalized_headers = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let finalized_headers_ = std::sync::Arc::clone(&finalized_headers);
+    drepo.set_on_finalize(move |header| finalized_headers_.lock().unwrap().push(header.clone()));
+    let mut events = drepo.subscribe();
+    drepo.sync(&invalid_commit).await.unwrap_err();
+    assert_eq!(*finalized_headers.lock().unwrap(), vec![headers[2].clone()]);
+    assert_eq!(
+        events.try_recv().unwrap(),
+        RepositoryEvent::Finalized(Finalized {
+            height: 2,
+            block_hash: headers[2].to_hash256(),
+            commit_hash: block_commits[1],
+        })
+    );
+    assert!(events.try_recv().is_err());
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        block_commits[1]
+    );
+    let fp_commit = drepo
+        .raw
+        .locate_branch(FP_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    assert_eq!(
+        drepo.raw.list_ancestors(fp_commit, Some(1)).await.unwrap(),
+        vec![block_commits[1]]
+    );
+    assert_eq!(
+        drepo.get_last_finalization_proof().await.unwrap(),
+        headers[3].prev_block_finalization_proof
+    );
+
+    // The next sync resumes from the checkpoint.
+    let mut heights = Vec::new();
+    drepo
+        .sync_with_progress(&block_commits[3], false, |height, _| heights.push(height))
+        .await
+        .unwrap();
+    assert_eq!(heights, vec![3, 4]);
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        block_commits[2]
+    );
+    assert!(drepo.check(0).await.unwrap());
+}
+
+/// Finalizes a block, and then fails to finalize the next one because the `fp` branch can't be
+/// written; `finalized` must stay on the first block and the callback must not be called.
+#[tokio::test]
+async fn finalize_rollback() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commits = Vec::new();
+    for _ in 0..2 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_commits.push((commit_hash, last_header.clone()));
+    }
+    let generate_proof = |header: &BlockHeader| {
+        keys.iter()
+            .map(|(_, private_key)| TypedSignature::sign(header, private_key).unwrap())
+            .collect::<FinalizationProof>()
+    };
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let finalized_headers = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let finalized_headers_ = std::sync::Arc::clone(&finalized_headers);
+    drepo.set_on_finalize(move |header| finalized_headers_.lock().unwrap().push(header.clone()));
+    let (block_commit, block_header) = &block_commits[0];
+    drepo
+        .finalize(block_commit, &generate_proof(block_header))
+        .await
+        .unwrap();
+    let finalized_commit = drepo
+        .raw
+        .locate_branch(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    assert_eq!(finalized_commit, *block_commit);
+    let fp_commit = drepo
+        .raw
+        .locate_branch(FP_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    assert_eq!(
+        drepo.raw.list_ancestors(fp_commit, Some(1)).await.unwrap(),
+        vec![*block_commit]
+    );
+
+    // Lock the `fp` branch so that writing it fails.
+    std::fs::write(Path::new(path).join(".git/refs/heads/fp.lock"), "").unwrap();
+    let (block_commit, block_header) = &block_commits[1];
+    drepo
+        .finalize(block_commit, &generate_proof(block_header))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        finalized_commit
+    );
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FP_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        fp_commit
+    );
+
+    // The callback is not called for the rolled-back one.
+    assert_eq!(
+        *finalized_headers.lock().unwrap(),
+        vec![block_commits[0].1.clone()]
+    );
+
+    // Once unlocked, it succeeds.
+    std::fs::remove_file(Path::new(path).join(".git/refs/heads/fp.lock")).unwrap();
+    drepo
+        .finalize(block_commit, &generate_proof(block_header))
+        .await
+        .unwrap();
+    assert_eq!(
+        *finalized_headers.lock().unwrap(),
+        vec![block_commits[0].1.clone(), block_commits[1].1.clone()]
+    );
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        *block_commit
+    );
+}
+
+/// Verifies the same commits twice, and checks that the second time is served from the cache
+/// by removing a commit that the verification would read.
+#[tokio::test]
+async fn verified_commits_cache() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let mut commit_hashes = Vec::new();
+    for commit in generate_next_block_commits(&keys, &last_header) {
+        commit_hashes.push(create_commit(&mut raw, &commit, &last_header).await);
+    }
+    let block_commit = *commit_hashes.last().unwrap();
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    drepo
+        .verify_commits_from_finalized(&block_commit)
+        .await
+        .unwrap();
+
+    // Without the agenda commit, nothing on top of it can be read.
+    let agenda_commit = format!("{:#}", commit_hashes[0]);
+    std::fs::remove_file(
+        Path::new(path)
+            .join(".git/objects")
+            .join(&agenda_commit[..2])
+            .join(&agenda_commit[2..]),
+    )
+    .unwrap();
+    for commit_hash in &commit_hashes {
+        drepo
+            .verify_commits_from_finalized(commit_hash)
+            .await
+            .unwrap();
+    }
+
+    // The cache doesn't apply to another commit of `finalized`.
+    let verified_commits = commit_hashes.iter().copied().collect();
+    *drepo.verified_commits_cache.lock().unwrap() = Some((block_commit, verified_commits));
+    drepo
+        .verify_commits_from_finalized(&block_commit)
+        .await
+        .unwrap_err();
+}
+
+/// Verifies a valid branch, its agenda proof, and an invalid branch on top of it,
+/// and checks that nothing in the repository changes.
+#[tokio::test]
+async fn verify_branch() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let genesis_header = generate_genesis_header(&keys);
+    let commits = generate_next_block_commits(&keys, &genesis_header);
+    let mut commit_hashes = Vec::new();
+    for commit in &commits {
+        commit_hashes.push(create_commit(&mut raw, commit, &genesis_header).await);
+    }
+    let block_header = match &commits[2] {
+        Commit::Block(block_header) => block_header.clone(),
+        _ => panic!("the last commit must be a block"),
+    };
+    // Another block at the same height.
+    let invalid_commit = create_commit(&mut raw, &commits[2], &block_header).await;
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let branches = drepo.raw.list_branches().await.unwrap();
+
+    let verification = drepo.verify_branch(&commit_hashes[2]).await.unwrap();
+    assert!(verification.is_valid(), "{:?}", verification.error);
+    assert_eq!(verification.phase, Phase::Block);
+    assert_eq!(
+        verification.finalizable_block,
+        Some((commit_hashes[2], block_header.clone()))
+    );
+
+    let verification = drepo.verify_branch(&commit_hashes[1]).await.unwrap();
+    assert!(verification.is_valid(), "{:?}", verification.error);
+    assert_eq!(verification.phase, Phase::AgendaProof);
+    assert_eq!(verification.finalizable_block, None);
+
+    let verification = drepo.verify_branch(&invalid_commit).await.unwrap();
+    assert!(!verification.is_valid());
+    assert_eq!(verification.phase, Phase::Block);
+    assert_eq!(
+        verification.finalizable_block,
+        Some((commit_hashes[2], block_header))
+    );
+
+    assert_eq!(drepo.raw.list_branches().await.unwrap(), branches);
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        genesis_commit
+    );
+}
+
+/// Reads commits through the cache, and checks which ones are kept.
+#[tokio::test]
+async fn commit_cache() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let last_header = generate_genesis_header(&keys);
+    let mut commit_hashes = Vec::new();
+    for commit in generate_next_block_commits(&keys, &last_header) {
+        commit_hashes.push(create_commit(&mut raw, &commit, &last_header).await);
+    }
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let is_cached = |drepo: &DistributedRepository<RawRepositoryImpl>, commit_hash| {
+        drepo
+            .commit_cache
+            .lock()
+            .unwrap()
+            .entries
+            .contains_key(commit_hash)
+    };
+
+    drepo.set_commit_cache_capacity(2);
+    for commit_hash in &commit_hashes {
+        drepo.get_commit(commit_hash).await.unwrap();
+    }
+    // The first one is evicted as the least recently used one.
+    assert!(!is_cached(&drepo, &commit_hashes[0]));
+    assert!(is_cached(&drepo, &commit_hashes[1]));
+    assert!(is_cached(&drepo, &commit_hashes[2]));
+
+    // A hit makes it the most recently used one.
+    let commit = drepo.get_commit(&commit_hashes[1]).await.unwrap();
+    assert_eq!(
+        commit,
+        drepo
+            .commit_cache
+            .lock()
+            .unwrap()
+            .entries
+            .get(&commit_hashes[1])
+            .unwrap()
+            .1
+    );
+    drepo.get_commit(&commit_hashes[0]).await.unwrap();
+    assert!(is_cached(&drepo, &commit_hashes[0]));
+    assert!(is_cached(&drepo, &commit_hashes[1]));
+    assert!(!is_cached(&drepo, &commit_hashes[2]));
+
+    drepo.set_commit_cache_capacity(0);
+    drepo.get_commit(&commit_hashes[2]).await.unwrap();
+    assert!(drepo.commit_cache.lock().unwrap().entries.is_empty());
+}
+
+/// Finalizes two blocks and checks that each gets its `block-<height>` tag.
+#[tokio::test]
+async fn finalize_block_tag() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commits = Vec::new();
+    for _ in 0..2 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_commits.push((commit_hash, last_header.clone()));
+    }
+    let generate_proof = |header: &BlockHeader| {
+        keys.iter()
+            .map(|(_, private_key)| TypedSignature::sign(header, private_key).unwrap())
+            .collect::<FinalizationProof>()
+    };
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let (block_commit, block_header) = &block_commits[0];
+    let finalized = drepo
+        .finalize(block_commit, &generate_proof(block_header))
+        .await
+        .unwrap();
+    assert_eq!(
+        finalized,
+        Finalized {
+            height: 1,
+            block_hash: block_header.to_hash256(),
+            commit_hash: *block_commit,
+        }
+    );
+    assert_eq!(
+        drepo.raw.locate_tag("block-1".to_owned()).await.unwrap(),
+        *block_commit
+    );
+    let reflog = drepo
+        .raw
+        .read_reflog(FINALIZED_BRANCH_NAME.into(), Some(1))
+        .await
+        .unwrap();
+    assert_eq!(reflog[0].message, "finalize: advance to block 1");
+
+    // A stale tag left by an earlier attempt is overwritten.
+    drepo
+        .raw
+        .create_tag("block-2".to_owned(), genesis_commit, None)
+        .await
+        .unwrap();
+    let (block_commit, block_header) = &block_commits[1];
+    drepo
+        .finalize(block_commit, &generate_proof(block_header))
+        .await
+        .unwrap();
+    assert_eq!(
+        drepo.raw.locate_tag("block-2".to_owned()).await.unwrap(),
+        *block_commit
+    );
+    assert!(drepo.check(0).await.unwrap());
+}
+
+/// Finalizes two blocks and rewinds `finalized` back to the first one.
+#[tokio::test]
+async fn rewind_finalized() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commits = Vec::new();
+    for _ in 0..2 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_commits.push((commit_hash, last_header.clone()));
+    }
+    let generate_proof = |header: &BlockHeader| {
+        keys.iter()
+            .map(|(_, private_key)| TypedSignature::sign(header, private_key).unwrap())
+            .collect::<FinalizationProof>()
+    };
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    for (block_commit, block_header) in &block_commits {
+        drepo
+            .finalize(block_commit, &generate_proof(block_header))
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        drepo
+            .get_last_finalized_block_header()
+            .await
+            .unwrap()
+            .height,
+        2
+    );
+
+    let (block_commit, _) = &block_commits[0];
+    drepo
+        .rewind_finalized(block_commit, false)
+        .await
+        .unwrap_err();
+    // Only an ancestor of the finalized block can be the target.
+    drepo
+        .rewind_finalized(&block_commits[1].0, true)
+        .await
+        .unwrap_err();
+    drepo.rewind_finalized(block_commit, true).await.unwrap();
+    assert_eq!(
+        drepo
+            .get_last_finalized_block_header()
+            .await
+            .unwrap()
+            .height,
+        1
+    );
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        *block_commit
+    );
+    assert!(!drepo
+        .raw
+        .list_tags()
+        .await
+        .unwrap()
+        .contains(&"block-2".to_owned()));
+    assert!(drepo.check(0).await.unwrap());
+}
+
+/// Finalizes two blocks, and reads the proofs of the genesis block and both of them.
+#[tokio::test]
+async fn get_proof_at_height() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commits = Vec::new();
+    for _ in 0..2 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_commits.push((commit_hash, last_header.clone()));
+    }
+    // Signed by three of the four validators, unlike the ones embedded in the blocks.
+    let generate_proof = |header: &BlockHeader| {
+        keys[..3]
+            .iter()
+            .map(|(_, private_key)| TypedSignature::sign(header, private_key).unwrap())
+            .collect::<FinalizationProof>()
+    };
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    for (block_commit, block_header) in &block_commits {
+        drepo
+            .finalize(block_commit, &generate_proof(block_header))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(
+        drepo.get_proof_at_height(0).await.unwrap(),
+        block_commits[0].1.prev_block_finalization_proof
+    );
+    assert_eq!(
+        drepo.get_proof_at_height(1).await.unwrap(),
+        block_commits[1].1.prev_block_finalization_proof
+    );
+    assert_eq!(
+        drepo.get_proof_at_height(2).await.unwrap(),
+        generate_proof(&block_commits[1].1)
+    );
+    drepo.get_proof_at_height(3).await.unwrap_err();
+}
+
+/// Builds a chain with a malformed commit before the second block,
+/// and reads the proof of the genesis block embedded in the first block over it.
+#[tokio::test]
+async fn get_proof_at_height_skips_malformed_commits() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    // Commits on the `finalized` branch directly, bypassing the verification.
+    let mut raw = setup_genesis(path, &keys).await;
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_headers = Vec::new();
+    for height in 1..=2 {
+        if height == 2 {
+            raw.create_commit("malformed".to_owned(), Some("".to_owned()))
+                .await
+                .unwrap();
+        }
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_headers.push(last_header.clone());
+    }
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    assert_eq!(
+        drepo.get_proof_at_height(0).await.unwrap(),
+        block_headers[0].prev_block_finalization_proof
+    );
+}
+
+/// Tries to finalize the second block over the genesis block, skipping the first one.
+#[tokio::test]
+async fn finalize_height_gap() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commit = genesis_commit;
+    for _ in 0..2 {
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            block_commit = create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+    }
+    let proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&last_header, private_key).unwrap())
+        .collect::<FinalizationProof>();
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let error = drepo.finalize(&block_commit, &proof).await.unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("has height 2, but the last finalized height is 0"),
+        "{}",
+        error
+    );
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        genesis_commit
+    );
+    assert!(!drepo
+        .raw
+        .list_branches()
+        .await
+        .unwrap()
+        .contains(&FP_BRANCH_NAME.to_owned()));
+}
+
+#[tokio::test]
+async fn finalize_from_json() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let genesis_header = generate_genesis_header(&keys);
+    let mut last_header = genesis_header.clone();
+    let mut block_commit = genesis_commit;
+    for commit in generate_next_block_commits(&keys, &genesis_header) {
+        block_commit = create_commit(&mut raw, &commit, &genesis_header).await;
+        if let Commit::Block(block_header) = commit {
+            last_header = block_header;
+        }
+    }
+    let proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&last_header, private_key).unwrap())
+        .collect::<FinalizationProof>();
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let error = drepo
+        .finalize_from_json(&block_commit, "{\"not\": \"a proof\"}")
+        .await
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("malformed finalization proof"),
+        "{}",
+        error
+    );
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        genesis_commit
+    );
+
+    let finalized = drepo
+        .finalize_from_json(&block_commit, &serde_json::to_string(&proof).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(finalized.height, 1);
+    assert_eq!(finalized.commit_hash, block_commit);
+    assert_eq!(drepo.get_last_finalization_proof().await.unwrap(), proof);
+}
+
+/// Moves `finalized` with `sync()`, `finalize()` and `rewind_finalized()`,
+/// and receives an event for each move but none for a failed finalization.
+#[tokio::test]
+async fn subscribe() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commits = Vec::new();
+    for _ in 0..2 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_commits.push((commit_hash, last_header.clone()));
+    }
+    let finalized = |(commit_hash, header): &(CommitHash, BlockHeader)| Finalized {
+        height: header.height,
+        block_hash: header.to_hash256(),
+        commit_hash: *commit_hash,
+    };
+    let proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&last_header, private_key).unwrap())
+        .collect::<FinalizationProof>();
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let mut events = drepo.subscribe();
+    // Syncing to the second block moves `finalized` to the first one.
+    drepo.sync(&block_commits[1].0).await.unwrap();
+    assert_eq!(
+        events.recv().await.unwrap(),
+        RepositoryEvent::Finalized(finalized(&block_commits[0]))
+    );
+
+    // A failed finalization sends nothing.
+    drepo
+        .finalize(&block_commits[1].0, &FinalizationProof::new())
+        .await
+        .unwrap_err();
+    let result = drepo.finalize(&block_commits[1].0, &proof).await.unwrap();
+    assert_eq!(result, finalized(&block_commits[1]));
+    assert_eq!(
+        events.recv().await.unwrap(),
+        RepositoryEvent::Finalized(finalized(&block_commits[1]))
+    );
+
+    drepo
+        .rewind_finalized(&block_commits[0].0, true)
+        .await
+        .unwrap();
+    assert_eq!(
+        events.recv().await.unwrap(),
+        RepositoryEvent::Rewound(finalized(&block_commits[0]))
+    );
+    assert!(events.try_recv().is_err());
+}
+
+/// Creates two agendas and approves one of them, which is then no longer actionable.
+#[tokio::test]
+async fn get_actionable_agendas() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+
+    // An approved agenda
+    raw.create_branch("approved".to_owned(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout("approved".to_owned()).await.unwrap();
+    let commits = generate_next_block_commits(&keys, &last_header);
+    let approved_agenda_commit = create_commit(&mut raw, &commits[0], &last_header).await;
+    raw.create_branch("agenda".to_owned(), approved_agenda_commit)
+        .await
+        .unwrap();
+    create_commit(&mut raw, &commits[1], &last_header).await;
+
+    // A pending agenda
+    raw.create_branch("pending".to_owned(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout("pending".to_owned()).await.unwrap();
+    let agenda = Commit::Agenda(Agenda {
+        author: keys[1].0.clone(),
+        timestamp: 1,
+        hash: Agenda::calculate_hash(last_header.height, &[]),
+    });
+    let pending_agenda_commit = create_commit(&mut raw, &agenda, &last_header).await;
+    raw.checkout(FINALIZED_BRANCH_NAME.into()).await.unwrap();
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let agendas = drepo.get_agendas().await.unwrap();
+    assert_eq!(agendas.len(), 2);
+    let actionable_agendas = drepo.get_actionable_agendas().await.unwrap();
+    assert_eq!(
+        actionable_agendas
+            .iter()
+            .map(|agenda| agenda.commit)
+            .collect::<Vec<_>>(),
+        vec![pending_agenda_commit]
+    );
+}
+
+/// Creates two agendas and vetoes one of them by two of the four members, which is past
+/// the threshold, and the other one by a single member, which is not.
+#[tokio::test]
+async fn get_actionable_agendas_vetoed() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+
+    let mut agenda_commits = Vec::new();
+    for (i, branch) in ["vetoed", "pending"].into_iter().enumerate() {
+        raw.create_branch(branch.to_owned(), genesis_commit)
+            .await
+            .unwrap();
+        raw.checkout(branch.to_owned()).await.unwrap();
+        let agenda = Commit::Agenda(Agenda {
+            author: keys[i].0.clone(),
+            timestamp: i as Timestamp,
+            hash: Agenda::calculate_hash(last_header.height, &[]),
+        });
+        agenda_commits.push(create_commit(&mut raw, &agenda, &last_header).await);
+    }
+    raw.checkout(FINALIZED_BRANCH_NAME.into()).await.unwrap();
+    // The last one refers to no member, so it is ignored.
+    for (tag, commit_hash) in [
+        ("veto-0", agenda_commits[0]),
+        ("veto-1", agenda_commits[0]),
+        ("veto-2", agenda_commits[1]),
+        ("veto-9", agenda_commits[1]),
+    ] {
+        raw.create_tag(tag.to_owned(), commit_hash, None)
+            .await
+            .unwrap();
+    }
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    assert_eq!(drepo.get_agendas().await.unwrap().len(), 2);
+    let actionable_agendas = drepo.get_actionable_agendas().await.unwrap();
+    assert_eq!(
+        actionable_agendas
+            .iter()
+            .map(|agenda| agenda.commit)
+            .collect::<Vec<_>>(),
+        vec![agenda_commits[1]]
+    );
+}
+
+/// Appends transactions to the `work` branch and counts them.
+#[tokio::test]
+async fn pending_transaction_count() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    for i in 0..3 {
+        let transaction = Commit::Transaction(Transaction {
+            author: keys[0].0.clone(),
+            timestamp: i,
+            head: format!("transaction {}", i),
+            body: "body".to_owned(),
+            diff: Diff::None,
+        });
+        create_commit(&mut raw, &transaction, &last_header).await;
+    }
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    assert_eq!(drepo.pending_transaction_count().await.unwrap(), 3);
+    // The agenda is not a transaction.
+    drepo.create_agenda(keys[0].0.clone()).await.unwrap();
+    assert_eq!(drepo.pending_transaction_count().await.unwrap(), 3);
+
+    // Not rebased
+    drepo
+        .raw
+        .create_branch("other".to_owned(), genesis_commit)
+        .await
+        .unwrap();
+    drepo
+        .raw
+        .create_commit_on_branch("other".to_owned(), "other".to_owned(), None)
+        .await
+        .unwrap();
+    let other_commit = drepo.raw.locate_branch("other".to_owned()).await.unwrap();
+    drepo
+        .raw
+        .move_branch(
+            FINALIZED_BRANCH_NAME.into(),
+            other_commit,
+            false,
+            "test".to_owned(),
+        )
+        .await
+        .unwrap();
+    drepo.pending_transaction_count().await.unwrap_err();
+}
+
+/// Creates an agenda on top of a transaction and checks that its hash is the one `get_agendas()` returns.
+#[tokio::test]
+async fn create_agenda() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let transaction = Commit::Transaction(Transaction {
+        author: keys[0].0.clone(),
+        timestamp: 1,
+        head: "transaction".to_owned(),
+        body: "body".to_owned(),
+        diff: Diff::None,
+    });
+    create_commit(&mut raw, &transaction, &last_header).await;
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    assert_eq!(drepo.get_agendas().await.unwrap(), vec![]);
+    let (agenda_commit, agenda_hash) = drepo.create_agenda(keys[0].0.clone()).await.unwrap();
+    let agenda = match drepo.get_commit(&agenda_commit).await.unwrap() {
+        Commit::Agenda(agenda) => agenda,
+        commit => panic!("not an agenda: {:?}", commit),
+    };
+    assert_eq!(
+        drepo.get_agendas().await.unwrap(),
+        vec![AgendaInfo {
+            commit: agenda_commit,
+            hash: agenda_hash,
+            author: keys[0].0.clone(),
+            timestamp: agenda.timestamp,
+            height: 1,
+        }]
+    );
+}
+
+/// Calls `create_agenda()` from two tasks sharing the repository,
+/// and checks that exactly one of them succeeds.
+#[tokio::test]
+async fn create_agenda_concurrently() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+
+    let drepo = Arc::new(tokio::sync::Mutex::new(
+        DistributedRepository::new(raw).await.unwrap(),
+    ));
+    let handles = keys
+        .iter()
+        .take(2)
+        .map(|(public_key, _)| {
+            let drepo = Arc::clone(&drepo);
+            let public_key = public_key.clone();
+            tokio::spawn(async move { drepo.lock().await.create_agenda(public_key).await })
+        })
+        .collect::<Vec<_>>();
+    let mut results = Vec::new();
+    for handle in handles {
+        results.push(handle.await.unwrap());
+    }
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+    let error = results.into_iter().find_map(Result::err).unwrap();
+    assert_eq!(
+        error.downcast_ref::<PhaseError>().unwrap().actual,
+        Phase::Agenda
+    );
+    assert_eq!(drepo.lock().await.get_agendas().await.unwrap().len(), 1);
+}
+
+/// Creates agendas on the same transactions in two different orders,
+/// and checks that they have the same agenda hash.
+#[tokio::test]
+async fn create_agenda_transaction_order() {
+    let keys = generate_keys(4);
+    let last_header = generate_genesis_header(&keys);
+    let transactions = (0..3)
+        .map(|i| {
+            Commit::Transaction(Transaction {
+                author: keys[0].0.clone(),
+                // Transactions of the same timestamp may be in any order.
+                timestamp: 1,
+                head: format!("transaction {}", i),
+                body: "body".to_owned(),
+                diff: Diff::None,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut agenda_hashes = Vec::new();
+    for order in [[0, 1, 2], [2, 0, 1]] {
+        let td = TempDir::new().unwrap();
+        let path = td.path().to_str().unwrap();
+        let mut raw = setup_genesis(path, &keys).await;
+        let genesis_commit = raw.get_head().await.unwrap();
+        raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+            .await
+            .unwrap();
+        raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+        for i in order {
+            create_commit(&mut raw, &transactions[i], &last_header).await;
+        }
+
+        let mut drepo = DistributedRepository::new(raw).await.unwrap();
+        let (agenda_commit, _) = drepo.create_agenda(keys[0].0.clone()).await.unwrap();
+        match drepo.get_commit(&agenda_commit).await.unwrap() {
+            Commit::Agenda(agenda) => agenda_hashes.push(agenda.hash),
+            commit => panic!("not an agenda: {:?}", commit),
+        }
+    }
+    assert_eq!(agenda_hashes[0], agenda_hashes[1]);
+}
+
+/// Builds a chain whose second block has a broken proof of the first one,
+/// and checks that the chain verification reports the second block.
+#[tokio::test]
+async fn verify_finalization_chain() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    // Commits on the `finalized` branch directly, bypassing the verification.
+    let mut raw = setup_genesis(path, &keys).await;
+    let mut last_header = generate_genesis_header(&keys);
+    for height in 1..=3 {
+        let mut commits = generate_next_block_commits(&keys, &last_header);
+        if let Some(Commit::Block(block_header)) = commits.last_mut() {
+            if height == 2 {
+                // Not enough voting power.
+                block_header.prev_block_finalization_proof.truncate(1);
+            }
+        }
+        for commit in commits {
+            create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+    }
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let error = drepo.verify_finalization_chain(0).await.unwrap_err();
+    assert!(error.to_string().contains("at height 2:"), "{}", error);
+    drepo.verify_finalization_chain(2).await.unwrap();
+}
+
+/// Builds a chain with a malformed commit before the second block and another chain
+/// that skips a height, and checks that the chain verification reports both.
+#[tokio::test]
+async fn verify_finalization_chain_broken_links() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let mut last_header = generate_genesis_header(&keys);
+    for height in 1..=2 {
+        if height == 2 {
+            raw.create_commit("malformed".to_owned(), Some("".to_owned()))
+                .await
+                .unwrap();
+        }
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+    }
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let error = drepo.verify_finalization_chain(0).await.unwrap_err();
+    assert!(error.to_string().contains("below height 2:"), "{}", error);
+    // The malformed commit is between the first and the second block.
+    drepo.verify_finalization_chain(1).await.unwrap_err();
+    drepo.verify_finalization_chain(2).await.unwrap();
+
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let mut raw = setup_genesis(path, &keys).await;
+    let mut last_header = generate_genesis_header(&keys);
+    for height in 1..=2 {
+        let mut commits = generate_next_block_commits(&keys, &last_header);
+        if let Some(Commit::Block(block_header)) = commits.last_mut() {
+            if height == 2 {
+                block_header.height += 1;
+            }
+        }
+        for commit in commits {
+            create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+    }
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let error = drepo.verify_finalization_chain(0).await.unwrap_err();
+    assert!(error.to_string().contains("at height 3:"), "{}", error);
+}
+
+/// Builds a chain whose second block has a broken proof of the first one,
+/// and checks the repository from the heights below and above it.
+#[tokio::test]
+async fn check_from_starting_height() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    // Commits on the `finalized` branch directly, bypassing the verification.
+    let mut raw = setup_genesis(path, &keys).await;
+    let mut last_header = generate_genesis_header(&keys);
+    for height in 1..=3 {
+        let mut commits = generate_next_block_commits(&keys, &last_header);
+        if let Some(Commit::Block(block_header)) = commits.last_mut() {
+            if height == 2 {
+                // Not enough voting power.
+                block_header.prev_block_finalization_proof.truncate(1);
+            }
+        }
+        for commit in commits {
+            create_commit(&mut raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+    }
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    assert!(!drepo.check(0).await.unwrap());
+    assert!(!drepo.check(1).await.unwrap());
+    // The broken block is trusted since it is not above the starting height.
+    assert!(drepo.check(2).await.unwrap());
+    assert!(drepo.check(3).await.unwrap());
+    assert!(drepo.check(4).await.is_err());
+}
+
+/// Creates a block that diverges from `finalized`, and checks that it is reported as a fork.
+#[tokio::test]
+async fn check_health_forks() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let commits = generate_next_block_commits(&keys, &last_header);
+    let mut block_commit = genesis_commit;
+    for commit in &commits {
+        block_commit = create_commit(&mut raw, commit, &last_header).await;
+    }
+    raw.create_branch("b-0".to_owned(), block_commit)
+        .await
+        .unwrap();
+
+    // Another block of the same height
+    raw.create_branch("b-1".to_owned(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout("b-1".to_owned()).await.unwrap();
+    let mut fork_commits = commits;
+    if let Some(Commit::Block(block_header)) = fork_commits.last_mut() {
+        block_header.timestamp += 1;
+    }
+    for commit in &fork_commits {
+        create_commit(&mut raw, commit, &last_header).await;
+    }
+    raw.checkout(FINALIZED_BRANCH_NAME.into()).await.unwrap();
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    assert_eq!(
+        drepo.check_health(0).await.unwrap(),
+        RepositoryHealth {
+            valid: true,
+            forks: vec![("b-1".to_owned(), 1)],
+            merges: vec![],
+        }
+    );
+}
+
+/// Puts a merge commit on an `a-#` branch, and checks that only that branch is reported.
+#[tokio::test]
+async fn check_merge_commits() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    let side_commit = raw
+        .create_commit_with_parents("side".to_owned(), vec![], vec![genesis_commit])
+        .await
+        .unwrap();
+    let merge_commit = raw
+        .create_commit_with_parents(
+            "merge".to_owned(),
+            vec![],
+            vec![genesis_commit, side_commit],
+        )
+        .await
+        .unwrap();
+    raw.create_branch("a-0".to_owned(), merge_commit)
+        .await
+        .unwrap();
+    // Branches other than the reserved ones are ignored.
+    raw.create_branch("other".to_owned(), merge_commit)
+        .await
+        .unwrap();
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    assert!(!drepo.check(0).await.unwrap());
+    assert_eq!(
+        drepo.check_health(0).await.unwrap(),
+        RepositoryHealth {
+            valid: false,
+            forks: vec![],
+            merges: vec![("a-0".to_owned(), merge_commit)],
+        }
+    );
+}
+
+/// Checks the repository with a valid and an invalid `fp` branch, and an unrelated `work` branch.
+#[tokio::test]
+async fn check_reserved_branches() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let last_header = generate_genesis_header(&keys);
+    let mut block = None;
+    for commit in generate_next_block_commits(&keys, &last_header) {
+        let commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+        if let Commit::Block(block_header) = commit {
+            block = Some((commit_hash, block_header));
+        }
+    }
+    let (block_commit, block_header) = block.unwrap();
+    let generate_fp_message = |signers: usize| {
+        let proof = keys[..signers]
+            .iter()
+            .map(|(_, private_key)| TypedSignature::sign(&block_header, private_key).unwrap())
+            .collect::<FinalizationProof>();
+        format!("fp\n\n{}", serde_json::to_string(&proof).unwrap())
+    };
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    let fp_commit = drepo
+        .raw
+        .create_commit_with_parents(generate_fp_message(3), vec![], vec![block_commit])
+        .await
+        .unwrap();
+    drepo
+        .raw
+        .create_branch(FP_BRANCH_NAME.into(), fp_commit)
+        .await
+        .unwrap();
+    assert!(drepo.check(0).await.unwrap());
+
+    // Not enough voting power
+    let fp_commit = drepo
+        .raw
+        .create_commit_with_parents(generate_fp_message(1), vec![], vec![block_commit])
+        .await
+        .unwrap();
+    drepo
+        .raw
+        .move_branch(FP_BRANCH_NAME.into(), fp_commit, false, "test".to_owned())
+        .await
+        .unwrap();
+    assert!(!drepo.check(0).await.unwrap());
+    drepo
+        .raw
+        .delete_branch(FP_BRANCH_NAME.into())
+        .await
+        .unwrap();
+
+    // A `work` branch with a history of its own
+    let work_commit = drepo
+        .raw
+        .create_commit_with_parents("unrelated".to_owned(), vec![], vec![])
+        .await
+        .unwrap();
+    drepo
+        .raw
+        .create_branch(WORK_BRANCH_NAME.into(), work_commit)
+        .await
+        .unwrap();
+    assert!(!drepo.check(0).await.unwrap());
+}
+
+/// Places valid and malformed reserved tags, and checks that only the latter fail the check.
+#[tokio::test]
+async fn check_reserved_tags() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let last_header = generate_genesis_header(&keys);
+    let mut agenda_commit = None;
+    let mut block_commit = None;
+    for commit in generate_next_block_commits(&keys, &last_header) {
+        let commit_hash = create_commit(&mut raw, &commit, &last_header).await;
+        match commit {
+            Commit::Agenda(_) => agenda_commit = Some(commit_hash),
+            Commit::Block(_) => block_commit = Some(commit_hash),
+            _ => (),
+        }
+    }
+    let (agenda_commit, block_commit) = (agenda_commit.unwrap(), block_commit.unwrap());
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    drepo
+        .raw
+        .create_tag("vote-0".to_owned(), agenda_commit, None)
+        .await
+        .unwrap();
+    drepo
+        .raw
+        .create_tag("veto-0".to_owned(), block_commit, None)
+        .await
+        .unwrap();
+    drepo
+        .raw
+        .create_tag("other".to_owned(), agenda_commit, None)
+        .await
+        .unwrap();
+    assert!(drepo.check(0).await.unwrap());
+
+    // A vote on a block
+    drepo
+        .raw
+        .create_tag("vote-1".to_owned(), block_commit, None)
+        .await
+        .unwrap();
+    assert!(!drepo.check(0).await.unwrap());
+    drepo.raw.remove_tag("vote-1".to_owned()).await.unwrap();
+
+    // A block tag with a wrong height
+    drepo
+        .raw
+        .create_tag("block-2".to_owned(), block_commit, None)
+        .await
+        .unwrap();
+    assert!(!drepo.check(0).await.unwrap());
+    drepo.raw.remove_tag("block-2".to_owned()).await.unwrap();
+
+    // Not a number
+    drepo
+        .raw
+        .create_tag("veto-x".to_owned(), block_commit, None)
+        .await
+        .unwrap();
+    assert!(!drepo.check(0).await.unwrap());
+}
+
+/*
+    a2 (work, a-0, vote-0)
+    |
+    b1 (finalized)
+    |
+    ap1
+    |
+    a1 (vote-2)  a1' (a-1, vote-1)
+    |           /
+    b0 ---------
+*/
+/// Cleans the repository, and checks that only the outdated `a-1` branch and the vote on it
+/// are removed.
+#[tokio::test]
+async fn clean() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    let genesis_header = generate_genesis_header(&keys);
+    let commits = generate_next_block_commits(&keys, &genesis_header);
+
+    // An agenda that will be outdated by the finalization of the first block.
+    let mut outdated_agenda = match &commits[0] {
+        Commit::Agenda(agenda) => agenda.clone(),
+        _ => panic!("the first commit must be an agenda"),
+    };
+    outdated_agenda.timestamp += 10;
+    raw.create_branch("a-1".to_owned(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout("a-1".to_owned()).await.unwrap();
+    let outdated_agenda_commit =
+        create_commit(&mut raw, &Commit::Agenda(outdated_agenda), &genesis_header).await;
+
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let mut commit_hashes = Vec::new();
+    for commit in &commits {
+        commit_hashes.push(create_commit(&mut raw, commit, &genesis_header).await);
+    }
+    let block_header = match &commits[2] {
+        Commit::Block(block_header) => block_header.clone(),
+        _ => panic!("the last commit must be a block"),
+    };
+    let proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&block_header, private_key).unwrap())
+        .collect::<FinalizationProof>();
+    let live_agenda = generate_next_block_commits(&keys, &block_header).remove(0);
+    let live_agenda_commit = create_commit(&mut raw, &live_agenda, &block_header).await;
+    raw.create_branch("a-0".to_owned(), live_agenda_commit)
+        .await
+        .unwrap();
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    drepo.finalize(&commit_hashes[2], &proof).await.unwrap();
+    for (tag, commit_hash) in [
+        ("vote-0", live_agenda_commit),
+        ("vote-1", outdated_agenda_commit),
+        ("vote-2", commit_hashes[0]),
+    ] {
+        drepo
+            .raw
+            .create_tag(tag.to_owned(), commit_hash, None)
+            .await
+            .unwrap();
+    }
+
+    drepo.clean().await.unwrap();
+    let branches = drepo.raw.list_branches().await.unwrap();
+    assert!(branches.contains(&"a-0".to_owned()));
+    assert!(!branches.contains(&"a-1".to_owned()));
+    let mut tags = drepo.raw.list_tags().await.unwrap();
+    tags.retain(|tag| tag.starts_with(VOTE_TAG_PREFIX));
+    assert_eq!(tags, vec!["vote-0".to_owned(), "vote-2".to_owned()]);
+}
+
+/// Tries to create commits in wrong phases and checks the phases in the errors,
+/// and then creates a block in the right phase, rejecting a non-proposer.
+#[tokio::test]
+async fn phase_error() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let commits = generate_next_block_commits(&keys, &last_header);
+    create_commit(&mut raw, &commits[0], &last_header).await;
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    let get_phase_error = |error: Error| error.downcast_ref::<PhaseError>().unwrap().clone();
+    let error = drepo.create_agenda(keys[0].0.clone()).await.unwrap_err();
+    assert_eq!(
+        get_phase_error(error),
+        PhaseError {
+            expected: Phase::Transaction,
+            actual: Phase::Agenda
+        }
+    );
+    let error = drepo.create_block(keys[0].0.clone()).await.unwrap_err();
+    assert_eq!(
+        get_phase_error(error),
+        PhaseError {
+            expected: Phase::AgendaProof,
+            actual: Phase::Agenda
+        }
+    );
+    let transaction = ExtraAgendaTransaction::Delegate(TxDelegate {
+        delegator: keys[0].0.clone(),
+        delegatee: keys[1].0.clone(),
+        governance: true,
+        // The tuple can't be signed directly, so reuse a signature's format.
+        proof: serde_json::from_value(
+            serde_json::to_value(TypedSignature::sign(&last_header, &keys[0].1).unwrap()).unwrap(),
+        )
+        .unwrap(),
+        timestamp: 1,
+    });
+    let error = drepo
+        .create_extra_agenda_transaction(&transaction)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        get_phase_error(error),
+        PhaseError {
+            expected: Phase::AgendaProof,
+            actual: Phase::Agenda
+        }
+    );
+
+    create_commit(&mut drepo.raw, &commits[1], &last_header).await;
+    let error = drepo.create_block(keys[1].0.clone()).await.unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("is not the proposer of height 1"),
+        "{}",
+        error
+    );
+    let (block_commit, block_header) = drepo.create_block(keys[0].0.clone()).await.unwrap();
+    assert_eq!(block_header.height, last_header.height + 1);
+    assert_eq!(block_header.previous_hash, last_header.to_hash256());
+    assert_eq!(
+        drepo.get_commit(&block_commit).await.unwrap(),
+        Commit::Block(block_header)
+    );
+    drepo.sync(&block_commit).await.unwrap();
+}
+
+/// Creates a block with a consensus leader order other than the order of the members,
+/// and checks that only the first leader can propose it.
+#[tokio::test]
+async fn create_block_proposer() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut reserved_state = generate_reserved_state(&keys);
+    reserved_state.consensus_leader_order.reverse();
+    let mut raw = setup_reserved_state(path, &reserved_state).await;
+    let last_header = generate_genesis_header(&keys);
+    let genesis_commit =
+        create_commit(&mut raw, &Commit::Block(last_header.clone()), &last_header).await;
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let commits = generate_next_block_commits(&keys, &last_header);
+    for commit in &commits[..2] {
+        create_commit(&mut raw, commit, &last_header).await;
+    }
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    let error = drepo.create_block(keys[0].0.clone()).await.unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("is not the proposer of height 1"),
+        "{}",
+        error
+    );
+    let (_, block_header) = drepo.create_block(keys[3].0.clone()).await.unwrap();
+    assert_eq!(block_header.author, keys[3].0);
+}
+
+/// Creates a block, which `get_blocks()` reports with the hash of the created header.
+#[tokio::test]
+async fn get_blocks() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let commits = generate_next_block_commits(&keys, &last_header);
+    create_commit(&mut raw, &commits[0], &last_header).await;
+    create_commit(&mut raw, &commits[1], &last_header).await;
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    assert_eq!(drepo.get_blocks().await.unwrap(), vec![]);
+
+    let (block_commit, block_header) = drepo.create_block(keys[0].0.clone()).await.unwrap();
+    drepo
+        .raw
+        .create_branch("b-0".into(), block_commit)
+        .await
+        .unwrap();
+    assert_eq!(
+        drepo.get_blocks().await.unwrap(),
+        vec![(block_commit, block_header.to_hash256())]
+    );
+}
+
+/// Creates a block and checks it against a different header, as if the format had drifted,
+/// which fails and moves `work` back.
+#[tokio::test]
+async fn create_block_read_back_mismatch() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let commits = generate_next_block_commits(&keys, &last_header);
+    create_commit(&mut raw, &commits[0], &last_header).await;
+    let agenda_proof_commit = create_commit(&mut raw, &commits[1], &last_header).await;
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    let (block_commit, mut block_header) = drepo.create_block(keys[0].0.clone()).await.unwrap();
+    block_header.timestamp += 1;
+    let error = drepo
+        .check_block_read_back(&block_commit, &block_header, &agenda_proof_commit)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("doesn't read back"), "{}", error);
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(WORK_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        agenda_proof_commit
+    );
+}
+
+/// Approves an agenda on `work`, and verifies the proof read back from the new commit.
+#[tokio::test]
+async fn approve() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let commits = generate_next_block_commits(&keys, &last_header);
+    let agenda_commit = create_commit(&mut raw, &commits[0], &last_header).await;
+    let agenda = match &commits[0] {
+        Commit::Agenda(agenda) => agenda.clone(),
+        _ => panic!("the first commit must be an agenda"),
+    };
+    let proof = keys
+        .iter()
+        .map(|(public_key, private_key)| {
+            (
+                public_key.clone(),
+                TypedSignature::sign(&agenda, private_key).unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    // Not enough voting power
+    drepo
+        .approve(&agenda_commit, proof[..2].to_vec())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(WORK_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        agenda_commit
+    );
+
+    let agenda_proof_commit = drepo.approve(&agenda_commit, proof.clone()).await.unwrap();
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(WORK_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        agenda_proof_commit
+    );
+    match drepo.get_commit(&agenda_proof_commit).await.unwrap() {
+        Commit::AgendaProof(agenda_proof) => {
+            assert_eq!(agenda_proof.agenda_hash, agenda.hash);
+            assert_eq!(
+                agenda_proof.proof,
+                proof
+                    .into_iter()
+                    .map(|(_, signature)| signature)
+                    .collect::<Vec<_>>()
+            );
+            for signature in &agenda_proof.proof {
+                signature.verify(&agenda).unwrap();
+            }
+        }
+        commit => panic!("not an agenda proof: {:?}", commit),
+    }
+    drepo
+        .verify_agenda_proof(&agenda_proof_commit)
+        .await
+        .unwrap();
+    drepo.create_block(keys[0].0.clone()).await.unwrap();
+}
+
+/// Tries to create a block on an agenda proof without enough voting power.
+#[tokio::test]
+async fn create_block_with_insufficient_agenda_proof() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let mut commits = generate_next_block_commits(&keys, &last_header);
+    if let Commit::AgendaProof(agenda_proof) = &mut commits[1] {
+        agenda_proof.proof.truncate(2);
+    }
+    create_commit(&mut raw, &commits[0], &last_header).await;
+    create_commit(&mut raw, &commits[1], &last_header).await;
+
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let error = drepo.create_block(keys[0].0.clone()).await.unwrap_err();
+    assert!(
+        error.to_string().contains("the agenda is not approved"),
+        "{}",
+        error
+    );
+}
+
+/// Creates extra-agenda transactions of each kind, with and without their preconditions met,
+/// and checks the reserved states they carry.
+#[tokio::test]
+async fn create_extra_agenda_transaction() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let genesis_commit = raw.get_head().await.unwrap();
+    raw.create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let commits = generate_next_block_commits(&keys, &last_header);
+    create_commit(&mut raw, &commits[0], &last_header).await;
+    create_commit(&mut raw, &commits[1], &last_header).await;
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    // The tuples can't be signed directly, so reuse a signature's format.
+    let signature =
+        serde_json::to_value(TypedSignature::sign(&last_header, &keys[0].1).unwrap()).unwrap();
+    let (non_member, _) = generate_keypair([100]);
+    let delegate = |delegator: &PublicKey, delegatee: &PublicKey| {
+        ExtraAgendaTransaction::Delegate(TxDelegate {
+            delegator: delegator.clone(),
+            delegatee: delegatee.clone(),
+            governance: true,
+            proof: serde_json::from_value(signature.clone()).unwrap(),
+            timestamp: 1,
+        })
+    };
+    let error = drepo
+        .create_extra_agenda_transaction(&delegate(&keys[0].0, &non_member))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("is not a member"), "{}", error);
+
+    // Nobody has delegated in the reserved state of `finalized`.
+    let undelegate = ExtraAgendaTransaction::Undelegate(TxUndelegate {
+        delegator: keys[2].0.clone(),
+        proof: serde_json::from_value(signature.clone()).unwrap(),
+        timestamp: 2,
+    });
+    let error = drepo
+        .create_extra_agenda_transaction(&undelegate)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("no delegation"), "{}", error);
+
+    let delegate_commit = drepo
+        .create_extra_agenda_transaction(&delegate(&keys[0].0, &keys[1].0))
+        .await
+        .unwrap();
+    assert_eq!(
+        drepo.get_commit(&delegate_commit).await.unwrap(),
+        Commit::ExtraAgendaTransaction(delegate(&keys[0].0, &keys[1].0))
+    );
+    let reserved_state = drepo.get_reserved_state_at(&delegate_commit).await.unwrap();
+    assert_eq!(
+        reserved_state.members[0].consensus_delegations,
+        Some(keys[1].0.clone())
+    );
+    assert_eq!(
+        reserved_state.members[0].governance_delegations,
+        Some(keys[1].0.clone())
+    );
+
+    // The delegatee of the first delegation can't delegate in turn.
+    let error = drepo
+        .create_extra_agenda_transaction(&delegate(&keys[1].0, &keys[2].0))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("delegated to it"), "{}", error);
+
+    let undelegate = ExtraAgendaTransaction::Undelegate(TxUndelegate {
+        delegator: keys[0].0.clone(),
+        proof: serde_json::from_value(signature).unwrap(),
+        timestamp: 3,
+    });
+    let undelegate_commit = drepo
+        .create_extra_agenda_transaction(&undelegate)
+        .await
+        .unwrap();
+    let reserved_state = drepo
+        .get_reserved_state_at(&undelegate_commit)
+        .await
+        .unwrap();
+    assert_eq!(reserved_state, generate_reserved_state(&keys));
+
+    // The block over the transactions passes the verification.
+    let (block_commit, _) = drepo.create_block(keys[0].0.clone()).await.unwrap();
+    drepo.sync(&block_commit).await.unwrap();
+}
+
+/// Opens the same repository twice; the second one fails until the first one is dropped.
+#[tokio::test]
+async fn lock() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let raw = RawRepositoryImpl::init(path, "initial", &FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+
+    let raw = RawRepositoryImpl::open(path).await.unwrap();
+    assert!(DistributedRepository::new(raw).await.is_err());
+
+    drop(drepo);
+    let raw = RawRepositoryImpl::open(path).await.unwrap();
+    DistributedRepository::new(raw).await.unwrap();
+}
+
+/// Leaves the lock file behind, as a crashed process would; it doesn't block `new()`.
+#[tokio::test]
+async fn leftover_lock_file() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let raw = RawRepositoryImpl::init(path, "initial", &FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+    let lock_path = td.path().join(".git").join(LOCK_FILE_NAME);
+    std::fs::write(&lock_path, "").unwrap();
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let raw = RawRepositoryImpl::open(path).await.unwrap();
+    assert!(DistributedRepository::new(raw).await.is_err());
+    drop(drepo);
+    assert!(lock_path.exists());
+}
+
+/// Runs `fetch` twice against the same peer, which must be a no-op the second time.
+#[tokio::test]
+async fn fetch_twice() {
+    let td = TempDir::new().unwrap();
+    let peer_path = td.path().join("peer");
+    let peer_path = peer_path.to_str().unwrap();
+    let local_path = td.path().join("local");
+    let local_path = local_path.to_str().unwrap();
+    let keys = generate_keys(4);
+    let last_header = generate_genesis_header(&keys);
+
+    let mut peer_raw = setup_genesis(peer_path, &keys).await;
+    let genesis_commit = peer_raw.get_head().await.unwrap();
+    for i in 0..2 {
+        let branch = format!("agenda-{}", i);
+        peer_raw
+            .create_branch(branch.clone(), genesis_commit)
+            .await
+            .unwrap();
+        peer_raw.checkout(branch).await.unwrap();
+        let agenda = Commit::Agenda(Agenda {
+            author: keys[0].0.clone(),
+            timestamp: i + 1,
+            hash: Agenda::calculate_hash(last_header.height, &[]),
+        });
+        create_commit(&mut peer_raw, &agenda, &last_header).await;
+    }
+    peer_raw
+        .checkout(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+
+    let repo = git2::Repository::clone(peer_path, local_path).unwrap();
+    repo.remote_delete("origin").unwrap();
+    let mut raw = RawRepositoryImpl::open(local_path).await.unwrap();
+    let peer = generate_peer(1, 1001);
+    raw.add_remote(get_remote_name(&peer), peer_path.to_owned())
+        .await
+        .unwrap();
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    let (public_key, private_key) = keys[0].clone();
+    let network_config = NetworkConfig {
+        network_id: "test".to_owned(),
+        port: None,
+        members: keys
+            .iter()
+            .map(|(public_key, _)| public_key.clone())
+            .collect(),
+        public_key,
+        private_key,
+        max_branches_per_peer: 16,
+        fetch_interval_ms: 10_000,
+        fetch_jitter_ms: 2_000,
+    };
+    let outcome = drepo
+        .fetch(&network_config, std::slice::from_ref(&peer))
+        .await
+        .unwrap();
+    assert_eq!(outcome.new_branches.len(), 2);
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    let branches = drepo.raw.list_branches().await.unwrap();
+    let remotes = drepo.raw.list_remotes().await.unwrap();
+
+    // The same peer given twice is added only once.
+    let outcome = drepo
+        .fetch(&network_config, &[peer.clone(), peer])
+        .await
+        .unwrap();
+    assert_eq!(outcome, FetchOutcome::default());
+    assert_eq!(drepo.raw.list_branches().await.unwrap(), branches);
+    assert_eq!(drepo.raw.list_remotes().await.unwrap(), remotes);
+}
+
+/// Runs `serve` with a long fetch interval, and checks that it fetches only when woken up.
+#[tokio::test]
+async fn serve_wake_up() {
+    let td = TempDir::new().unwrap();
+    let peer_path = td.path().join("peer");
+    let peer_path = peer_path.to_str().unwrap();
+    let local_path = td.path().join("local");
+    let local_path = local_path.to_str().unwrap();
+    let keys = generate_keys(4);
+    let last_header = generate_genesis_header(&keys);
+
+    let mut peer_raw = setup_genesis(peer_path, &keys).await;
+    let genesis_commit = peer_raw.get_head().await.unwrap();
+    let repo = git2::Repository::clone(peer_path, local_path).unwrap();
+    repo.remote_delete("origin").unwrap();
+    peer_raw
+        .create_branch("agenda".to_owned(), genesis_commit)
+        .await
+        .unwrap();
+    peer_raw.checkout("agenda".to_owned()).await.unwrap();
+    let agenda = Commit::Agenda(Agenda {
+        author: keys[0].0.clone(),
+        timestamp: 1,
+        hash: Agenda::calculate_hash(last_header.height, &[]),
+    });
+    create_commit(&mut peer_raw, &agenda, &last_header).await;
+
+    let mut raw = RawRepositoryImpl::open(local_path).await.unwrap();
+    let peer = generate_peer(1, 1001);
+    raw.add_remote(get_remote_name(&peer), peer_path.to_owned())
+        .await
+        .unwrap();
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let (public_key, private_key) = keys[0].clone();
+    let network_config = NetworkConfig {
+        network_id: "test".to_owned(),
+        port: None,
+        members: keys
+            .iter()
+            .map(|(public_key, _)| public_key.clone())
+            .collect(),
+        public_key,
+        private_key,
+        max_branches_per_peer: 16,
+        fetch_interval_ms: 3_600_000,
+        fetch_jitter_ms: 0,
+    };
+    let timer = FetchTimer::new(&network_config);
+    let handle = drepo
+        .serve(
+            &network_config,
+            SharedKnownPeers::new(vec![peer]),
+            timer.clone(),
+        )
+        .await
+        .unwrap();
+
+    let count_branches = || {
+        repo.branches(Some(git2::BranchType::Local))
+            .unwrap()
+            .count()
+    };
+    let initial_count = count_branches();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(count_branches(), initial_count);
+
+    timer.wake_up();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while count_branches() == initial_count {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+    handle.abort();
+}
+
+/// Fetches from a peer advertising more agenda branches than the limit,
+/// and checks that only the limited number of them is imported at a time.
+#[tokio::test]
+async fn fetch_branch_limit() {
+    let td = TempDir::new().unwrap();
+    let peer_path = td.path().join("peer");
+    let peer_path = peer_path.to_str().unwrap();
+    let local_path = td.path().join("local");
+    let local_path = local_path.to_str().unwrap();
+    let keys = generate_keys(4);
+    let last_header = generate_genesis_header(&keys);
+
+    let mut peer_raw = setup_genesis(peer_path, &keys).await;
+    let genesis_commit = peer_raw.get_head().await.unwrap();
+    for i in 0..5 {
+        let branch = format!("agenda-{}", i);
+        peer_raw
+            .create_branch(branch.clone(), genesis_commit)
+            .await
+            .unwrap();
+        peer_raw.checkout(branch).await.unwrap();
+        let agenda = Commit::Agenda(Agenda {
+            author: keys[0].0.clone(),
+            timestamp: i + 1,
+            hash: Agenda::calculate_hash(last_header.height, &[]),
+        });
+        create_commit(&mut peer_raw, &agenda, &last_header).await;
+    }
+    peer_raw
+        .checkout(FINALIZED_BRANCH_NAME.into())
+        .await
+        .unwrap();
+
+    let repo = git2::Repository::clone(peer_path, local_path).unwrap();
+    repo.remote_delete("origin").unwrap();
+    let mut raw = RawRepositoryImpl::open(local_path).await.unwrap();
+    let peer = generate_peer(1, 1001);
+    raw.add_remote(get_remote_name(&peer), peer_path.to_owned())
+        .await
+        .unwrap();
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+
+    let (public_key, private_key) = keys[0].clone();
+    let mut network_config = NetworkConfig {
+        network_id: "test".to_owned(),
+        port: None,
+        members: keys
+            .iter()
+            .map(|(public_key, _)| public_key.clone())
+            .collect(),
+        public_key,
+        private_key,
+        max_branches_per_peer: 2,
+        fetch_interval_ms: 10_000,
+        fetch_jitter_ms: 2_000,
+    };
+    let count_agenda_branches = |branches: Vec<Branch>| {
+        branches
+            .iter()
+            .filter(|branch| branch.starts_with("a-"))
+            .count()
+    };
+    drepo
+        .fetch(&network_config, std::slice::from_ref(&peer))
+        .await
+        .unwrap();
+    assert_eq!(
+        count_agenda_branches(drepo.raw.list_branches().await.unwrap()),
+        2
+    );
+
+    // The already imported branches don't count toward the limit.
+    network_config.max_branches_per_peer = 3;
+    drepo.fetch(&network_config, &[peer]).await.unwrap();
+    assert_eq!(
+        count_agenda_branches(drepo.raw.list_branches().await.unwrap()),
+        5
+    );
+    assert_eq!(drepo.get_agendas().await.unwrap().len(), 5);
+}
+
+/// Fetches a branch of two blocks with a depth of one commit, and checks that it is rejected
+/// without downloading the rest of the branch; a deep enough fetch then imports it.
+#[tokio::test]
+async fn fetch_shallow() {
+    let td = TempDir::new().unwrap();
+    let peer_path = td.path().join("peer");
+    let peer_path = peer_path.to_str().unwrap();
+    let local_path = td.path().join("local");
+    let local_path = local_path.to_str().unwrap();
+    let keys = generate_keys(4);
+
+    let mut peer_raw = setup_genesis(peer_path, &keys).await;
+    let repo = git2::Repository::clone(peer_path, local_path).unwrap();
+    repo.remote_delete("origin").unwrap();
+
+    let genesis_commit = peer_raw.get_head().await.unwrap();
+    peer_raw
+        .create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    peer_raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let mut last_header = generate_genesis_header(&keys);
+    let mut commit_hashes = Vec::new();
+    for _ in 0..2 {
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hashes.push(create_commit(&mut peer_raw, &commit, &last_header).await);
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+    }
+
+    let mut raw = RawRepositoryImpl::open(local_path).await.unwrap();
+    let peer = generate_peer(1, 1001);
+    raw.add_remote(get_remote_name(&peer), peer_path.to_owned())
+        .await
+        .unwrap();
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let (public_key, private_key) = keys[0].clone();
+    let network_config = NetworkConfig {
+        network_id: "test".to_owned(),
+        port: None,
+        members: keys
+            .iter()
+            .map(|(public_key, _)| public_key.clone())
+            .collect(),
+        public_key,
+        private_key,
+        max_branches_per_peer: 16,
+        fetch_interval_ms: 10_000,
+        fetch_jitter_ms: 2_000,
+    };
+
+    drepo.set_fetch_depth(Some(1));
+    let outcome = drepo
+        .fetch(&network_config, std::slice::from_ref(&peer))
+        .await
+        .unwrap();
+    assert!(outcome.new_branches.is_empty());
+    assert_eq!(outcome.errors.len(), 1, "{:?}", outcome.errors);
+    assert!(
+        outcome.errors[0].1.contains("within the fetch depth"),
+        "{:?}",
+        outcome.errors
+    );
+    // Only the tip has been downloaded.
+    drepo
+        .raw
+        .read_semantic_commit(*commit_hashes.last().unwrap())
+        .await
+        .unwrap();
+    drepo
+        .raw
+        .read_semantic_commit(commit_hashes[0])
+        .await
+        .unwrap_err();
+    // The rejected branch is not left for the walks over the history.
+    assert!(!drepo
+        .raw
+        .list_remote_tracking_branches(None)
+        .await
+        .unwrap()
+        .into_iter()
+        .any(|(_, branch, _)| branch == WORK_BRANCH_NAME));
+    assert!(drepo
+        .raw
+        .list_descendants(genesis_commit, None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    drepo.set_fetch_depth(Some(commit_hashes.len()));
+    let outcome = drepo.fetch(&network_config, &[peer]).await.unwrap();
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    assert_eq!(outcome.new_branches, vec!["b-0".to_owned()]);
+}
+
+/// Fetches from a peer that has finalized two more blocks, and checks that `finalized` follows it.
+#[tokio::test]
+async fn fetch_finalized() {
+    let td = TempDir::new().unwrap();
+    let peer_path = td.path().join("peer");
+    let peer_path = peer_path.to_str().unwrap();
+    let local_path = td.path().join("local");
+    let local_path = local_path.to_str().unwrap();
+    let keys = generate_keys(4);
+
+    let mut peer_raw = setup_genesis(peer_path, &keys).await;
+    let repo = git2::Repository::clone(peer_path, local_path).unwrap();
+    repo.remote_delete("origin").unwrap();
+    // Writing the `fp` commit requires the committer.
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "name").unwrap();
+    config.set_str("user.email", "email").unwrap();
+
+    let genesis_commit = peer_raw.get_head().await.unwrap();
+    peer_raw
+        .create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    peer_raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commits = Vec::new();
+    for _ in 0..2 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut peer_raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_commits.push((commit_hash, last_header.clone()));
+    }
+    let mut peer_drepo = DistributedRepository::new(peer_raw).await.unwrap();
+    for (block_commit, block_header) in &block_commits {
+        let proof = keys
+            .iter()
+            .map(|(_, private_key)| TypedSignature::sign(block_header, private_key).unwrap())
+            .collect::<FinalizationProof>();
+        peer_drepo.finalize(block_commit, &proof).await.unwrap();
+    }
+
+    let mut raw = RawRepositoryImpl::open(local_path).await.unwrap();
+    let peer = generate_peer(1, 1001);
+    raw.add_remote(get_remote_name(&peer), peer_path.to_owned())
+        .await
+        .unwrap();
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let (public_key, private_key) = keys[0].clone();
+    let network_config = NetworkConfig {
+        network_id: "test".to_owned(),
+        port: None,
+        members: keys
+            .iter()
+            .map(|(public_key, _)| public_key.clone())
+            .collect(),
+        public_key,
+        private_key,
+        max_branches_per_peer: 16,
+        fetch_interval_ms: 10_000,
+        fetch_jitter_ms: 2_000,
+    };
+    let outcome = drepo.fetch(&network_config, &[peer]).await.unwrap();
+    assert_eq!(outcome.finalized_height, Some(2));
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        block_commits[1].0
+    );
+}
+
+/// Finalizes the first block with a proof signed by three of the four validators, and fetches
+/// a peer whose second block embeds a proof signed by all of them; the second block must not be
+/// finalized but only imported as a `b-#` branch.
+#[tokio::test]
+async fn fetch_mismatched_embedded_proof() {
+    let td = TempDir::new().unwrap();
+    let peer_path = td.path().join("peer");
+    let peer_path = peer_path.to_str().unwrap();
+    let local_path = td.path().join("local");
+    let local_path = local_path.to_str().unwrap();
+    let keys = generate_keys(4);
+
+    let mut peer_raw = setup_genesis(peer_path, &keys).await;
+    let repo = git2::Repository::clone(peer_path, local_path).unwrap();
+    repo.remote_delete("origin").unwrap();
+    // Writing the `fp` commit requires the committer.
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "name").unwrap();
+    config.set_str("user.email", "email").unwrap();
+
+    let genesis_commit = peer_raw.get_head().await.unwrap();
+    peer_raw
+        .create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    peer_raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let mut last_header = generate_genesis_header(&keys);
+    let mut block_commits = Vec::new();
+    for _ in 0..2 {
+        let mut commit_hash = genesis_commit;
+        for commit in generate_next_block_commits(&keys, &last_header) {
+            commit_hash = create_commit(&mut peer_raw, &commit, &last_header).await;
+            if let Commit::Block(block_header) = commit {
+                last_header = block_header;
+            }
+        }
+        block_commits.push((commit_hash, last_header.clone()));
+    }
+
+    let mut raw = RawRepositoryImpl::open(local_path).await.unwrap();
+    let peer = generate_peer(1, 1001);
+    raw.add_remote(get_remote_name(&peer), peer_path.to_owned())
+        .await
+        .unwrap();
+    raw.fetch_all().await.unwrap();
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let partial_proof = keys[..3]
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&block_commits[0].1, private_key).unwrap())
+        .collect::<FinalizationProof>();
+    drepo
+        .finalize(&block_commits[0].0, &partial_proof)
+        .await
+        .unwrap();
+
+    let mut peer_drepo = DistributedRepository::new(peer_raw).await.unwrap();
+    for (block_commit, block_header) in &block_commits {
+        let proof = keys
+            .iter()
+            .map(|(_, private_key)| TypedSignature::sign(block_header, private_key).unwrap())
+            .collect::<FinalizationProof>();
+        peer_drepo.finalize(block_commit, &proof).await.unwrap();
+    }
+
+    let (public_key, private_key) = keys[0].clone();
+    let network_config = NetworkConfig {
+        network_id: "test".to_owned(),
+        port: None,
+        members: keys
+            .iter()
+            .map(|(public_key, _)| public_key.clone())
+            .collect(),
+        public_key,
+        private_key,
+        max_branches_per_peer: 16,
+        fetch_interval_ms: 10_000,
+        fetch_jitter_ms: 2_000,
+    };
+    let outcome = drepo.fetch(&network_config, &[peer]).await.unwrap();
+    assert_eq!(outcome.finalized_height, None);
+    assert_eq!(outcome.errors.len(), 1, "{:?}", outcome.errors);
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        block_commits[0].0
+    );
+    assert_eq!(outcome.new_branches, vec!["b-0".to_owned()]);
+    assert_eq!(
+        drepo.raw.locate_branch("b-0".into()).await.unwrap(),
+        block_commits[1].0
+    );
+}
+
+/// Fetches a peer that distributes the finalization proof as an `fp-<height>` tag,
+/// instead of the `fp` branch, and checks that the block is finalized.
+#[tokio::test]
+async fn fetch_fp_tag() {
+    let td = TempDir::new().unwrap();
+    let peer_path = td.path().join("peer");
+    let peer_path = peer_path.to_str().unwrap();
+    let local_path = td.path().join("local");
+    let local_path = local_path.to_str().unwrap();
+    let keys = generate_keys(4);
+
+    let mut peer_raw = setup_genesis(peer_path, &keys).await;
+    let repo = git2::Repository::clone(peer_path, local_path).unwrap();
+    repo.remote_delete("origin").unwrap();
+
+    let genesis_commit = peer_raw.get_head().await.unwrap();
+    peer_raw
+        .create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    peer_raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let mut block_commit = genesis_commit;
+    let mut block_header = last_header.clone();
+    for commit in generate_next_block_commits(&keys, &last_header) {
+        block_commit = create_commit(&mut peer_raw, &commit, &last_header).await;
+        if let Commit::Block(header) = commit {
+            block_header = header;
+        }
+    }
+    let proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&block_header, private_key).unwrap())
+        .collect::<FinalizationProof>();
+    let peer_repo = git2::Repository::open(peer_path).unwrap();
+    peer_repo
+        .tag(
+            "fp-1",
+            &peer_repo
+                .find_object(git2::Oid::from_bytes(&block_commit.hash).unwrap(), None)
+                .unwrap(),
+            &peer_repo.signature().unwrap(),
+            &serde_json::to_string(&proof).unwrap(),
+            false,
+        )
+        .unwrap();
+
+    let mut raw = RawRepositoryImpl::open(local_path).await.unwrap();
+    let peer = generate_peer(1, 1001);
+    raw.add_remote(get_remote_name(&peer), peer_path.to_owned())
+        .await
+        .unwrap();
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let (public_key, private_key) = keys[0].clone();
+    let network_config = NetworkConfig {
+        network_id: "test".to_owned(),
+        port: None,
+        members: keys
+            .iter()
+            .map(|(public_key, _)| public_key.clone())
+            .collect(),
+        public_key,
+        private_key,
+        max_branches_per_peer: 16,
+        fetch_interval_ms: 10_000,
+        fetch_jitter_ms: 2_000,
+    };
+    let outcome = drepo.fetch(&network_config, &[peer]).await.unwrap();
+    assert_eq!(outcome.finalized_height, Some(1));
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        block_commit
+    );
+    assert!(drepo.check(0).await.unwrap());
+}
+
+#[tokio::test]
+async fn fetch_stale_branch() {
+    let td = TempDir::new().unwrap();
+    let peer_path = td.path().join("peer");
+    let peer_path = peer_path.to_str().unwrap();
+    let local_path = td.path().join("local");
+    let local_path = local_path.to_str().unwrap();
+    let keys = generate_keys(4);
+
+    let mut peer_raw = setup_genesis(peer_path, &keys).await;
+    let repo = git2::Repository::clone(peer_path, local_path).unwrap();
+    repo.remote_delete("origin").unwrap();
+
+    let genesis_commit = peer_raw.get_head().await.unwrap();
+    peer_raw
+        .create_branch(WORK_BRANCH_NAME.into(), genesis_commit)
+        .await
+        .unwrap();
+    peer_raw.checkout(WORK_BRANCH_NAME.into()).await.unwrap();
+    let last_header = generate_genesis_header(&keys);
+    let mut block_commit = genesis_commit;
+    let mut block_header = last_header.clone();
+    for commit in generate_next_block_commits(&keys, &last_header) {
+        block_commit = create_commit(&mut peer_raw, &commit, &last_header).await;
+        if let Commit::Block(header) = commit {
+            block_header = header;
+        }
+    }
+    let proof = keys
+        .iter()
+        .map(|(_, private_key)| TypedSignature::sign(&block_header, private_key).unwrap())
+        .collect::<FinalizationProof>();
+    let peer_repo = git2::Repository::open(peer_path).unwrap();
+    peer_repo
+        .tag(
+            "fp-1",
+            &peer_repo
+                .find_object(git2::Oid::from_bytes(&block_commit.hash).unwrap(), None)
+                .unwrap(),
+            &peer_repo.signature().unwrap(),
+            &serde_json::to_string(&proof).unwrap(),
+            false,
+        )
+        .unwrap();
+
+    let mut raw = RawRepositoryImpl::open(local_path).await.unwrap();
+    let peer = generate_peer(1, 1001);
+    raw.add_remote(get_remote_name(&peer), peer_path.to_owned())
+        .await
+        .unwrap();
+    let mut drepo = DistributedRepository::new(raw).await.unwrap();
+    let (public_key, private_key) = keys[0].clone();
+    let network_config = NetworkConfig {
+        network_id: "test".to_owned(),
+        port: None,
+        members: keys
+            .iter()
+            .map(|(public_key, _)| public_key.clone())
+            .collect(),
+        public_key,
+        private_key,
+        max_branches_per_peer: 16,
+        fetch_interval_ms: 10_000,
+        fetch_jitter_ms: 2_000,
+    };
+    let outcome = drepo
+        .fetch(&network_config, std::slice::from_ref(&peer))
+        .await
+        .unwrap();
+    assert_eq!(outcome.finalized_height, Some(1));
+    assert_eq!(
+        drepo
+            .raw
+            .locate_branch(FINALIZED_BRANCH_NAME.into())
+            .await
+            .unwrap(),
+        block_commit
+    );
+
+    // The peer now advertises a branch on the genesis block, which is below the finalized tip.
+    peer_raw
+        .create_branch("stale".into(), genesis_commit)
+        .await
+        .unwrap();
+    let outcome = drepo.fetch(&network_config, &[peer]).await.unwrap();
+    assert_eq!(outcome.finalized_height, None);
+    assert!(outcome.new_branches.is_empty());
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+}
+
+/// A semantic commit of a known type but with a malformed body.
+#[tokio::test]
+async fn get_commit_parse_error() {
+    let td = TempDir::new().unwrap();
+    let path = td.path().to_str().unwrap();
+    let keys = generate_keys(4);
+    let mut raw = setup_genesis(path, &keys).await;
+    let body = "x".repeat(1000);
+    let commit_hash = raw
+        .create_semantic_commit(
+            raw::SemanticCommit {
+                title: "block: 1/0000".to_owned(),
+                body: body.clone(),
+                diff: Diff::None,
+            },
+            FINALIZED_BRANCH_NAME.into(),
+        )
+        .await
+        .unwrap();
+
+    let drepo = DistributedRepository::new(raw).await.unwrap();
+    let error = drepo
+        .get_commit(&commit_hash)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("block: 1/0000"), "{}", error);
+    assert!(error.contains(&body[..100]), "{}", error);
+    assert!(!error.contains(&body[..101]), "{}", error);
+}
+
+/// Samples the fetch delays, which should be spread over the jitter range,
+/// and wakes up the timer in the middle of a long wait.
+#[tokio::test]
+async fn fetch_timer() {
+    let (public_key, private_key) = generate_keypair([0]);
+    let mut network_config = NetworkConfig {
+        network_id: "test".to_owned(),
+        port: None,
+        members: vec![public_key.clone()],
+        public_key,
+        private_key,
+        max_branches_per_peer: 16,
+        fetch_interval_ms: 10_000,
+        fetch_jitter_ms: 2_000,
+    };
+    let timer = FetchTimer::new(&network_config);
+    let delays = (0..100)
+        .map(|_| timer.next_delay())
+        .collect::<BTreeSet<_>>();
+    assert!(delays.len() > 1);
+    assert!(*delays.iter().next().unwrap() >= Duration::from_millis(10_000));
+    assert!(*delays.iter().next_back().unwrap() <= Duration::from_millis(12_000));
+
+    network_config.fetch_jitter_ms = 0;
+    let timer = FetchTimer::new(&network_config);
+    assert_eq!(timer.next_delay(), Duration::from_millis(10_000));
+
+    let handle = {
+        let timer = timer.clone();
+        tokio::spawn(async move { timer.wait().await })
+    };
+    timer.wake_up();
+    tokio::time::timeout(Duration::from_secs(1), handle)
+        .await
+        .unwrap()
+        .unwrap();
+}